@@ -43,6 +43,8 @@ const plugins = [
 const nextConfig = {
   distDir: "build",
   swcMinify: true,
+  // shared helpers are imported from the sdk outside the app
+  experimental: { externalDir: true },
   webpack: (config, { isServer }) => {
     if (!isServer) {
       config.resolve.fallback.fs = false;
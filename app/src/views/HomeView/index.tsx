@@ -7,6 +7,8 @@ import { useConnection } from '@solana/wallet-adapter-react';
 import { WalletMultiButton } from "@solana/wallet-adapter-react-ui";
 import * as anchor from '@project-serum/anchor';
 import { NoLossLottery } from "../../../../target/types/no_loss_lottery";
+import { canonicalNumbers } from "../../../../sdk/numbers";
+import { swapQuote } from "../../../../sdk/quote";
 import { TicketCard } from "./ticketcard";
 import styles from "./index.module.css";
 import CountDownTimer from "components/CountDownTimer";
@@ -152,14 +154,34 @@ async function buy(
   numbers: Array<number>,
   config: Config,
 ): Promise<[anchor.web3.PublicKey, number]> {
+  // picks sorted ascending with the unused 0 slots last, equivalent picks
+  // share a combination
+  numbers = canonicalNumbers(numbers);
+
+  // combination PDA shared by every ticket with the same numbers
+  const [combination, _combinationBump] = await anchor.web3.PublicKey.findProgramAddress(
+    [Uint8Array.from(numbers), config.keys.get(VAULT_MANAGER)!.toBuffer()],
+    program.programId
+  );
+  const combinationAccount = await program.account.combination.fetchNullable(combination);
+  const index = combinationAccount ? combinationAccount.nextIndex : new anchor.BN(0);
+
   // create ticket PDA
   const [ticket, ticketBump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Uint8Array.from(numbers), config.keys.get(VAULT_MANAGER)!.toBuffer()],
+    [
+      Uint8Array.from(numbers),
+      config.keys.get(VAULT_MANAGER)!.toBuffer(),
+      index.toArrayLike(Buffer, "le", 8),
+    ],
     program.programId
   );
 
   console.log("ticket: %s", ticket.toString());
 
+  // ticket takes the next registry slot
+  const vaultMgrAccount = await program.account.vaultManager.fetch(config.keys.get(VAULT_MANAGER)!);
+  const ticketEntry = await ticketEntryAddress(program, config, vaultMgrAccount.ticketCount);
+
   try {
     // buy a ticket
     const buyTxSig = await program.rpc.buy(numbers, {
@@ -170,7 +192,9 @@ async function buy(
         yieldVault: config.keys.get(YIELD_VAULT)!,
         vaultManager: config.keys.get(VAULT_MANAGER)!,
         tickets: config.keys.get(TICKETS)!,
+        combination: combination,
         ticket: ticket,
+        ticketEntry: ticketEntry,
         userTicketsAta: config.keys.get(USER_TICKET_ATA)!,
         user: program.provider.wallet.publicKey,
         userDepositAta: config.keys.get(USER_DEPOSIT_ATA)!,
@@ -187,14 +211,81 @@ async function buy(
   return [ticket, ticketBump];
 }
 
+async function ticketEntryAddress(
+  program: Program<NoLossLottery>,
+  config: Config,
+  index: anchor.BN,
+): Promise<anchor.web3.PublicKey> {
+  const [ticketEntry, _ticketEntryBump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("ticket_entry"),
+      config.keys.get(VAULT_MANAGER)!.toBuffer(),
+      index.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+  return ticketEntry;
+}
+
+// balance of a token account
+async function tokenAmount(
+  program: Program<NoLossLottery>,
+  tokenAccount: anchor.web3.PublicKey,
+): Promise<anchor.BN> {
+  const balance = await program.provider.connection.getTokenAccountBalance(tokenAccount);
+  return new anchor.BN(balance.value.amount);
+}
+
+// deposit tokens the whole yield position swaps for at the current pool reserves
+async function positionQuote(
+  program: Program<NoLossLottery>,
+  config: Config,
+): Promise<anchor.BN> {
+  const yieldAmount = await tokenAmount(program, config.keys.get(YIELD_VAULT)!);
+  if (yieldAmount.isZero()) {
+    return yieldAmount;
+  }
+
+  const ammAccount = await program.provider.connection.getAccountInfo(config.keys.get(TOKEN_SWAP_ACCOUNT)!);
+  const pool = tokenSwap.TokenSwapLayout.decode(ammAccount!.data);
+  const fees = {
+    tradeFeeNumerator: tokenSwap.Numberu64.fromBuffer(pool.tradeFeeNumerator),
+    tradeFeeDenominator: tokenSwap.Numberu64.fromBuffer(pool.tradeFeeDenominator),
+    ownerTradeFeeNumerator: tokenSwap.Numberu64.fromBuffer(pool.ownerTradeFeeNumerator),
+    ownerTradeFeeDenominator: tokenSwap.Numberu64.fromBuffer(pool.ownerTradeFeeDenominator),
+  };
+  return swapQuote(
+    yieldAmount,
+    await tokenAmount(program, config.keys.get(SWAP_YIELD_VAULT)!),
+    await tokenAmount(program, config.keys.get(SWAP_DEPOSIT_VAULT)!),
+    fees,
+  );
+}
+
 async function redeem(
   program: Program<NoLossLottery>,
   config: Config,
   ticket: anchor.web3.PublicKey,
 ) {
+  // combination PDA of the ticket numbers
+  const ticketAccount = await program.account.ticket.fetch(ticket);
+  const [combination, _combinationBump] = await anchor.web3.PublicKey.findProgramAddress(
+    [Uint8Array.from(ticketAccount.numbers), config.keys.get(VAULT_MANAGER)!.toBuffer()],
+    program.programId
+  );
+
+  // last registry entry is moved into the slot of the redeemed ticket
+  const ticketEntry = await ticketEntryAddress(program, config, ticketAccount.registryIndex);
+  const vaultMgrAccount = await program.account.vaultManager.fetch(config.keys.get(VAULT_MANAGER)!);
+  const lastTicketEntry = await ticketEntryAddress(program, config, vaultMgrAccount.ticketCount.subn(1));
+  const lastTicketEntryAccount = await program.account.ticketEntry.fetch(lastTicketEntry);
+
   try {
+    // redeem fails if the pool moved too far from this quote
+    const expectedPositionValue = await positionQuote(program, config);
+
     // user redeem token
-    const redeemTxSig = await program.rpc.redeem({
+    const redeemTxSig = await program.rpc.redeem(expectedPositionValue, {
       accounts: {
         depositMint: config.keys.get(DEPOSIT_MINT)!,
         yieldMint: config.keys.get(YIELD_MINT)!,
@@ -202,17 +293,23 @@ async function redeem(
         yieldVault: config.keys.get(YIELD_VAULT)!,
         tickets: config.keys.get(TICKETS)!,
         vaultManager: config.keys.get(VAULT_MANAGER)!,
+        combination: combination,
         ticket: ticket,
-        swapYieldVault: config.keys.get(SWAP_YIELD_VAULT)!,
-        swapDepositVault: config.keys.get(SWAP_DEPOSIT_VAULT)!,
-        poolMint: config.keys.get(POOL_MINT)!,
-        amm: config.keys.get(TOKEN_SWAP_ACCOUNT)!,
-        ammAuthority: config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY)!,
-        poolFee: config.keys.get(POOL_FEE)!,
+        ticketEntry: ticketEntry,
+        lastTicketEntry: lastTicketEntry,
+        lastTicket: lastTicketEntryAccount.ticket,
+        swapPool: {
+          swapYieldVault: config.keys.get(SWAP_YIELD_VAULT)!,
+          swapDepositVault: config.keys.get(SWAP_DEPOSIT_VAULT)!,
+          poolMint: config.keys.get(POOL_MINT)!,
+          amm: config.keys.get(TOKEN_SWAP_ACCOUNT)!,
+          ammAuthority: config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY)!,
+          poolFee: config.keys.get(POOL_FEE)!,
+          tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
+        },
         userTicketsAta: config.keys.get(USER_TICKET_ATA)!,
         user: program.provider.wallet.publicKey,
         userDepositAta: config.keys.get(USER_DEPOSIT_ATA)!,
-        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
@@ -289,7 +386,7 @@ export const HomeView: FC = ({}) => {
       };
       // Get ticket PDAs by matching with the account size
       const sizeFilter: DataSizeFilter = {
-        dataSize: 174,
+        dataSize: program.account.ticket.size,
       }
       const filters = [walletMemcmp, sizeFilter];
       const config: GetProgramAccountsConfig = { filters: filters };
@@ -334,8 +431,20 @@ export const HomeView: FC = ({}) => {
 
       let newDashboard = [];
       let drawing = "N/A";
-      if (vaultManagerAccount.previousWinningNumbers.toString() !== "0,0,0,0,0,0") {
-        drawing = vaultManagerAccount.previousWinningNumbers.join(' ');
+      if (vaultManagerAccount.winningNumbers.toString() !== "0,0,0,0,0,0") {
+        drawing = vaultManagerAccount.winningNumbers.join(' ');
+      } else if (vaultManagerAccount.drawId.toNumber() > 1) {
+        // latest dispensed draw
+        const [drawRecord, _drawRecordBump] = await anchor.web3.PublicKey.findProgramAddress(
+          [
+            Buffer.from("draw_record"),
+            vaultManager.toBuffer(),
+            vaultManagerAccount.drawId.subn(1).toArrayLike(Buffer, "le", 8),
+          ],
+          program.programId
+        );
+        const drawRecordAccount = await program.account.drawRecord.fetch(drawRecord);
+        drawing = drawRecordAccount.winningNumbers.join(' ');
       }
       newDashboard = [
         depositTokenBalance.value.amount,
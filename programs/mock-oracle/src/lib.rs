@@ -0,0 +1,54 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar;
+use anchor_spl::token;
+use no_loss_lottery::cpi::accounts::FulfillRandomness;
+use no_loss_lottery::program::NoLossLottery;
+use no_loss_lottery::VaultManager;
+
+declare_id!("G9WudtmoqDyhWPRorFspjFC4isrHLsTZGNnkezHQ6BBc");
+
+// stand-in for a VRF oracle callback when running against a local validator
+// the oracle key signs the pending request off-chain with the nonce it committed before
+// the draw, the transaction carries that signature in an Ed25519 program instruction
+// right before this one
+// never use outside of testing
+#[program]
+pub mod mock_oracle {
+    use super::*;
+    pub fn fulfill(ctx: Context<Fulfill>) -> Result<()> {
+        let fulfill_accounts = FulfillRandomness {
+            deposit_mint: ctx.accounts.deposit_mint.clone().to_account_info(),
+            yield_mint: ctx.accounts.yield_mint.clone().to_account_info(),
+            deposit_vault: ctx.accounts.deposit_vault.clone().to_account_info(),
+            yield_vault: ctx.accounts.yield_vault.clone().to_account_info(),
+            vault_manager: ctx.accounts.vault_manager.clone().to_account_info(),
+            instructions: ctx.accounts.instructions.clone(),
+        };
+
+        // the lottery verifies the oracle signature itself
+        no_loss_lottery::cpi::fulfill_randomness(CpiContext::new(
+            ctx.accounts.lottery_program.clone().to_account_info(),
+            fulfill_accounts,
+        ))
+    }
+}
+
+#[derive(Accounts)]
+pub struct Fulfill<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    /// CHECK: instructions sysvar, read by the lottery for the oracle signature
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    pub lottery_program: Program<'info, NoLossLottery>,
+}
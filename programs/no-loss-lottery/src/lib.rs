@@ -1,10 +1,17 @@
 use anchor_lang::prelude::*;
-use anchor_lang::AccountsClose;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::{
+    ed25519_program,
+    program::{invoke, invoke_signed, set_return_data},
+    system_instruction, sysvar,
+};
 use anchor_spl::{
     associated_token,
     token::{self},
 };
-use spl_token_swap::instruction::{swap, Swap};
+use yield_source::{SplTokenSwap, YieldSource};
+
+pub mod yield_source;
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
@@ -15,17 +22,17 @@ pub mod no_loss_lottery {
         ctx: Context<Initialize>,
         draw_duration: u64,
         ticket_price: u64,
+        randomness_mode: RandomnessMode,
+        reveal_duration: u64,
+        commit_bond: u64,
+        fulfill_duration: u64,
+        registration_duration: u64,
+        claim_duration: u64,
+        prize_tiers: [u16; PRIZE_TIER_COUNT],
+        fee_bps: u16,
+        lottery_mode: LotteryMode,
+        number_domain: NumberDomain,
     ) -> Result<()> {
-        // ticket_price must be > 0
-        if ticket_price <= 0 {
-            return Err(error!(ErrorCode::InvalidTicketPrice));
-        }
-
-        // draw_duration must be > 0
-        if draw_duration <= 0 {
-            return Err(error!(ErrorCode::InvalidDrawDuration));
-        }
-
         // set vault manager config
         let vault_mgr = &mut ctx.accounts.vault_manager;
         vault_mgr.draw_duration = draw_duration;
@@ -36,40 +43,63 @@ pub mod no_loss_lottery {
         vault_mgr.yield_mint = ctx.accounts.yield_mint.clone().key();
         vault_mgr.yield_vault = ctx.accounts.yield_vault.clone().key();
         vault_mgr.tickets = ctx.accounts.tickets.clone().key();
-        vault_mgr.deposit_token_reserve = 10 * ticket_price;
+        vault_mgr.deposit_token_reserve = ticket_price
+            .checked_mul(10)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        vault_mgr.randomness_oracle = ctx.accounts.randomness_oracle.key();
+        vault_mgr.randomness_mode = randomness_mode;
+        vault_mgr.reveal_duration = reveal_duration;
+        vault_mgr.commit_bond = commit_bond;
+        vault_mgr.fulfill_duration = fulfill_duration;
+        vault_mgr.draw_id = 1;
+        vault_mgr.registration_duration = registration_duration;
+        vault_mgr.claim_duration = claim_duration;
+        vault_mgr.prize_tiers = prize_tiers;
+        vault_mgr.authority = ctx.accounts.user.key();
+        vault_mgr.commit_authority = ctx.accounts.user.key();
+        vault_mgr.fee_bps = fee_bps;
+        vault_mgr.treasury = ctx.accounts.treasury.key();
+        vault_mgr.slippage_bps = DEFAULT_SLIPPAGE_BPS;
+        vault_mgr.lottery_mode = lottery_mode;
+        vault_mgr.number_domain = number_domain;
+        validate_config(vault_mgr)?;
 
         Ok(())
     }
 
-    pub fn buy(ctx: Context<Buy>, numbers: [u8; 6]) -> Result<()> {
-        // if cutoff_time is 0, drawing has never started
-        if ctx.accounts.vault_manager.cutoff_time == 0 {
-            // get current timestamp from Clock program
-            let now = get_current_time();
-
-            // set last draw time to now
-            ctx.accounts.vault_manager.cutoff_time =
-                now as u64 + ctx.accounts.vault_manager.draw_duration;
-        };
-
-        // do not allow user to pass in zeroed array of numbers
-        if numbers == [0u8; 6] {
-            return Err(error!(ErrorCode::InvalidNumbers));
-        }
+    // registry tree nodes of the new slot are passed in remaining_accounts, created on first use
+    pub fn buy<'info>(ctx: Context<'_, '_, '_, 'info, Buy<'info>>, numbers: [u8; 6]) -> Result<()> {
+        check_can_buy(&mut ctx.accounts.vault_manager)?;
 
-        // if buy is locked, call find
-        if ctx.accounts.vault_manager.locked {
-            return Err(error!(ErrorCode::CallDispense));
-        }
+        // numbers are sorted so equivalent picks share a combination, and must be a possible pick
+        let numbers = canonical_numbers(&numbers);
+        validate_numbers(&numbers, &ctx.accounts.vault_manager.number_domain)?;
 
         // create ticket PDA data
-        let ticket_account = &mut ctx.accounts.ticket;
-        ticket_account.deposit_mint = ctx.accounts.deposit_mint.clone().key();
-        ticket_account.yield_mint = ctx.accounts.yield_mint.clone().key();
-        ticket_account.vault = ctx.accounts.deposit_vault.clone().key();
-        ticket_account.tickets = ctx.accounts.tickets.clone().key();
-        ticket_account.owner = ctx.accounts.user.key();
-        ticket_account.numbers = numbers;
+        let ticket_key = ctx.accounts.ticket.key();
+        let owner = ctx.accounts.user.key();
+        record_ticket(
+            &mut ctx.accounts.vault_manager,
+            &mut ctx.accounts.combination,
+            &mut ctx.accounts.ticket,
+            ticket_key,
+            &mut ctx.accounts.ticket_entry,
+            owner,
+            numbers,
+        )?;
+
+        // ticket weighs in the draw from the slot it takes
+        let mut registry = RegistryTree::new(
+            ctx.remaining_accounts,
+            ctx.accounts.vault_manager.key(),
+            ctx.accounts.vault_manager.draw_id,
+            Some((
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            )),
+        );
+        registry.add(&ctx.accounts.ticket, ctx.program_id)?;
+        registry.exit(ctx.program_id)?;
 
         // transfer tokens from user wallet to vault
         let transfer_accounts = token::Transfer {
@@ -86,6 +116,14 @@ pub mod no_loss_lottery {
             ctx.accounts.vault_manager.clone().ticket_price,
         )?;
 
+        // ticket price is owed back to the holder on redeem
+        ctx.accounts.vault_manager.total_principal = ctx
+            .accounts
+            .vault_manager
+            .total_principal
+            .checked_add(ctx.accounts.vault_manager.ticket_price)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
         // mint tickets to vault
         let mint_to_accounts = token::MintTo {
             mint: ctx.accounts.tickets.clone().to_account_info(),
@@ -107,87 +145,364 @@ pub mod no_loss_lottery {
                 ]],
             ),
             1,
+        )?;
+
+        emit!(TicketPurchased {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            ticket: ctx.accounts.ticket.key(),
+            owner: ctx.accounts.user.key(),
+            numbers,
+            draw_id: ctx.accounts.ticket.draw_id,
+            price: ctx.accounts.vault_manager.ticket_price,
+        });
+        Ok(())
+    }
+
+    // buy a ticket with numbers derived from a recent slot hash, the user and a nonce
+    // the client derives the same numbers to pass in the ticket accounts
+    // a roll is only skipped if its combination exists, its account is passed in remaining_accounts
+    // followed by the registry tree nodes of the new slot
+    pub fn buy_quick_pick<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyQuickPick<'info>>,
+        numbers: [u8; 6],
+        slot: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        let slot_hash = find_slot_hash(&ctx.accounts.slot_hashes, slot)?;
+        let user = ctx.accounts.buy.user.key();
+        let vault_manager = ctx.accounts.buy.vault_manager.key();
+        let number_domain = ctx.accounts.buy.vault_manager.number_domain;
+
+        let mut roll = 0;
+        loop {
+            let candidate = quick_pick_numbers(&slot_hash, &user, nonce, roll, &number_domain);
+            if candidate == canonical_numbers(&numbers) {
+                break;
+            }
+
+            // combination of this roll already exists, its account is passed at the roll's position
+            let (combination, _bump) =
+                Pubkey::find_program_address(&[&candidate, vault_manager.as_ref()], ctx.program_id);
+            let taken = ctx
+                .remaining_accounts
+                .get(roll as usize)
+                .ok_or(error!(ErrorCode::InvalidQuickPick))?;
+            if taken.key() != combination || taken.owner != ctx.program_id || taken.data_is_empty()
+            {
+                return Err(error!(ErrorCode::InvalidQuickPick));
+            }
+
+            roll += 1;
+            if roll >= MAX_QUICK_PICK_ROLLS {
+                return Err(error!(ErrorCode::InvalidQuickPick));
+            }
+        }
+
+        // picked combination must be new, existing ones are created with a ticket
+        if ctx.accounts.buy.combination.next_index > 0 {
+            return Err(error!(ErrorCode::InvalidQuickPick));
+        }
+
+        buy(
+            Context::new(
+                ctx.program_id,
+                &mut ctx.accounts.buy,
+                ctx.remaining_accounts,
+                ctx.bumps.clone(),
+            ),
+            numbers,
         )
     }
 
+    // buy a ticket for each set of numbers in one instruction
+    // remaining_accounts holds the combination, ticket and registry entry PDAs of each ticket
+    // in order, then the registry tree nodes of the new slots
+    // the PDAs are created here, a combination can be shared by several tickets of the batch
+    pub fn buy_many<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyMany<'info>>,
+        numbers: Vec<[u8; 6]>,
+    ) -> Result<()> {
+        // batch must fit in a transaction, with 3 accounts per ticket and the registry tree nodes
+        let count = numbers.len();
+        if count == 0 || count > MAX_BATCH_SIZE || ctx.remaining_accounts.len() < count * 3 {
+            return Err(error!(ErrorCode::InvalidBatchSize));
+        }
+
+        check_can_buy(&mut ctx.accounts.vault_manager)?;
+
+        let vault_manager_key = ctx.accounts.vault_manager.key();
+        let owner = ctx.accounts.user.key();
+        let user = ctx.accounts.user.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+
+        // tickets of the batch take consecutive slots, nodes they share are loaded once
+        let mut registry = RegistryTree::new(
+            &ctx.remaining_accounts[count * 3..],
+            vault_manager_key,
+            ctx.accounts.vault_manager.draw_id,
+            Some((user.clone(), system_program.clone())),
+        );
+        for (i, numbers) in numbers.iter().enumerate() {
+            let numbers = canonical_numbers(numbers);
+            validate_numbers(&numbers, &ctx.accounts.vault_manager.number_domain)?;
+
+            // holders of this number combination, created by the first ticket holding it
+            let combination_info = &ctx.remaining_accounts[i * 3];
+            let created = create_pda_account(
+                &user,
+                combination_info,
+                &system_program,
+                ctx.program_id,
+                &[&numbers, vault_manager_key.as_ref()],
+                account_space::<Combination>()?,
+            )?;
+            let mut combination: Account<Combination> = if created {
+                Account::try_from_unchecked(combination_info)?
+            } else {
+                Account::try_from(combination_info)?
+            };
+
+            let ticket_info = &ctx.remaining_accounts[i * 3 + 1];
+            let created = create_pda_account(
+                &user,
+                ticket_info,
+                &system_program,
+                ctx.program_id,
+                &[
+                    &numbers,
+                    vault_manager_key.as_ref(),
+                    &combination.next_index.to_le_bytes(),
+                ],
+                account_space::<Ticket>()?,
+            )?;
+            if !created {
+                return Err(error!(ErrorCode::InvalidBatchAccount));
+            }
+            let mut ticket: Account<Ticket> = Account::try_from_unchecked(ticket_info)?;
+
+            let ticket_entry_info = &ctx.remaining_accounts[i * 3 + 2];
+            let created = create_pda_account(
+                &user,
+                ticket_entry_info,
+                &system_program,
+                ctx.program_id,
+                &[
+                    TICKET_ENTRY_SEED,
+                    vault_manager_key.as_ref(),
+                    &ctx.accounts.vault_manager.ticket_count.to_le_bytes(),
+                ],
+                account_space::<TicketEntry>()?,
+            )?;
+            if !created {
+                return Err(error!(ErrorCode::InvalidBatchAccount));
+            }
+            let mut ticket_entry: Account<TicketEntry> =
+                Account::try_from_unchecked(ticket_entry_info)?;
+
+            record_ticket(
+                &mut ctx.accounts.vault_manager,
+                &mut combination,
+                &mut ticket,
+                ticket_info.key(),
+                &mut ticket_entry,
+                owner,
+                numbers,
+            )?;
+            registry.add(&ticket, ctx.program_id)?;
+
+            // write the accounts now, a later ticket of the batch can load the same combination
+            combination.exit(ctx.program_id)?;
+            ticket.exit(ctx.program_id)?;
+            ticket_entry.exit(ctx.program_id)?;
+
+            emit!(TicketPurchased {
+                vault_manager: vault_manager_key,
+                ticket: ticket_info.key(),
+                owner,
+                numbers,
+                draw_id: ticket.draw_id,
+                price: ctx.accounts.vault_manager.ticket_price,
+            });
+        }
+        registry.exit(ctx.program_id)?;
+
+        // transfer the price of every ticket at once
+        let amount = ctx
+            .accounts
+            .vault_manager
+            .ticket_price
+            .checked_mul(count as u64)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        let transfer_accounts = token::Transfer {
+            from: ctx.accounts.user_deposit_ata.clone().to_account_info(),
+            to: ctx.accounts.deposit_vault.clone().to_account_info(),
+            authority: ctx.accounts.user.clone().to_account_info(),
+        };
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.clone().to_account_info(),
+                transfer_accounts,
+            ),
+            amount,
+        )?;
+
+        // ticket price is owed back to each holder on redeem
+        ctx.accounts.vault_manager.total_principal = ctx
+            .accounts
+            .vault_manager
+            .total_principal
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // mint one ticket token per ticket
+        let mint_to_accounts = token::MintTo {
+            mint: ctx.accounts.tickets.clone().to_account_info(),
+            to: ctx.accounts.user_tickets_ata.clone().to_account_info(),
+            authority: ctx.accounts.vault_manager.clone().to_account_info(),
+        };
+
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.clone().to_account_info(),
+                mint_to_accounts,
+                &[&[
+                    ctx.accounts.deposit_mint.clone().key().as_ref(),
+                    ctx.accounts.yield_mint.clone().key().as_ref(),
+                    ctx.accounts.deposit_vault.clone().key().as_ref(),
+                    ctx.accounts.yield_vault.clone().key().as_ref(),
+                    &[*ctx.bumps.get("vault_manager").unwrap()],
+                ]],
+            ),
+            count as u64,
+        )?;
+        Ok(())
+    }
+
     // redeem tickets for deposited tokens
-    pub fn redeem(ctx: Context<Redeem>) -> Result<()> {
-        // TODO: check lockout period
+    // expected_position_value is the caller's quote of the whole yield position in deposit tokens
+    // registry tree nodes of the ticket's slot and the last slot are passed in remaining_accounts
+    pub fn redeem<'info>(
+        ctx: Context<'_, '_, '_, 'info, Redeem<'info>>,
+        expected_position_value: u64,
+    ) -> Result<()> {
+        // ticket takes part in the draw until dispense
+        // a paused draw cannot be dispensed, tickets can still be redeemed so users can always exit
+        if ctx.accounts.vault_manager.locked && !ctx.accounts.vault_manager.paused {
+            return Err(error!(ErrorCode::DrawInProgress));
+        }
+
+        // tickets cannot be bought just before a draw and redeemed right after it
+        let redeemable_at = ctx
+            .accounts
+            .ticket
+            .purchased_at
+            .checked_add(ctx.accounts.vault_manager.min_holding_period)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        if get_current_time() < redeemable_at {
+            return Err(error!(ErrorCode::HoldingPeriodNotElapsed));
+        }
 
         // check if not enough tokens in deposit_vault for redemption, do a swap from yield to deposit vault
-        let deposit_vault_amount = ctx.accounts.deposit_vault.amount;
+        // tokens reserved for prize claims cannot be redeemed
+        let deposit_vault_amount = ctx
+            .accounts
+            .deposit_vault
+            .amount
+            .checked_sub(ctx.accounts.vault_manager.unclaimed_prizes)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
         let ticket_price = ctx.accounts.vault_manager.ticket_price;
-        if deposit_vault_amount < ticket_price {
-            // double it to make sure we can get our minimum_amount_out
-            // TODO: what to do if we dont have enough in yield_vault?
-            let amount_in = ticket_price * 5;
 
-            // swap tokens from yield_vault to deposit_vault
-            // tell the vault manager to approve the user calling this function to swap
-            let approve_accounts = token::Approve {
-                to: ctx.accounts.yield_vault.clone().to_account_info(),
-                delegate: ctx.accounts.user.clone().to_account_info(),
-                authority: ctx.accounts.vault_manager.clone().to_account_info(),
-            };
+        // when the pool is worth less than the deposits, every ticket takes the same loss
+        // without a position the pool accounts are not used, tickets can be redeemed
+        // before set_yield_source
+        let yield_amount = ctx.accounts.yield_vault.amount;
+        let yield_source = if yield_amount > 0 {
+            Some(ctx.accounts.swap_pool.yield_source(
+                &ctx.accounts.vault_manager,
+                &ctx.accounts.deposit_vault,
+                &ctx.accounts.yield_vault,
+                &ctx.accounts.token_program,
+            )?)
+        } else {
+            None
+        };
+        // the position values the redemption, it is bounded by the caller's quote
+        let position_value = match &yield_source {
+            Some(yield_source) => {
+                let position_value = yield_source.position_value(yield_amount)?;
+                check_quote(
+                    position_value,
+                    ctx.accounts.vault_manager.slippage_bps,
+                    expected_position_value,
+                )?;
+                position_value
+            }
+            None => 0,
+        };
+        let pool_value = deposit_vault_amount
+            .checked_add(position_value)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        update_solvency(&mut ctx.accounts.vault_manager, pool_value);
+        let amount = redemption_amount(
+            ticket_price,
+            ctx.accounts.vault_manager.total_principal,
+            pool_value,
+        );
 
-            token::approve(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.clone().to_account_info(),
-                    approve_accounts,
-                    &[&[
-                        ctx.accounts.deposit_mint.clone().key().as_ref(),
-                        ctx.accounts.yield_mint.clone().key().as_ref(),
-                        ctx.accounts.deposit_vault.clone().key().as_ref(),
-                        ctx.accounts.yield_vault.clone().key().as_ref(),
-                        &[*ctx.bumps.get("vault_manager").unwrap()],
-                    ]],
-                ),
-                amount_in,
+        if deposit_vault_amount < amount {
+            let yield_source = yield_source.ok_or(error!(ErrorCode::NotEnoughTokens))?;
+
+            // swap just enough yield tokens for the missing deposit tokens after fees and slippage,
+            // at most the whole position
+            // amount > deposit_vault_amount here
+            let amount_out = amount_out_before_slippage(
+                amount - deposit_vault_amount,
+                ctx.accounts.vault_manager.slippage_bps,
+            );
+            let amount_in =
+                yield_source.withdraw_amount_in(amount_out, ctx.accounts.yield_vault.amount)?;
+
+            // bound the swap output by the pool price
+            let minimum_amount_out = minimum_amount_out(
+                yield_source.withdraw_quote(amount_in)?,
+                ctx.accounts.vault_manager.slippage_bps,
             )?;
 
-            // accounts array
-            let accounts = [
-                ctx.accounts.token_swap_program.clone(),
-                ctx.accounts.token_program.clone().to_account_info(),
-                ctx.accounts.amm.clone(),
-                ctx.accounts.amm_authority.clone(),
-                ctx.accounts.user.clone().to_account_info(),
-                ctx.accounts.deposit_vault.clone().to_account_info(),
-                ctx.accounts.swap_deposit_vault.clone().to_account_info(),
-                ctx.accounts.swap_yield_vault.clone().to_account_info(),
-                ctx.accounts.yield_vault.clone().to_account_info(),
-                ctx.accounts.pool_mint.clone().to_account_info(),
-                ctx.accounts.pool_fee.clone().to_account_info(),
-            ];
-
-            // set data for swap instruction
-            let data = Swap {
-                amount_in: amount_in,
-                minimum_amount_out: ticket_price,
-            };
+            // swap must cover the missing tokens
+            // amount > deposit_vault_amount here
+            if minimum_amount_out < amount - deposit_vault_amount {
+                return Err(error!(ErrorCode::NotEnoughTokens));
+            }
 
-            // create swap instruction
-            let ix = swap(
-                &ctx.accounts.token_swap_program.clone().key(),
-                &ctx.accounts.token_program.clone().key(),
-                &ctx.accounts.amm.clone().key(),
-                &ctx.accounts.amm_authority.clone().key(),
-                &ctx.accounts.user.clone().key(),
-                &ctx.accounts.yield_vault.clone().key(),
-                &ctx.accounts.swap_yield_vault.clone().key(),
-                &ctx.accounts.swap_deposit_vault.clone().key(),
-                &ctx.accounts.deposit_vault.clone().key(),
-                &ctx.accounts.pool_mint.clone().key(),
-                &ctx.accounts.pool_fee.clone().key(),
-                None,
-                data,
+            // swap tokens from yield_vault to deposit_vault
+            yield_source.withdraw(
+                amount_in,
+                minimum_amount_out,
+                &[&[
+                    ctx.accounts.deposit_mint.clone().key().as_ref(),
+                    ctx.accounts.yield_mint.clone().key().as_ref(),
+                    ctx.accounts.deposit_vault.clone().key().as_ref(),
+                    ctx.accounts.yield_vault.clone().key().as_ref(),
+                    &[*ctx.bumps.get("vault_manager").unwrap()],
+                ]],
             )?;
 
-            // swap tokens
-            match anchor_lang::solana_program::program::invoke(&ix, &accounts) {
-                Ok(()) => {}
-                Err(e) => return Err(e.into()),
-            };
+            // reload account to update deposit_vault amount
+            ctx.accounts.deposit_vault.reload()?;
+
+            emit!(SwapExecuted {
+                vault_manager: ctx.accounts.vault_manager.key(),
+                source_vault: ctx.accounts.yield_vault.key(),
+                destination_vault: ctx.accounts.deposit_vault.key(),
+                amount_in,
+                amount_out: ctx
+                    .accounts
+                    .deposit_vault
+                    .amount
+                    .saturating_sub(ctx.accounts.vault_manager.unclaimed_prizes)
+                    .saturating_sub(deposit_vault_amount),
+            });
         }
 
         let transfer_accounts = token::Transfer {
@@ -209,8 +524,49 @@ pub mod no_loss_lottery {
                     &[*ctx.bumps.get("vault_manager").unwrap()],
                 ]],
             ),
-            ticket_price,
+            amount,
+        )?;
+
+        // ticket is paid back in full or takes its share of the loss
+        ctx.accounts.vault_manager.total_principal = ctx
+            .accounts
+            .vault_manager
+            .total_principal
+            .checked_sub(ticket_price)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // move the last registry entry into the slot of the redeemed ticket
+        // registry slots stay contiguous so any index below ticket_count can be drawn
+        // the last slot is closed on exit, with the redeemed ticket
+        let mut registry = RegistryTree::new(
+            ctx.remaining_accounts,
+            ctx.accounts.vault_manager.key(),
+            ctx.accounts.vault_manager.draw_id,
+            None,
+        );
+        move_last_ticket_entry(
+            &ctx.accounts.ticket,
+            &ctx.accounts.ticket_entry,
+            &ctx.accounts.last_ticket_entry,
+            &ctx.accounts.last_ticket,
+            &mut registry,
+            ctx.program_id,
         )?;
+        registry.exit(ctx.program_id)?;
+        ctx.accounts.vault_manager.ticket_count = ctx
+            .accounts
+            .vault_manager
+            .ticket_count
+            .checked_sub(1)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // the winner of a paused draw would be picked from a registry that changed after draw,
+        // the draw is cancelled and drawn again once unpaused
+        // a commitment not revealed yet carries over to the next draw with its bond
+        if ctx.accounts.vault_manager.locked && draws_from_registry(&ctx.accounts.vault_manager) {
+            let now = get_current_time();
+            cancel_draw(&mut ctx.accounts.vault_manager, now)?;
+        }
 
         // burn a ticket from the user ATA
         let burn_accounts = token::Burn {
@@ -228,11 +584,24 @@ pub mod no_loss_lottery {
             1,
         )?;
 
-        // close ticket PDA
-        // return tokens to user
-        ctx.accounts
-            .ticket
-            .close(ctx.accounts.user.clone().to_account_info())
+        // remove ticket from the holders of its number combination
+        ctx.accounts.combination.count = ctx
+            .accounts
+            .combination
+            .count
+            .checked_sub(1)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        emit!(TicketRedeemed {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            ticket: ctx.accounts.ticket.key(),
+            owner: ctx.accounts.user.key(),
+            numbers: ctx.accounts.ticket.numbers,
+            amount,
+        });
+
+        // ticket PDA is closed on exit, its rent goes back to the user
+        Ok(())
     }
 
     pub fn draw(ctx: Context<Draw>) -> Result<()> {
@@ -255,147 +624,287 @@ pub mod no_loss_lottery {
             return Err(ErrorCode::TimeRemaining.into());
         }
 
-        // randomly choose 6 winning numbers
-        let numbers: [u8; 6] = [1, 2, 3, 4, 5, 6];
+        let slot = Clock::get()?.slot;
+        match ctx.accounts.vault_manager.randomness_mode {
+            RandomnessMode::Oracle => {
+                // the oracle signs the request with the nonce it committed to, the request
+                // mixes in a slot hash produced after that commitment so the nonce could
+                // not have been picked with the request in mind
+                let (hash_slot, slot_hash) = latest_slot_hash(&ctx.accounts.slot_hashes)?;
+                if ctx.accounts.vault_manager.oracle_nonce == [0u8; 32]
+                    || hash_slot <= ctx.accounts.vault_manager.oracle_nonce_slot
+                {
+                    return Err(ErrorCode::NoOracleNonce.into());
+                }
+
+                // request randomness from the oracle
+                // seed is unique per draw so a fulfillment cannot be replayed for a later draw
+                ctx.accounts.vault_manager.randomness_request = hashv(&[
+                    ctx.accounts.vault_manager.key().as_ref(),
+                    &cutoff_time.to_le_bytes(),
+                    &slot.to_le_bytes(),
+                    &slot_hash,
+                ])
+                .to_bytes();
+
+                // oracle has until the deadline to fulfill
+                ctx.accounts.vault_manager.reveal_deadline = now
+                    .checked_add(ctx.accounts.vault_manager.fulfill_duration)
+                    .ok_or(error!(ErrorCode::MathOverflow))?;
+            }
+            RandomnessMode::CommitReveal => {
+                // crank must have committed before cutoff_time
+                if ctx.accounts.vault_manager.commitment == [0u8; 32] {
+                    return Err(ErrorCode::NoCommitment.into());
+                }
+
+                // reveal is mixed with the hash of a slot that has not been produced yet
+                ctx.accounts.vault_manager.reveal_slot = slot
+                    .checked_add(REVEAL_SLOT_DELAY)
+                    .ok_or(error!(ErrorCode::MathOverflow))?;
+                ctx.accounts.vault_manager.reveal_deadline = now
+                    .checked_add(ctx.accounts.vault_manager.reveal_duration)
+                    .ok_or(error!(ErrorCode::MathOverflow))?;
+            }
+        }
+        ctx.accounts.vault_manager.randomness_pending = true;
 
-        // set numbers in vault_manager account
-        ctx.accounts.vault_manager.winning_numbers = numbers;
+        // recorded in the draw record by dispense
+        ctx.accounts.vault_manager.drawn_at = now;
+        ctx.accounts.vault_manager.draw_ticket_supply = ctx.accounts.tickets.supply;
 
-        // store numbers for frontend to query
-        ctx.accounts.vault_manager.previous_winning_numbers = numbers;
+        // winning numbers are set once the randomness is fulfilled or revealed
+        ctx.accounts.vault_manager.winning_numbers = [0u8; 6];
 
         // locked `buy` function until `find` called
         ctx.accounts.vault_manager.locked = true;
+
+        emit!(DrawStarted {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: ctx.accounts.vault_manager.draw_id,
+            randomness_mode: ctx.accounts.vault_manager.randomness_mode,
+            ticket_supply: ctx.accounts.vault_manager.draw_ticket_supply,
+            drawn_at: now,
+        });
         Ok(())
     }
 
-    // check if a winning PDA exists
-    // force passing in the winning numbers PDA
-    // if PDA exists, send prize
-    // if not error
-    pub fn dispense(ctx: Context<Dispense>, numbers: [u8; 6]) -> Result<()> {
-        // crank must pass in winning PDA
-        if numbers != ctx.accounts.vault_manager.winning_numbers {
-            return Err(ErrorCode::PassInWinningPDA.into());
+    // fulfills the request made by `draw` with the randomness oracle signature over it
+    // the signature is checked by the Ed25519 program instruction right before this one
+    // and hashed into the randomness
+    // an ed25519 signer picks its own nonce, so any number of valid signatures exist for one
+    // request, the signature must use the nonce committed before the draw which leaves
+    // exactly one valid signature, the oracle can withhold it but cannot choose another
+    pub fn fulfill_randomness(ctx: Context<FulfillRandomness>) -> Result<()> {
+        if ctx.accounts.vault_manager.randomness_mode != RandomnessMode::Oracle {
+            return Err(ErrorCode::InvalidRandomnessMode.into());
         }
 
-        let now = get_current_time();
+        // only fulfill if draw has requested randomness
+        if !ctx.accounts.vault_manager.locked || !ctx.accounts.vault_manager.randomness_pending {
+            return Err(ErrorCode::RandomnessNotRequested.into());
+        }
 
-        // set next cutoff time
-        ctx.accounts.vault_manager.cutoff_time = now + ctx.accounts.vault_manager.draw_duration;
+        // with the nonce point R fixed, S = r + H(R, A, request) * a is unique as long as it
+        // is reduced, one request has exactly one randomness
+        let request = ctx.accounts.vault_manager.randomness_request;
+        let signature = verify_oracle_signature(
+            &ctx.accounts.instructions,
+            &ctx.accounts.vault_manager.randomness_oracle,
+            &request,
+        )?;
+        if signature[..32] != ctx.accounts.vault_manager.oracle_nonce
+            || !is_reduced_scalar(&signature[32..])
+        {
+            return Err(ErrorCode::InvalidRandomnessProof.into());
+        }
+        let randomness = hashv(&[&signature]).to_bytes();
 
-        // unlock buy tickets
-        ctx.accounts.vault_manager.locked = false;
+        // derive winning numbers of the number domain from the randomness
+        let numbers =
+            derive_winning_numbers(&randomness, &ctx.accounts.vault_manager.number_domain);
 
-        // zero out winning numbers
-        ctx.accounts.vault_manager.winning_numbers = [0u8; 6];
+        // set numbers in vault_manager account
+        ctx.accounts.vault_manager.winning_numbers = numbers;
 
-        // if numbers are zeroed out this means this account was initialized in this instruction
-        // no winner found
-        if ctx.accounts.ticket.numbers == [0u8; 6] {
-            // we cannot error here because we need the variables to persist in the vault_manager account
-            // close newly created account and return SOL to user
-            // TODO: emit an event for this condition
-            return ctx
-                .accounts
-                .ticket
-                .close(ctx.accounts.user.to_account_info());
+        // kept for the draw record so the numbers can be verified
+        ctx.accounts.vault_manager.randomness = randomness;
+        // the signed request, the signature itself stays in the fulfill transaction
+        ctx.accounts.vault_manager.randomness_proof = request;
+        ctx.accounts.vault_manager.fulfilled_at = get_current_time();
+
+        // request fulfilled, dispense can be called
+        ctx.accounts.vault_manager.randomness_pending = false;
+        ctx.accounts.vault_manager.randomness_request = [0u8; 32];
+
+        // nonce is spent, signing a second message with it would reveal the oracle key
+        ctx.accounts.vault_manager.oracle_nonce = [0u8; 32];
+        ctx.accounts.vault_manager.oracle_nonce_slot = 0;
+
+        emit!(WinningNumbersDrawn {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: ctx.accounts.vault_manager.draw_id,
+            winning_numbers: numbers,
+        });
+        Ok(())
+    }
+
+    // oracle commits to the nonce point R of its signature over the next request
+    // the nonce can be replaced until the draw starts, the request is only known after that
+    pub fn commit_oracle_nonce(ctx: Context<CommitOracleNonce>, nonce: [u8; 32]) -> Result<()> {
+        if ctx.accounts.vault_manager.randomness_mode != RandomnessMode::Oracle {
+            return Err(ErrorCode::InvalidRandomnessMode.into());
         }
 
-        // swap all tokens from yield vault to deposit vault
-        let amount_in = ctx.accounts.yield_vault.amount;
-        let minimum_amount_out = amount_in / 2; // TODO: how to configure slippage?
+        // if locked, the draw has already started
+        if ctx.accounts.vault_manager.locked {
+            return Err(ErrorCode::CallDispense.into());
+        }
 
-        // if amount_in is 0 or less, return without error
-        if amount_in <= 0 {
-            return Ok(());
+        // the zero nonce marks no commitment
+        if nonce == [0u8; 32] {
+            return Err(ErrorCode::NoOracleNonce.into());
         }
 
-        // tell the vault manager to approve the user calling this function to swap
-        let approve_accounts = token::Approve {
-            to: ctx.accounts.yield_vault.clone().to_account_info(),
-            delegate: ctx.accounts.user.clone().to_account_info(),
-            authority: ctx.accounts.vault_manager.clone().to_account_info(),
+        let slot = Clock::get()?.slot;
+        ctx.accounts.vault_manager.oracle_nonce = nonce;
+        ctx.accounts.vault_manager.oracle_nonce_slot = slot;
+
+        emit!(OracleNonceCommitted {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: ctx.accounts.vault_manager.draw_id,
+            nonce,
+            slot,
+        });
+        Ok(())
+    }
+
+    // commit to a secret before cutoff_time, commit-reveal mode only
+    // only the commit authority can commit, it posts a bond of commit_bond deposit tokens to the
+    // treasury which is returned on reveal and forfeited if the reveal never arrives
+    pub fn commit(ctx: Context<Commit>, commitment: [u8; 32]) -> Result<()> {
+        if ctx.accounts.vault_manager.randomness_mode != RandomnessMode::CommitReveal {
+            return Err(ErrorCode::InvalidRandomnessMode.into());
+        }
+
+        let cutoff_time = ctx.accounts.vault_manager.cutoff_time;
+
+        // if no tickets have been purchased, there is nothing to draw
+        if cutoff_time == 0 {
+            return Err(ErrorCode::NoTicketsPurchased.into());
+        }
+
+        // if locked, the draw has already started
+        if ctx.accounts.vault_manager.locked {
+            return Err(ErrorCode::CallDispense.into());
+        }
+
+        // commitment must be made before the ticket set is final
+        if get_current_time() >= cutoff_time {
+            return Err(ErrorCode::CommitmentTooLate.into());
+        }
+
+        // only one commitment per draw
+        if ctx.accounts.vault_manager.commitment != [0u8; 32] {
+            return Err(ErrorCode::CommitmentExists.into());
+        }
+
+        // zeroed commitment means no commitment
+        if commitment == [0u8; 32] {
+            return Err(ErrorCode::InvalidCommitment.into());
+        }
+
+        // transfer bond from committer to treasury
+        let transfer_accounts = token::Transfer {
+            from: ctx.accounts.user_deposit_ata.clone().to_account_info(),
+            to: ctx.accounts.treasury.clone().to_account_info(),
+            authority: ctx.accounts.user.clone().to_account_info(),
         };
 
-        token::approve(
-            CpiContext::new_with_signer(
+        token::transfer(
+            CpiContext::new(
                 ctx.accounts.token_program.clone().to_account_info(),
-                approve_accounts,
-                &[&[
-                    ctx.accounts.deposit_mint.clone().key().as_ref(),
-                    ctx.accounts.yield_mint.clone().key().as_ref(),
-                    ctx.accounts.deposit_vault.clone().key().as_ref(),
-                    ctx.accounts.yield_vault.clone().key().as_ref(),
-                    &[*ctx.bumps.get("vault_manager").unwrap()],
-                ]],
+                transfer_accounts,
             ),
-            amount_in,
+            ctx.accounts.vault_manager.commit_bond,
         )?;
 
-        // accounts array
-        let accounts = [
-            ctx.accounts.token_swap_program.clone(),
-            ctx.accounts.token_program.clone().to_account_info(),
-            ctx.accounts.amm.clone(),
-            ctx.accounts.amm_authority.clone(),
-            ctx.accounts.user.clone().to_account_info(),
-            ctx.accounts.deposit_vault.clone().to_account_info(),
-            ctx.accounts.swap_deposit_vault.clone().to_account_info(),
-            ctx.accounts.swap_yield_vault.clone().to_account_info(),
-            ctx.accounts.yield_vault.clone().to_account_info(),
-            ctx.accounts.pool_mint.clone().to_account_info(),
-            ctx.accounts.pool_fee.clone().to_account_info(),
-        ];
-
-        // set data for swap instruction
-        let data = Swap {
-            amount_in: amount_in,
-            minimum_amount_out: minimum_amount_out,
-        };
+        ctx.accounts.vault_manager.commitment = commitment;
+        ctx.accounts.vault_manager.committer = ctx.accounts.user.key();
+        // bond config can change before the reveal, the posted amount is returned
+        ctx.accounts.vault_manager.committed_bond = ctx.accounts.vault_manager.commit_bond;
+
+        emit!(Committed {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: ctx.accounts.vault_manager.draw_id,
+            committer: ctx.accounts.user.key(),
+            commitment,
+        });
+        Ok(())
+    }
 
-        // create swap instruction
-        let ix = swap(
-            &ctx.accounts.token_swap_program.clone().key(),
-            &ctx.accounts.token_program.clone().key(),
-            &ctx.accounts.amm.clone().key(),
-            &ctx.accounts.amm_authority.clone().key(),
-            &ctx.accounts.user.clone().key(),
-            &ctx.accounts.yield_vault.clone().key(),
-            &ctx.accounts.swap_yield_vault.clone().key(),
-            &ctx.accounts.swap_deposit_vault.clone().key(),
-            &ctx.accounts.deposit_vault.clone().key(),
-            &ctx.accounts.pool_mint.clone().key(),
-            &ctx.accounts.pool_fee.clone().key(),
-            None,
-            data,
+    // reveal the committed secret after draw, commit-reveal mode only
+    // the secret is mixed with the hash of `reveal_slot` to derive the winning numbers
+    pub fn reveal(ctx: Context<Reveal>, secret: [u8; 32]) -> Result<()> {
+        if ctx.accounts.vault_manager.randomness_mode != RandomnessMode::CommitReveal {
+            return Err(ErrorCode::InvalidRandomnessMode.into());
+        }
+
+        // only reveal if draw is waiting for randomness
+        if !ctx.accounts.vault_manager.locked || !ctx.accounts.vault_manager.randomness_pending {
+            return Err(ErrorCode::RandomnessNotRequested.into());
+        }
+
+        // reveal window closed, call expire_draw
+        if get_current_time() > ctx.accounts.vault_manager.reveal_deadline {
+            return Err(ErrorCode::RevealExpired.into());
+        }
+
+        // secret must match the commitment
+        if hashv(&[&secret]).to_bytes() != ctx.accounts.vault_manager.commitment {
+            return Err(ErrorCode::InvalidReveal.into());
+        }
+
+        // mix the secret with a slot hash unknown at commit time
+        let slot_hash = find_slot_hash(
+            &ctx.accounts.slot_hashes,
+            ctx.accounts.vault_manager.reveal_slot,
         )?;
+        let randomness = hashv(&[&secret, &slot_hash]).to_bytes();
 
-        // swap tokens
-        match anchor_lang::solana_program::program::invoke(&ix, &accounts) {
-            Ok(()) => {
-                // reload account to update deposit_vault amount
-                ctx.accounts.deposit_vault.reload()?;
-            }
-            Err(e) => return Err(e.into()),
-        };
+        // derive winning numbers of the number domain from the randomness
+        let numbers =
+            derive_winning_numbers(&randomness, &ctx.accounts.vault_manager.number_domain);
 
-        // calculate winner prize
-        // TODO: add our fee
-        let prize_amount = calculate_prize(
-            ctx.accounts.tickets.supply,
-            ctx.accounts.vault_manager.ticket_price,
-            ctx.accounts.deposit_vault.amount,
-        );
+        // set numbers in vault_manager account
+        ctx.accounts.vault_manager.winning_numbers = numbers;
+
+        // kept for the draw record, the secret is the proof in commit-reveal mode
+        ctx.accounts.vault_manager.randomness = randomness;
+        ctx.accounts.vault_manager.randomness_proof = secret;
+        ctx.accounts.vault_manager.fulfilled_at = get_current_time();
+
+        // revealed, dispense can be called
+        ctx.accounts.vault_manager.randomness_pending = false;
+        ctx.accounts.vault_manager.commitment = [0u8; 32];
+        ctx.accounts.vault_manager.committer = Pubkey::default();
+
+        emit!(WinningNumbersDrawn {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: ctx.accounts.vault_manager.draw_id,
+            winning_numbers: numbers,
+        });
+
+        // return bond to committer
+        let committed_bond = ctx.accounts.vault_manager.committed_bond;
+        ctx.accounts.vault_manager.committed_bond = 0;
 
-        // transfer prize amount to winner
         let transfer_accounts = token::Transfer {
-            from: ctx.accounts.deposit_vault.clone().to_account_info(),
+            from: ctx.accounts.treasury.clone().to_account_info(),
             to: ctx.accounts.user_deposit_ata.clone().to_account_info(),
             authority: ctx.accounts.vault_manager.clone().to_account_info(),
         };
 
-        // transfer prize to winner
         token::transfer(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.clone().to_account_info(),
@@ -408,88 +917,951 @@ pub mod no_loss_lottery {
                     &[*ctx.bumps.get("vault_manager").unwrap()],
                 ]],
             ),
-            prize_amount,
+            committed_bond,
         )
     }
 
-    // convert deposit_mint tokens into yield_mint tokens
-    // call with a crank
-    pub fn stake(ctx: Context<Stake>) -> Result<()> {
-        let mut amount_in = ctx.accounts.deposit_vault.amount;
-
-        // if less than n tokens, do not stake
-        // wait for more tickets to be purchased
-        if amount_in < ctx.accounts.vault_manager.deposit_token_reserve {
-            return Err(error!(ErrorCode::NotEnoughTokens));
-        };
+    // fallback so the lottery can never be stuck
+    // if the randomness is not fulfilled or revealed by the deadline, the draw is cancelled
+    // and the committer bond is forfeited
+    // in commit-reveal mode, if nobody committed before cutoff_time, the draw is pushed back
+    // by draw_duration
+    pub fn expire_draw(ctx: Context<ExpireDraw>) -> Result<()> {
+        let now = get_current_time();
+        let vault_mgr = &mut ctx.accounts.vault_manager;
 
-        // subtract reserve from amount to stake
-        amount_in = amount_in - ctx.accounts.vault_manager.deposit_token_reserve;
+        if vault_mgr.locked && vault_mgr.randomness_pending {
+            // oracle or committer still has time
+            if now <= vault_mgr.reveal_deadline {
+                return Err(ErrorCode::DrawNotExpired.into());
+            }
 
-        // tell the vault manager to approve the user calling this function to swap
-        let approve_accounts = token::Approve {
-            to: ctx.accounts.deposit_vault.clone().to_account_info(),
-            delegate: ctx.accounts.user.clone().to_account_info(),
-            authority: ctx.accounts.vault_manager.clone().to_account_info(),
-        };
+            // cancel the draw, tickets roll over to the next draw
+            cancel_draw(vault_mgr, now)?;
+
+            // in commit-reveal mode the bond stays in the treasury for the authority to withdraw,
+            // paying it to the caller would let the committer cancel draws for free by expiring
+            // its own draw
+            if vault_mgr.committed_bond > 0 {
+                vault_mgr.total_fees_collected = vault_mgr
+                    .total_fees_collected
+                    .checked_add(vault_mgr.committed_bond)
+                    .ok_or(error!(ErrorCode::MathOverflow))?;
+
+                emit!(BondForfeited {
+                    vault_manager: vault_mgr.key(),
+                    draw_id: vault_mgr.draw_id,
+                    committer: vault_mgr.committer,
+                    amount: vault_mgr.committed_bond,
+                });
+            }
+            vault_mgr.commitment = [0u8; 32];
+            vault_mgr.committer = Pubkey::default();
+            vault_mgr.committed_bond = 0;
+            return Ok(());
+        }
 
-        token::approve(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.clone().to_account_info(),
-                approve_accounts,
-                &[&[
-                    ctx.accounts.deposit_mint.clone().key().as_ref(),
-                    ctx.accounts.yield_mint.clone().key().as_ref(),
-                    ctx.accounts.deposit_vault.clone().key().as_ref(),
-                    ctx.accounts.yield_vault.clone().key().as_ref(),
-                    &[*ctx.bumps.get("vault_manager").unwrap()],
-                ]],
-            ),
-            amount_in,
-        )?;
+        // no commitment made in time, draw cannot happen
+        if vault_mgr.randomness_mode == RandomnessMode::CommitReveal
+            && !vault_mgr.locked
+            && vault_mgr.commitment == [0u8; 32]
+            && vault_mgr.cutoff_time != 0
+            && now >= vault_mgr.cutoff_time
+        {
+            vault_mgr.cutoff_time = now
+                .checked_add(vault_mgr.draw_duration)
+                .ok_or(error!(ErrorCode::MathOverflow))?;
+
+            emit!(DrawPostponed {
+                vault_manager: vault_mgr.key(),
+                draw_id: vault_mgr.draw_id,
+                cutoff_time: vault_mgr.cutoff_time,
+            });
+            return Ok(());
+        }
 
-        // accounts array
-        let accounts = [
-            ctx.accounts.token_swap_program.clone(),
-            ctx.accounts.token_program.clone().to_account_info(),
-            ctx.accounts.amm.clone(),
-            ctx.accounts.amm_authority.clone(),
-            ctx.accounts.user.clone().to_account_info(),
-            ctx.accounts.deposit_vault.clone().to_account_info(),
-            ctx.accounts.swap_deposit_vault.clone().to_account_info(),
-            ctx.accounts.swap_yield_vault.clone().to_account_info(),
-            ctx.accounts.yield_vault.clone().to_account_info(),
-            ctx.accounts.pool_mint.clone().to_account_info(),
-            ctx.accounts.pool_fee.clone().to_account_info(),
-        ];
-
-        // set data for swap instruction
-        // TODO: figure out best way to determine minimum_amount_out with least amount of slippage
-        // for now set to 50%
-        let data = Swap {
-            amount_in: amount_in,
-            minimum_amount_out: amount_in / 2,
-        };
+        Err(ErrorCode::DrawNotExpired.into())
+    }
 
-        // create swap instruction
-        let ix = swap(
-            &ctx.accounts.token_swap_program.clone().key(),
-            &ctx.accounts.token_program.clone().key(),
-            &ctx.accounts.amm.clone().key(),
-            &ctx.accounts.amm_authority.clone().key(),
-            &ctx.accounts.user.clone().key(),
-            &ctx.accounts.deposit_vault.clone().key(),
-            &ctx.accounts.swap_deposit_vault.clone().key(),
-            &ctx.accounts.swap_yield_vault.clone().key(),
-            &ctx.accounts.yield_vault.clone().key(),
-            &ctx.accounts.pool_mint.clone().key(),
-            &ctx.accounts.pool_fee.clone().key(),
-            None,
-            data,
+    // swap yield and record the prize of every tier in the draw record
+    // ticket holders register their match count with `register_match`
+    // and withdraw their share with `claim_prize` once registration closes
+    // expected_amount_out is the caller's quote of swapping the whole yield position
+    pub fn dispense(
+        ctx: Context<Dispense>,
+        numbers: [u8; 6],
+        expected_amount_out: u64,
+    ) -> Result<()> {
+        // no swaps while paused
+        if ctx.accounts.vault_manager.paused {
+            return Err(error!(ErrorCode::Paused));
+        }
+
+        // crank must pass in winning numbers
+        if numbers != ctx.accounts.vault_manager.winning_numbers {
+            return Err(ErrorCode::PassInWinningPDA.into());
+        }
+
+        // winning numbers must be drawn before dispensing
+        if !ctx.accounts.vault_manager.locked || ctx.accounts.vault_manager.randomness_pending {
+            return Err(ErrorCode::RandomnessNotFulfilled.into());
+        }
+
+        let now = get_current_time();
+
+        // set next cutoff time
+        ctx.accounts.vault_manager.cutoff_time = now
+            .checked_add(ctx.accounts.vault_manager.draw_duration)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // unlock buy tickets
+        ctx.accounts.vault_manager.locked = false;
+
+        // zero out winning numbers
+        ctx.accounts.vault_manager.winning_numbers = [0u8; 6];
+
+        // tickets bought from now on take part in the next draw
+        let draw_id = ctx.accounts.vault_manager.draw_id;
+        ctx.accounts.vault_manager.draw_id = draw_id
+            .checked_add(1)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // swap all tokens from yield vault to deposit vault
+        let amount_in = ctx.accounts.yield_vault.amount;
+
+        // nothing to swap, the prize is whatever is left in the deposit vault
+        if amount_in > 0 {
+            // bound the swap output by the pool price
+            let yield_source = ctx.accounts.swap_pool.yield_source(
+                &ctx.accounts.vault_manager,
+                &ctx.accounts.deposit_vault,
+                &ctx.accounts.yield_vault,
+                &ctx.accounts.token_program,
+            )?;
+            let pool_amount_out = yield_source.withdraw_quote(amount_in)?;
+            check_quote(
+                pool_amount_out,
+                ctx.accounts.vault_manager.slippage_bps,
+                expected_amount_out,
+            )?;
+            let minimum_amount_out =
+                minimum_amount_out(pool_amount_out, ctx.accounts.vault_manager.slippage_bps)?;
+
+            // swap tokens from yield_vault to deposit_vault
+            let deposit_vault_amount = ctx.accounts.deposit_vault.amount;
+            yield_source.withdraw(
+                amount_in,
+                minimum_amount_out,
+                &[&[
+                    ctx.accounts.deposit_mint.clone().key().as_ref(),
+                    ctx.accounts.yield_mint.clone().key().as_ref(),
+                    ctx.accounts.deposit_vault.clone().key().as_ref(),
+                    ctx.accounts.yield_vault.clone().key().as_ref(),
+                    &[*ctx.bumps.get("vault_manager").unwrap()],
+                ]],
+            )?;
+
+            // reload account to update deposit_vault amount
+            ctx.accounts.deposit_vault.reload()?;
+
+            emit!(SwapExecuted {
+                vault_manager: ctx.accounts.vault_manager.key(),
+                source_vault: ctx.accounts.yield_vault.key(),
+                destination_vault: ctx.accounts.deposit_vault.key(),
+                amount_in,
+                amount_out: ctx
+                    .accounts
+                    .deposit_vault
+                    .amount
+                    .saturating_sub(deposit_vault_amount),
+            });
+        }
+
+        // calculate prize
+        // tokens reserved for earlier draws are not part of this prize
+        // the whole position has been swapped back, the deposit vault is the pool value
+        let pool_value = ctx
+            .accounts
+            .deposit_vault
+            .amount
+            .checked_sub(ctx.accounts.vault_manager.unclaimed_prizes)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // pool is worth less than the deposits, e.g. after impermanent loss
+        // there is no prize and the draw goes ahead so the lottery is not stuck
+        update_solvency(&mut ctx.accounts.vault_manager, pool_value);
+        let mut prize_amount =
+            calculate_prize(ctx.accounts.vault_manager.total_principal, pool_value);
+
+        // protocol fee is taken from the yield before it is shared out
+        // prizes carried from earlier draws were charged when they were first dispensed
+        let carried = std::cmp::min(ctx.accounts.vault_manager.rolled_over, prize_amount);
+        let fee_amount = bps_of(prize_amount - carried, ctx.accounts.vault_manager.fee_bps)?;
+        if fee_amount > 0 {
+            let transfer_accounts = token::Transfer {
+                from: ctx.accounts.deposit_vault.clone().to_account_info(),
+                to: ctx.accounts.treasury.clone().to_account_info(),
+                authority: ctx.accounts.vault_manager.clone().to_account_info(),
+            };
+
+            // move fee from vault to treasury
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.clone().to_account_info(),
+                    transfer_accounts,
+                    &[&[
+                        ctx.accounts.deposit_mint.clone().key().as_ref(),
+                        ctx.accounts.yield_mint.clone().key().as_ref(),
+                        ctx.accounts.deposit_vault.clone().key().as_ref(),
+                        ctx.accounts.yield_vault.clone().key().as_ref(),
+                        &[*ctx.bumps.get("vault_manager").unwrap()],
+                    ]],
+                ),
+                fee_amount,
+            )?;
+
+            // fee is at most the whole prize
+            prize_amount -= fee_amount;
+            ctx.accounts.vault_manager.total_fees_collected = ctx
+                .accounts
+                .vault_manager
+                .total_fees_collected
+                .checked_add(fee_amount)
+                .ok_or(error!(ErrorCode::MathOverflow))?;
+        }
+
+        // record draw history
+        let registration_deadline = now
+            .checked_add(ctx.accounts.vault_manager.registration_duration)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        let draw_record = &mut ctx.accounts.draw_record;
+        draw_record.vault_manager = ctx.accounts.vault_manager.key();
+        draw_record.draw_id = draw_id;
+        draw_record.winning_numbers = numbers;
+        draw_record.randomness_mode = ctx.accounts.vault_manager.randomness_mode;
+        draw_record.randomness = ctx.accounts.vault_manager.randomness;
+        draw_record.randomness_proof = ctx.accounts.vault_manager.randomness_proof;
+        draw_record.ticket_supply = ctx.accounts.vault_manager.draw_ticket_supply;
+        draw_record.drawn_at = ctx.accounts.vault_manager.drawn_at;
+        draw_record.fulfilled_at = ctx.accounts.vault_manager.fulfilled_at;
+        draw_record.dispensed_at = now;
+        draw_record.prize_amount = prize_amount;
+        draw_record.fee_amount = fee_amount;
+        draw_record.period_start = ctx.accounts.vault_manager.period_start;
+        draw_record.registration_deadline = registration_deadline;
+        draw_record.claim_deadline = registration_deadline
+            .checked_add(ctx.accounts.vault_manager.claim_duration)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // prizes carried from earlier draws are part of this prize
+        draw_record.rolled_over = carried;
+        ctx.accounts.vault_manager.rolled_over = 0;
+        ctx.accounts.vault_manager.jackpot = prize_amount;
+
+        // too many draws in a row without a winner, one ticket from the registry takes the prize
+        let consolation = consolation_due(&ctx.accounts.vault_manager);
+        let lottery_mode = if consolation {
+            LotteryMode::Ticket
+        } else {
+            ctx.accounts.vault_manager.lottery_mode
+        };
+        draw_record.lottery_mode = lottery_mode;
+        draw_record.consolation = consolation;
+        draw_record.number_domain = ctx.accounts.vault_manager.number_domain;
+
+        match lottery_mode {
+            LotteryMode::Numbers => {
+                // share out the prize between the tiers, what is left stays in the pool
+                for (tier, bps) in ctx.accounts.vault_manager.prize_tiers.iter().enumerate() {
+                    draw_record.tier_prizes[tier] = bps_of(prize_amount, *bps)?;
+                    draw_record.reserved = draw_record
+                        .reserved
+                        .checked_add(draw_record.tier_prizes[tier])
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                }
+
+                // draws are counted here in draw id order, a draw rolls over when no ticket
+                // holds the winning numbers
+                let jackpot_holders = load_jackpot_holders(
+                    ctx.remaining_accounts,
+                    &ctx.accounts.vault_manager.key(),
+                    &numbers,
+                    ctx.program_id,
+                )?;
+                if jackpot_holders == 0 {
+                    ctx.accounts.vault_manager.rollover_count = ctx
+                        .accounts
+                        .vault_manager
+                        .rollover_count
+                        .checked_add(1)
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                } else {
+                    ctx.accounts.vault_manager.rollover_count = 0;
+                }
+            }
+            LotteryMode::Ticket => {
+                // registry cannot change between draw and dispense, buy and redeem are locked
+                // and redeeming while paused cancels the draw
+                let ticket_count = ctx.accounts.vault_manager.ticket_count;
+
+                // no tickets left, the prize stays in the pool and the draw rolls over
+                if ticket_count == 0 {
+                    ctx.accounts.vault_manager.rollover_count = ctx
+                        .accounts
+                        .vault_manager
+                        .rollover_count
+                        .checked_add(1)
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                } else {
+                    // tickets are drawn by the time they were held during the period,
+                    // the registry entry of the drawn slot comes first in remaining_accounts
+                    let (winning_index, registry_weight) = draw_registry_slot(
+                        ctx.remaining_accounts,
+                        &ctx.accounts.vault_manager.key(),
+                        draw_record,
+                        ctx.program_id,
+                    )?;
+                    let winning_entry = load_ticket_entry(
+                        ctx.remaining_accounts,
+                        &ctx.accounts.vault_manager.key(),
+                        winning_index,
+                        ctx.program_id,
+                    )?;
+
+                    // drawn ticket wins the whole prize in the top tier
+                    draw_record.winning_index = winning_index;
+                    draw_record.winning_ticket = winning_entry.ticket;
+                    draw_record.registry_weight = registry_weight;
+                    draw_record.tier_prizes[PRIZE_TIER_COUNT - 1] = prize_amount;
+                    draw_record.reserved = prize_amount;
+
+                    // the jackpot is paid out, rollovers start over
+                    ctx.accounts.vault_manager.rollover_count = 0;
+
+                    emit!(WinningTicketDrawn {
+                        vault_manager: ctx.accounts.vault_manager.key(),
+                        draw_id,
+                        winning_index,
+                        ticket: winning_entry.ticket,
+                    });
+                }
+            }
+        }
+
+        // prize left unreserved stays in the pool and is carried into the next prize
+        ctx.accounts.vault_manager.rolled_over = prize_amount
+            .checked_sub(draw_record.reserved)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // next draw period starts now
+        ctx.accounts.vault_manager.period_start = now;
+
+        // reserve prizes in the deposit vault until they are claimed or expire
+        ctx.accounts.vault_manager.unclaimed_prizes = ctx
+            .accounts
+            .vault_manager
+            .unclaimed_prizes
+            .checked_add(draw_record.reserved)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        emit!(DrawDispensed {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id,
+            draw_record: draw_record.key(),
+            prize_amount,
+            fee_amount,
+            tier_prizes: draw_record.tier_prizes,
+            rolled_over: draw_record.rolled_over,
+            consolation,
+        });
+        Ok(())
+    }
+
+    // register a ticket for the prize tier of its match count
+    // only tickets bought before the draw can register, until the registration deadline
+    pub fn register_match(ctx: Context<RegisterMatch>) -> Result<()> {
+        let draw_record = &mut ctx.accounts.draw_record;
+
+        if get_current_time() > draw_record.registration_deadline {
+            return Err(ErrorCode::RegistrationClosed.into());
+        }
+
+        // ticket must have been bought before the draw
+        if ctx.accounts.ticket.draw_id > draw_record.draw_id {
+            return Err(ErrorCode::TicketNotInDraw.into());
+        }
+
+        let tier = match draw_record.lottery_mode {
+            LotteryMode::Numbers => {
+                // match count is checked against the stored winning numbers
+                let matches =
+                    count_matches(&ctx.accounts.ticket.numbers, &draw_record.winning_numbers);
+                match_tier(matches, draw_record.number_domain.pick_count)
+                    .ok_or(error!(ErrorCode::NoPrizeMatch))?
+            }
+            LotteryMode::Ticket => {
+                // only the drawn ticket wins, in the top tier
+                if ctx.accounts.ticket.key() != draw_record.winning_ticket {
+                    return Err(ErrorCode::NoPrizeMatch.into());
+                }
+                (PRIZE_TIER_COUNT - 1) as u8
+            }
+        };
+        draw_record.tier_winners[tier as usize] = draw_record.tier_winners[tier as usize]
+            .checked_add(1)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // tier prize is shared by the time each ticket was held during the draw period
+        let weight = ticket_weight(
+            ctx.accounts.ticket.purchased_at,
+            draw_record.period_start,
+            draw_record.drawn_at,
+        );
+        draw_record.tier_weights[tier as usize] = draw_record.tier_weights[tier as usize]
+            .checked_add(weight)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // ticket claim PDA can only be created once per ticket and draw
+        let ticket_claim = &mut ctx.accounts.ticket_claim;
+        ticket_claim.draw_record = draw_record.key();
+        ticket_claim.ticket = ctx.accounts.ticket.key();
+        ticket_claim.owner = ctx.accounts.user.key();
+        ticket_claim.tier = tier;
+        ticket_claim.weight = weight;
+
+        emit!(MatchRegistered {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: draw_record.draw_id,
+            ticket: ctx.accounts.ticket.key(),
+            owner: ctx.accounts.user.key(),
+            tier,
+            weight,
+        });
+        Ok(())
+    }
+
+    // winner withdraws their share of a prize tier once registration has closed
+    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+        let now = get_current_time();
+
+        // share depends on the weights of the winners in the tier
+        if now <= ctx.accounts.draw_record.registration_deadline {
+            return Err(ErrorCode::RegistrationOpen.into());
+        }
+
+        // unclaimed prize rolls back into the pool
+        if now > ctx.accounts.draw_record.claim_deadline {
+            return Err(ErrorCode::PrizeClaimExpired.into());
+        }
+
+        // share of what is left of the tier prize for the ticket weight out of the weights left
+        // the last ticket claimed gets the rounding so the tier pays out in full
+        let tier = ctx.accounts.ticket_claim.tier as usize;
+        let draw_record = &mut ctx.accounts.draw_record;
+        let amount = weighted_share(
+            draw_record.tier_prizes[tier]
+                .checked_sub(draw_record.tier_claimed[tier])
+                .ok_or(error!(ErrorCode::MathOverflow))?,
+            ctx.accounts.ticket_claim.weight,
+            draw_record.tier_weights[tier]
+                .checked_sub(draw_record.tier_claimed_weights[tier])
+                .ok_or(error!(ErrorCode::MathOverflow))?,
+        )?;
+        draw_record.tier_claimed[tier] = draw_record.tier_claimed[tier]
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        draw_record.tier_claimed_weights[tier] = draw_record.tier_claimed_weights[tier]
+            .checked_add(ctx.accounts.ticket_claim.weight)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        ctx.accounts.draw_record.claimed = ctx
+            .accounts
+            .draw_record
+            .claimed
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        ctx.accounts.vault_manager.unclaimed_prizes = ctx
+            .accounts
+            .vault_manager
+            .unclaimed_prizes
+            .checked_sub(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        emit!(WinnerPaid {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            draw_id: ctx.accounts.draw_record.draw_id,
+            ticket: ctx.accounts.ticket_claim.ticket,
+            winner: ctx.accounts.winner.key(),
+            tier: ctx.accounts.ticket_claim.tier,
+            amount,
+        });
+
+        // tier prize too small to share, ticket claim is still closed
+        if amount == 0 {
+            return Ok(());
+        }
+
+        // transfer prize amount to winner
+        let transfer_accounts = token::Transfer {
+            from: ctx.accounts.deposit_vault.clone().to_account_info(),
+            to: ctx.accounts.winner_deposit_ata.clone().to_account_info(),
+            authority: ctx.accounts.vault_manager.clone().to_account_info(),
+        };
+
+        // ticket claim PDA is closed to the winner
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.clone().to_account_info(),
+                transfer_accounts,
+                &[&[
+                    ctx.accounts.deposit_mint.clone().key().as_ref(),
+                    ctx.accounts.yield_mint.clone().key().as_ref(),
+                    ctx.accounts.deposit_vault.clone().key().as_ref(),
+                    ctx.accounts.yield_vault.clone().key().as_ref(),
+                    &[*ctx.bumps.get("vault_manager").unwrap()],
+                ]],
+            ),
+            amount,
+        )
+    }
+
+    // release prizes left unclaimed after the claim deadline back into the pool
+    // anyone can call, the draw record is kept as history
+    pub fn expire_draw_record(ctx: Context<ExpireDrawRecord>) -> Result<()> {
+        if get_current_time() <= ctx.accounts.draw_record.claim_deadline {
+            return Err(ErrorCode::PrizeClaimNotExpired.into());
+        }
+
+        // prizes can only be released once
+        if ctx.accounts.draw_record.expired {
+            return Err(ErrorCode::PrizeClaimExpired.into());
+        }
+        ctx.accounts.draw_record.expired = true;
+
+        // tokens stay in the deposit vault and become part of the next prize
+        let released_amount = ctx
+            .accounts
+            .draw_record
+            .reserved
+            .checked_sub(ctx.accounts.draw_record.claimed)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        ctx.accounts.vault_manager.unclaimed_prizes = ctx
+            .accounts
+            .vault_manager
+            .unclaimed_prizes
+            .checked_sub(released_amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // released prizes are counted in the next jackpot
+        ctx.accounts.vault_manager.rolled_over = ctx
+            .accounts
+            .vault_manager
+            .rolled_over
+            .checked_add(released_amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // no ticket registered a match in this draw
+        // rollovers are counted by dispense, draw records can expire in any order
+        if ctx
+            .accounts
+            .draw_record
+            .tier_winners
+            .iter()
+            .all(|winners| *winners == 0)
+        {
+            emit!(NoWinner {
+                vault_manager: ctx.accounts.vault_manager.key(),
+                draw_id: ctx.accounts.draw_record.draw_id,
+                released_amount,
+            });
+        }
+
+        if released_amount > 0 {
+            emit!(JackpotRolledOver {
+                vault_manager: ctx.accounts.vault_manager.key(),
+                draw_id: ctx.accounts.draw_record.draw_id,
+                amount: released_amount,
+                rolled_over: ctx.accounts.vault_manager.rolled_over,
+                rollover_count: ctx.accounts.vault_manager.rollover_count,
+            });
+        }
+        Ok(())
+    }
+
+    // convert deposit_mint tokens into yield_mint tokens
+    // call with a crank, expected_amount_out is the crank's quote of the swap output
+    pub fn stake(ctx: Context<Stake>, expected_amount_out: u64) -> Result<()> {
+        // no swaps while paused
+        if ctx.accounts.vault_manager.paused {
+            return Err(error!(ErrorCode::Paused));
+        }
+
+        // reserve and unclaimed prizes stay in the deposit vault
+        let reserved = ctx
+            .accounts
+            .vault_manager
+            .deposit_token_reserve
+            .checked_add(ctx.accounts.vault_manager.unclaimed_prizes)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // if less than n tokens, do not stake
+        // wait for more tickets to be purchased
+        if ctx.accounts.deposit_vault.amount < reserved {
+            return Err(error!(ErrorCode::NotEnoughTokens));
+        };
+
+        // subtract reserve and unclaimed prizes from amount to stake
+        let amount_in = ctx.accounts.deposit_vault.amount - reserved;
+
+        // bound the swap output by the pool price
+        let yield_source = ctx.accounts.swap_pool.yield_source(
+            &ctx.accounts.vault_manager,
+            &ctx.accounts.deposit_vault,
+            &ctx.accounts.yield_vault,
+            &ctx.accounts.token_program,
+        )?;
+        let pool_amount_out = yield_source.deposit_quote(amount_in)?;
+        check_quote(
+            pool_amount_out,
+            ctx.accounts.vault_manager.slippage_bps,
+            expected_amount_out,
+        )?;
+        let minimum_amount_out =
+            minimum_amount_out(pool_amount_out, ctx.accounts.vault_manager.slippage_bps)?;
+
+        // swap tokens from deposit_vault to yield_vault
+        let yield_vault_amount = ctx.accounts.yield_vault.amount;
+        yield_source.deposit(
+            amount_in,
+            minimum_amount_out,
+            &[&[
+                ctx.accounts.deposit_mint.clone().key().as_ref(),
+                ctx.accounts.yield_mint.clone().key().as_ref(),
+                ctx.accounts.deposit_vault.clone().key().as_ref(),
+                ctx.accounts.yield_vault.clone().key().as_ref(),
+                &[*ctx.bumps.get("vault_manager").unwrap()],
+            ]],
+        )?;
+
+        // reload account to update yield_vault amount
+        ctx.accounts.yield_vault.reload()?;
+        let amount_out = ctx
+            .accounts
+            .yield_vault
+            .amount
+            .saturating_sub(yield_vault_amount);
+
+        emit!(SwapExecuted {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            source_vault: ctx.accounts.deposit_vault.key(),
+            destination_vault: ctx.accounts.yield_vault.key(),
+            amount_in,
+            amount_out,
+        });
+        emit!(Staked {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            amount_in,
+            amount_out,
+        });
+        Ok(())
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        draw_duration: Option<u64>,
+        ticket_price: Option<u64>,
+        deposit_token_reserve: Option<u64>,
+        reveal_duration: Option<u64>,
+        registration_duration: Option<u64>,
+        claim_duration: Option<u64>,
+        prize_tiers: Option<[u16; PRIZE_TIER_COUNT]>,
+        fee_bps: Option<u16>,
+        slippage_bps: Option<u16>,
+        min_holding_period: Option<u64>,
+        max_rollovers: Option<u64>,
+        number_domain: Option<NumberDomain>,
+        fulfill_duration: Option<u64>,
+        commit_bond: Option<u64>,
+    ) -> Result<()> {
+        let vault_mgr = &mut ctx.accounts.vault_manager;
+
+        // outstanding tickets are redeemed at the price they were bought for
+        // and were picked from the current domain
+        if (ticket_price.is_some() || number_domain.is_some()) && ctx.accounts.tickets.supply > 0 {
+            return Err(error!(ErrorCode::TicketsOutstanding));
+        }
+
+        if let Some(draw_duration) = draw_duration {
+            vault_mgr.draw_duration = draw_duration;
+        }
+        if let Some(ticket_price) = ticket_price {
+            vault_mgr.ticket_price = ticket_price;
+        }
+        if let Some(deposit_token_reserve) = deposit_token_reserve {
+            vault_mgr.deposit_token_reserve = deposit_token_reserve;
+        }
+        if let Some(reveal_duration) = reveal_duration {
+            vault_mgr.reveal_duration = reveal_duration;
+        }
+        if let Some(registration_duration) = registration_duration {
+            vault_mgr.registration_duration = registration_duration;
+        }
+        if let Some(claim_duration) = claim_duration {
+            vault_mgr.claim_duration = claim_duration;
+        }
+        if let Some(prize_tiers) = prize_tiers {
+            vault_mgr.prize_tiers = prize_tiers;
+        }
+        if let Some(fee_bps) = fee_bps {
+            vault_mgr.fee_bps = fee_bps;
+        }
+        if let Some(slippage_bps) = slippage_bps {
+            vault_mgr.slippage_bps = slippage_bps;
+        }
+        if let Some(min_holding_period) = min_holding_period {
+            vault_mgr.min_holding_period = min_holding_period;
+        }
+        if let Some(max_rollovers) = max_rollovers {
+            vault_mgr.max_rollovers = max_rollovers;
+        }
+        if let Some(number_domain) = number_domain {
+            vault_mgr.number_domain = number_domain;
+        }
+        if let Some(fulfill_duration) = fulfill_duration {
+            vault_mgr.fulfill_duration = fulfill_duration;
+        }
+        if let Some(commit_bond) = commit_bond {
+            vault_mgr.commit_bond = commit_bond;
+        }
+        validate_config(vault_mgr)?;
+
+        emit!(ConfigUpdated {
+            vault_manager: vault_mgr.key(),
+            draw_duration: vault_mgr.draw_duration,
+            ticket_price: vault_mgr.ticket_price,
+            deposit_token_reserve: vault_mgr.deposit_token_reserve,
+            reveal_duration: vault_mgr.reveal_duration,
+            registration_duration: vault_mgr.registration_duration,
+            claim_duration: vault_mgr.claim_duration,
+            prize_tiers: vault_mgr.prize_tiers,
+            fee_bps: vault_mgr.fee_bps,
+            slippage_bps: vault_mgr.slippage_bps,
+            min_holding_period: vault_mgr.min_holding_period,
+            max_rollovers: vault_mgr.max_rollovers,
+            number_domain: vault_mgr.number_domain,
+            fulfill_duration: vault_mgr.fulfill_duration,
+            commit_bond: vault_mgr.commit_bond,
+        });
+        Ok(())
+    }
+
+    pub fn transfer_authority(ctx: Context<Admin>, new_authority: Pubkey) -> Result<()> {
+        // new authority has to accept before it takes over
+        ctx.accounts.vault_manager.pending_authority = new_authority;
+
+        emit!(AuthorityTransferProposed {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            authority: ctx.accounts.vault_manager.authority,
+            pending_authority: new_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let vault_mgr = &mut ctx.accounts.vault_manager;
+        let previous_authority = vault_mgr.authority;
+        vault_mgr.authority = vault_mgr.pending_authority;
+        vault_mgr.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferred {
+            vault_manager: vault_mgr.key(),
+            previous_authority,
+            new_authority: vault_mgr.authority,
+        });
+        Ok(())
+    }
+
+    pub fn set_yield_source(ctx: Context<SetYieldSource>) -> Result<()> {
+        // pool must trade between the vault mints through the passed in accounts
+        yield_source::check_spl_token_swap_pool(
+            &ctx.accounts.token_swap_program,
+            &ctx.accounts.amm,
+            &ctx.accounts.amm_authority,
+            &ctx.accounts.swap_deposit_vault,
+            &ctx.accounts.swap_yield_vault,
+            &ctx.accounts.pool_mint.to_account_info(),
+            &ctx.accounts.pool_fee.to_account_info(),
+        )?;
+
+        // only these accounts are accepted by stake, redeem and dispense
+        let vault_mgr = &mut ctx.accounts.vault_manager;
+        vault_mgr.token_swap_program = ctx.accounts.token_swap_program.key();
+        vault_mgr.amm = ctx.accounts.amm.key();
+        vault_mgr.amm_authority = ctx.accounts.amm_authority.key();
+        vault_mgr.swap_deposit_vault = ctx.accounts.swap_deposit_vault.key();
+        vault_mgr.swap_yield_vault = ctx.accounts.swap_yield_vault.key();
+        vault_mgr.pool_mint = ctx.accounts.pool_mint.key();
+        vault_mgr.pool_fee = ctx.accounts.pool_fee.key();
+
+        emit!(YieldSourceUpdated {
+            vault_manager: vault_mgr.key(),
+            token_swap_program: vault_mgr.token_swap_program,
+            amm: vault_mgr.amm,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        // the bond of a pending commitment is still owed to the committer
+        let available = ctx
+            .accounts
+            .treasury
+            .amount
+            .checked_sub(ctx.accounts.vault_manager.committed_bond)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        if amount > available {
+            return Err(error!(ErrorCode::NotEnoughTokens));
+        }
+
+        let transfer_accounts = token::Transfer {
+            from: ctx.accounts.treasury.clone().to_account_info(),
+            to: ctx.accounts.destination.clone().to_account_info(),
+            authority: ctx.accounts.vault_manager.clone().to_account_info(),
+        };
+
+        // transfer fees from treasury to the authority's choice of account
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.clone().to_account_info(),
+                transfer_accounts,
+                &[&[
+                    ctx.accounts.deposit_mint.clone().key().as_ref(),
+                    ctx.accounts.yield_mint.clone().key().as_ref(),
+                    ctx.accounts.deposit_vault.clone().key().as_ref(),
+                    ctx.accounts.yield_vault.clone().key().as_ref(),
+                    &[*ctx.bumps.get("vault_manager").unwrap()],
+                ]],
+            ),
+            amount,
         )?;
 
-        // swap tokens
-        anchor_lang::solana_program::program::invoke(&ix, &accounts).map_err(|e| e.into())
+        ctx.accounts.vault_manager.total_fees_withdrawn = ctx
+            .accounts
+            .vault_manager
+            .total_fees_withdrawn
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        emit!(TreasuryWithdrawn {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    pub fn set_guardian(ctx: Context<Admin>, guardian: Pubkey) -> Result<()> {
+        // guardian can pause the lottery but not unpause it
+        let previous_guardian = ctx.accounts.vault_manager.guardian;
+        ctx.accounts.vault_manager.guardian = guardian;
+
+        emit!(GuardianUpdated {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            previous_guardian,
+            guardian,
+        });
+        Ok(())
+    }
+
+    pub fn set_commit_authority(ctx: Context<Admin>, commit_authority: Pubkey) -> Result<()> {
+        // takes effect from the next commitment, a pending one can still be revealed
+        let previous_commit_authority = ctx.accounts.vault_manager.commit_authority;
+        ctx.accounts.vault_manager.commit_authority = commit_authority;
+
+        emit!(CommitAuthorityUpdated {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            previous_commit_authority,
+            commit_authority,
+        });
+        Ok(())
+    }
+
+    pub fn pause(ctx: Context<Pause>) -> Result<()> {
+        if ctx.accounts.vault_manager.paused {
+            return Err(error!(ErrorCode::Paused));
+        }
+
+        // stops buy, stake and dispense, redeem and prize claims keep working
+        ctx.accounts.vault_manager.paused = true;
+
+        emit!(PauseChanged {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            paused: true,
+            signer: ctx.accounts.signer.key(),
+        });
+        Ok(())
+    }
+
+    pub fn unpause(ctx: Context<Admin>) -> Result<()> {
+        if !ctx.accounts.vault_manager.paused {
+            return Err(error!(ErrorCode::NotPaused));
+        }
+
+        ctx.accounts.vault_manager.paused = false;
+
+        emit!(PauseChanged {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            paused: false,
+            signer: ctx.accounts.authority.key(),
+        });
+        Ok(())
+    }
+
+    // report the pool value against the principal owed to ticket holders
+    // read only, the report is emitted and set as return data
+    pub fn pool_health(ctx: Context<PoolHealth>) -> Result<()> {
+        let deposit_amount = ctx
+            .accounts
+            .deposit_vault
+            .amount
+            .checked_sub(ctx.accounts.vault_manager.unclaimed_prizes)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // nothing can be staked before set_yield_source, there is no position to value
+        let position_value = if ctx.accounts.vault_manager.amm == Pubkey::default() {
+            0
+        } else {
+            ctx.accounts
+                .swap_pool
+                .yield_source(
+                    &ctx.accounts.vault_manager,
+                    &ctx.accounts.deposit_vault,
+                    &ctx.accounts.yield_vault,
+                    &ctx.accounts.token_program,
+                )?
+                .position_value(ctx.accounts.yield_vault.amount)?
+        };
+        let pool_value = deposit_amount
+            .checked_add(position_value)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        let report = PoolHealthReported {
+            vault_manager: ctx.accounts.vault_manager.key(),
+            total_principal: ctx.accounts.vault_manager.total_principal,
+            deposit_amount,
+            position_value,
+            pool_value,
+            insolvent: pool_value < ctx.accounts.vault_manager.total_principal,
+            jackpot: calculate_prize(ctx.accounts.vault_manager.total_principal, pool_value),
+            rolled_over: ctx.accounts.vault_manager.rolled_over,
+            rollover_count: ctx.accounts.vault_manager.rollover_count,
+        };
+        set_return_data(
+            &report
+                .try_to_vec()
+                .map_err(|_| anchor_lang::error::ErrorCode::AccountDidNotSerialize)?,
+        );
+        emit!(report);
+        Ok(())
     }
 }
 
@@ -530,6 +1902,17 @@ pub struct Initialize<'info> {
     )]
     pub tickets: Account<'info, token::Mint>,
 
+    // protocol fees are collected here
+    #[account(init,
+        payer = user,
+        token::mint = deposit_mint,
+        token::authority = vault_manager,
+        seeds = [TREASURY_SEED, vault_manager.key().as_ref()], bump)]
+    pub treasury: Box<Account<'info, token::TokenAccount>>,
+
+    /// CHECK: only stored, the key whose signature over a request fulfills it
+    pub randomness_oracle: AccountInfo<'info>,
+
     #[account(mut)]
     pub user: Signer<'info>,
 
@@ -566,13 +1949,29 @@ pub struct Buy<'info> {
     #[account(mut)]
     pub tickets: Account<'info, token::Mint>,
 
+    // holders of this number combination
+    #[account(init_if_needed,
+        payer = user,
+        seeds = [&canonical_numbers(&numbers), vault_manager.key().as_ref()],
+        bump,
+    )]
+    pub combination: Box<Account<'info, Combination>>,
+
     #[account(init,
         payer = user,
-        seeds = [&numbers, vault_manager.key().as_ref()],
+        seeds = [&canonical_numbers(&numbers), vault_manager.key().as_ref(), &combination.next_index.to_le_bytes()],
         bump,
     )]
     pub ticket: Box<Account<'info, Ticket>>,
 
+    // next ticket registry slot
+    #[account(init,
+        payer = user,
+        seeds = [TICKET_ENTRY_SEED, vault_manager.key().as_ref(), &vault_manager.ticket_count.to_le_bytes()],
+        bump,
+    )]
+    pub ticket_entry: Box<Account<'info, TicketEntry>>,
+
     #[account(init_if_needed,
         payer = user,
         associated_token::mint = tickets,
@@ -591,8 +1990,19 @@ pub struct Buy<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
+// buy accounts are derived from the quick pick numbers, passed as the first argument
 #[derive(Accounts)]
-pub struct Redeem<'info> {
+pub struct BuyQuickPick<'info> {
+    pub buy: Buy<'info>,
+
+    /// CHECK: address checked, read as raw bytes since the sysvar is too large to deserialize
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+}
+
+// combination, ticket and registry entry PDAs of each ticket are passed in remaining_accounts
+#[derive(Accounts)]
+pub struct BuyMany<'info> {
     #[account(mut)]
     pub deposit_mint: Box<Account<'info, token::Mint>>,
 
@@ -618,10 +2028,8 @@ pub struct Redeem<'info> {
     #[account(mut)]
     pub tickets: Account<'info, token::Mint>,
 
-    #[account(mut)]
-    pub ticket: Box<Account<'info, Ticket>>,
-
-    #[account(mut,
+    #[account(init_if_needed,
+        payer = user,
         associated_token::mint = tickets,
         associated_token::authority = user)]
     pub user_tickets_ata: Box<Account<'info, token::TokenAccount>>,
@@ -629,33 +2037,97 @@ pub struct Redeem<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
-    // swap program token accounts
-    #[account(mut)]
-    pub swap_yield_vault: Box<Account<'info, token::TokenAccount>>,
     #[account(mut)]
-    pub swap_deposit_vault: Box<Account<'info, token::TokenAccount>>,
+    pub user_deposit_ata: Account<'info, token::TokenAccount>,
 
-    // LP mint
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, token::Token>,
+    pub associated_token_program: Program<'info, associated_token::AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Redeem<'info> {
     #[account(mut)]
-    pub pool_mint: Account<'info, token::Mint>,
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-    /// CHECK: TODO
-    #[account()]
-    pub amm: AccountInfo<'info>,
+    #[account(mut)]
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(mut, seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut, seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = tickets,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
 
-    /// CHECK: TODO
     #[account(mut)]
-    pub amm_authority: AccountInfo<'info>,
+    pub tickets: Account<'info, token::Mint>,
+
+    // only the owner can redeem a ticket of this lottery
+    #[account(mut,
+        has_one = vault_manager,
+        has_one = deposit_mint,
+        has_one = yield_mint,
+        has_one = tickets,
+        constraint = ticket.owner == user.key() @ ErrorCode::InvalidTicketOwner,
+        seeds = [&ticket.numbers, vault_manager.key().as_ref(), &ticket.index.to_le_bytes()],
+        bump,
+        close = user)]
+    pub ticket: Box<Account<'info, Ticket>>,
+
+    #[account(mut,
+        has_one = vault_manager,
+        seeds = [&ticket.numbers, vault_manager.key().as_ref()],
+        bump)]
+    pub combination: Box<Account<'info, Combination>>,
+
+    // registry slot of the redeemed ticket, the last slot is moved into it
+    // when the redeemed ticket is in the last slot this is last_ticket_entry and is only closed
+    /// CHECK: PDA of the ticket's registry slot, loaded by redeem unless it is the last slot
+    #[account(mut,
+        seeds = [TICKET_ENTRY_SEED, vault_manager.key().as_ref(), &ticket.registry_index.to_le_bytes()],
+        bump)]
+    pub ticket_entry: UncheckedAccount<'info>,
+
+    // last registry slot, closed
+    #[account(mut,
+        has_one = vault_manager,
+        seeds = [TICKET_ENTRY_SEED, vault_manager.key().as_ref(), &vault_manager.ticket_count.saturating_sub(1).to_le_bytes()],
+        bump,
+        close = user)]
+    pub last_ticket_entry: Box<Account<'info, TicketEntry>>,
+
+    // ticket in the last registry slot, takes the slot of the redeemed ticket
+    // when the redeemed ticket is in the last slot this is ticket and is only closed
+    /// CHECK: must be the ticket of last_ticket_entry, loaded by redeem unless it is the redeemed ticket
+    #[account(mut,
+        constraint = last_ticket.key() == last_ticket_entry.ticket @ ErrorCode::InvalidTicketEntry)]
+    pub last_ticket: UncheckedAccount<'info>,
+
+    #[account(mut,
+        associated_token::mint = tickets,
+        associated_token::authority = user)]
+    pub user_tickets_ata: Box<Account<'info, token::TokenAccount>>,
 
-    // fees go here
     #[account(mut)]
-    pub pool_fee: Account<'info, token::TokenAccount>,
+    pub user: Signer<'info>,
+
+    // pool the vault tokens are swapped through
+    pub swap_pool: SwapPool<'info>,
 
     #[account(mut)]
     pub user_deposit_ata: Account<'info, token::TokenAccount>,
 
-    /// CHECK: TODO
-    pub token_swap_program: AccountInfo<'info>,
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, token::Token>,
     pub rent: Sysvar<'info, Rent>,
@@ -691,24 +2163,25 @@ pub struct Draw<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
+    /// CHECK: address checked, read as raw bytes since the sysvar is too large to deserialize
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, token::Token>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(numbers: [u8; 6])]
-pub struct Dispense<'info> {
-    #[account(mut)]
+pub struct FulfillRandomness<'info> {
     pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-    #[account(mut)]
     pub yield_mint: Box<Account<'info, token::Mint>>,
 
-    #[account(mut, seeds = [deposit_mint.key().as_ref()], bump)]
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
     pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
 
-    #[account(mut, seeds = [yield_mint.key().as_ref()], bump)]
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
     pub yield_vault: Box<Account<'info, token::TokenAccount>>,
 
     #[account(mut,
@@ -716,63 +2189,86 @@ pub struct Dispense<'info> {
         has_one = deposit_mint,
         has_one = yield_vault,
         has_one = yield_mint,
-        has_one = tickets,
         seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
         bump)]
     pub vault_manager: Box<Account<'info, VaultManager>>,
 
-    #[account(mut)]
-    pub tickets: Account<'info, token::Mint>,
+    /// CHECK: instructions sysvar, holds the Ed25519 program instruction with the oracle signature
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+}
 
-    #[account(init_if_needed, payer = user, seeds = [&numbers, vault_manager.key().as_ref()], bump)]
-    pub ticket: Box<Account<'info, Ticket>>,
+#[derive(Accounts)]
+pub struct CommitOracleNonce<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-    // swap program token accounts
-    #[account(mut)]
-    pub swap_yield_vault: Box<Account<'info, token::TokenAccount>>,
-    #[account(mut)]
-    pub swap_deposit_vault: Box<Account<'info, token::TokenAccount>>,
+    pub yield_mint: Box<Account<'info, token::Mint>>,
 
-    // LP mint
-    #[account(mut)]
-    pub pool_mint: Account<'info, token::Mint>,
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
 
-    /// CHECK: TODO
-    #[account()]
-    pub amm: AccountInfo<'info>,
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
 
-    /// CHECK: TODO
-    #[account(mut)]
-    pub amm_authority: AccountInfo<'info>,
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        constraint = vault_manager.randomness_oracle == oracle.key() @ ErrorCode::InvalidOracle,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
 
-    // fees go here
-    #[account(mut)]
-    pub pool_fee: Account<'info, token::TokenAccount>,
+    pub oracle: Signer<'info>,
+}
 
-    #[account(mut)]
+#[derive(Accounts)]
+pub struct Commit<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = treasury,
+        constraint = vault_manager.commit_authority == user.key() @ ErrorCode::InvalidCommitter,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    #[account(mut, seeds = [TREASURY_SEED, vault_manager.key().as_ref()], bump)]
+    pub treasury: Box<Account<'info, token::TokenAccount>>,
+
+    // commit authority
     pub user: Signer<'info>,
 
-    #[account(mut)]
-    pub user_deposit_ata: Account<'info, token::TokenAccount>,
+    // pays the bond
+    #[account(mut, token::mint = deposit_mint)]
+    pub user_deposit_ata: Box<Account<'info, token::TokenAccount>>,
 
-    /// CHECK: TODO
-    pub token_swap_program: AccountInfo<'info>,
-    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, token::Token>,
 }
 
 #[derive(Accounts)]
-pub struct Stake<'info> {
-    // swap mints
-    #[account()]
-    pub yield_mint: Account<'info, token::Mint>,
-    #[account()]
-    pub deposit_mint: Account<'info, token::Mint>,
+pub struct Reveal<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-    #[account(mut, seeds = [deposit_mint.key().as_ref()], bump)]
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
     pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
 
-    #[account(mut, seeds = [yield_mint.key().as_ref()], bump)]
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
     pub yield_vault: Box<Account<'info, token::TokenAccount>>,
 
     #[account(mut,
@@ -780,113 +2276,2212 @@ pub struct Stake<'info> {
         has_one = deposit_mint,
         has_one = yield_vault,
         has_one = yield_mint,
+        has_one = treasury,
         seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
         bump)]
     pub vault_manager: Box<Account<'info, VaultManager>>,
 
-    // swap program token accounts
-    #[account(mut)]
-    pub swap_yield_vault: Box<Account<'info, token::TokenAccount>>,
-    #[account(mut)]
-    pub swap_deposit_vault: Box<Account<'info, token::TokenAccount>>,
-
-    // LP mint
-    #[account(mut)]
-    pub pool_mint: Account<'info, token::Mint>,
-
-    /// CHECK: TODO
-    #[account()]
-    pub amm: AccountInfo<'info>,
+    #[account(mut, seeds = [TREASURY_SEED, vault_manager.key().as_ref()], bump)]
+    pub treasury: Box<Account<'info, token::TokenAccount>>,
 
-    /// CHECK: TODO
-    #[account(mut)]
-    pub amm_authority: AccountInfo<'info>,
+    // only the committer can reveal
+    #[account(constraint = user.key() == vault_manager.committer @ ErrorCode::InvalidCommitter)]
+    pub user: Signer<'info>,
 
-    // fees go here
-    #[account(mut)]
-    pub pool_fee: Account<'info, token::TokenAccount>,
+    // receives the bond back
+    #[account(mut, token::mint = deposit_mint)]
+    pub user_deposit_ata: Box<Account<'info, token::TokenAccount>>,
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+    /// CHECK: address checked, read as raw bytes since the sysvar is too large to deserialize
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
 
-    /// CHECK: TODO
-    pub token_swap_program: AccountInfo<'info>,
     pub token_program: Program<'info, token::Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
-#[account]
-#[derive(Default)]
-pub struct VaultManager {
-    pub deposit_mint: Pubkey,
-    pub deposit_vault: Pubkey,
-    pub yield_mint: Pubkey,
-    pub yield_vault: Pubkey,
-    pub tickets: Pubkey,
-    pub cutoff_time: u64,   // in seconds, cutoff time for next draw
-    pub draw_duration: u64, // in seconds, duration until next draw time
-    pub ticket_price: u64,
-    pub winning_numbers: [u8; 6],
-    pub previous_winning_numbers: [u8; 6],
-    pub locked: bool, // when draw is called, lock the program until dispense is called
-    pub deposit_token_reserve: u64, // amount of tokens to keep in deposit_vault at all times
-}
+#[derive(Accounts)]
+pub struct ExpireDraw<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-#[account]
-#[derive(Default)]
-pub struct Ticket {
-    pub deposit_mint: Pubkey,
-    pub yield_mint: Pubkey,
-    pub vault: Pubkey,
-    pub tickets: Pubkey,
-    pub owner: Pubkey,
-    pub numbers: [u8; 6],
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    pub user: Signer<'info>,
 }
 
-#[error_code]
-pub enum ErrorCode {
-    #[msg("TimeRemaining")]
-    TimeRemaining,
+#[derive(Accounts)]
+pub struct Dispense<'info> {
+    #[account(mut)]
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-    #[msg("Must call Dispense")]
-    CallDispense,
+    #[account(mut)]
+    pub yield_mint: Box<Account<'info, token::Mint>>,
 
-    #[msg("Invalid Numbers")]
-    InvalidNumbers,
+    #[account(mut, seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
 
-    #[msg("No Tickets Purchased")]
-    NoTicketsPurchased,
+    #[account(mut, seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
 
-    #[msg("Must Pass in Winning PDA to Dispense")]
-    PassInWinningPDA,
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = tickets,
+        has_one = treasury,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
 
-    #[msg("Not enough tokens for swap")]
-    NotEnoughTokens,
+    #[account(mut)]
+    pub tickets: Account<'info, token::Mint>,
 
-    #[msg("Invalid ticket price")]
-    InvalidTicketPrice,
+    // results of the draw being dispensed
+    #[account(init,
+        payer = user,
+        seeds = [DRAW_RECORD_SEED, vault_manager.key().as_ref(), &vault_manager.draw_id.to_le_bytes()],
+        bump)]
+    pub draw_record: Box<Account<'info, DrawRecord>>,
 
-    #[msg("Invalid draw duration")]
-    InvalidDrawDuration,
-}
+    #[account(mut, seeds = [TREASURY_SEED, vault_manager.key().as_ref()], bump)]
+    pub treasury: Box<Account<'info, token::TokenAccount>>,
 
-fn get_current_time() -> u64 {
-    return Clock::get().unwrap().unix_timestamp as u64;
+    // pool the vault tokens are swapped through
+    pub swap_pool: SwapPool<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, token::Token>,
 }
 
-// calculate prize to send to winner
-// this function is expected to be called after swapping all yield tokens back to deposit tokens
-fn calculate_prize(tickets_supply: u64, ticket_price: u64, deposit_vault_amount: u64) -> u64 {
-    // deposit_vault amount - (tickets_supply * ticket_price) = prize amount
-    let deposit_amount = tickets_supply * ticket_price;
-    let mut prize_amount = deposit_vault_amount - deposit_amount;
+#[derive(Accounts)]
+pub struct RegisterMatch<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
 
-    // not enough gains for a prize
-    // set amount to 0, so we can unlock the vault and continue the lottery
-    if prize_amount <= 0 {
-        prize_amount = 0;
-    }
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
 
-    return prize_amount;
+    #[account(
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    #[account(mut,
+        has_one = vault_manager,
+        seeds = [DRAW_RECORD_SEED, vault_manager.key().as_ref(), &draw_record.draw_id.to_le_bytes()],
+        bump)]
+    pub draw_record: Box<Account<'info, DrawRecord>>,
+
+    // only the owner can register a ticket of this lottery
+    #[account(
+        has_one = vault_manager,
+        constraint = ticket.owner == user.key() @ ErrorCode::InvalidTicketOwner,
+        seeds = [&ticket.numbers, vault_manager.key().as_ref(), &ticket.index.to_le_bytes()],
+        bump)]
+    pub ticket: Box<Account<'info, Ticket>>,
+
+    #[account(init,
+        payer = user,
+        seeds = [TICKET_CLAIM_SEED, draw_record.key().as_ref(), ticket.key().as_ref()],
+        bump)]
+    pub ticket_claim: Box<Account<'info, TicketClaim>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPrize<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(mut, seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    #[account(mut,
+        has_one = vault_manager,
+        seeds = [DRAW_RECORD_SEED, vault_manager.key().as_ref(), &draw_record.draw_id.to_le_bytes()],
+        bump)]
+    pub draw_record: Box<Account<'info, DrawRecord>>,
+
+    // only the owner who registered the ticket can claim
+    #[account(mut,
+        has_one = draw_record,
+        constraint = ticket_claim.owner == winner.key() @ ErrorCode::NotPrizeWinner,
+        seeds = [TICKET_CLAIM_SEED, draw_record.key().as_ref(), ticket_claim.ticket.as_ref()],
+        bump,
+        close = winner)]
+    pub ticket_claim: Box<Account<'info, TicketClaim>>,
+
+    #[account(mut,
+        associated_token::mint = deposit_mint,
+        associated_token::authority = winner)]
+    pub winner_deposit_ata: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut)]
+    pub winner: Signer<'info>,
+
+    pub token_program: Program<'info, token::Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireDrawRecord<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    #[account(mut,
+        has_one = vault_manager,
+        seeds = [DRAW_RECORD_SEED, vault_manager.key().as_ref(), &draw_record.draw_id.to_le_bytes()],
+        bump)]
+    pub draw_record: Box<Account<'info, DrawRecord>>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    // swap mints
+    #[account()]
+    pub yield_mint: Account<'info, token::Mint>,
+    #[account()]
+    pub deposit_mint: Account<'info, token::Mint>,
+
+    #[account(mut, seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut, seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    // pool the vault tokens are swapped through
+    pub swap_pool: SwapPool<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub token_program: Program<'info, token::Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = tickets,
+        has_one = authority @ ErrorCode::Unauthorized,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    pub tickets: Account<'info, token::Mint>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Admin<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = authority @ ErrorCode::Unauthorized,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetYieldSource<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = authority @ ErrorCode::Unauthorized,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    // pool token accounts
+    #[account(constraint = swap_deposit_vault.mint == deposit_mint.key() @ ErrorCode::InvalidYieldSource)]
+    pub swap_deposit_vault: Box<Account<'info, token::TokenAccount>>,
+    #[account(constraint = swap_yield_vault.mint == yield_mint.key() @ ErrorCode::InvalidYieldSource)]
+    pub swap_yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    // LP mint
+    pub pool_mint: Box<Account<'info, token::Mint>>,
+
+    // fees go here
+    pub pool_fee: Box<Account<'info, token::TokenAccount>>,
+
+    /// CHECK: swap state is unpacked and checked against the other pool accounts
+    #[account(owner = token_swap_program.key() @ ErrorCode::InvalidYieldSource)]
+    pub amm: AccountInfo<'info>,
+
+    /// CHECK: must be derived from amm
+    pub amm_authority: AccountInfo<'info>,
+
+    /// CHECK: owner of amm, only the spl-token-swap program is accepted
+    #[account(executable, address = spl_token_swap::id() @ ErrorCode::InvalidYieldSource)]
+    pub token_swap_program: AccountInfo<'info>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PoolHealth<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    // pool the position is valued with
+    pub swap_pool: SwapPoolView<'info>,
+
+    pub token_program: Program<'info, token::Token>,
+}
+
+// spl-token-swap pool the vault tokens are swapped through
+// must be the pool stored on vault_manager by set_yield_source, the accounts are only
+// checked and deserialized by yield_source so instructions that do not swap accept any
+#[derive(Accounts)]
+pub struct SwapPool<'info> {
+    /// CHECK: pool token account, checked by yield_source
+    #[account(mut)]
+    pub swap_yield_vault: AccountInfo<'info>,
+    /// CHECK: pool token account, checked by yield_source
+    #[account(mut)]
+    pub swap_deposit_vault: AccountInfo<'info>,
+
+    /// CHECK: LP mint, checked by yield_source
+    #[account(mut)]
+    pub pool_mint: AccountInfo<'info>,
+
+    /// CHECK: must be the pool stored on vault_manager
+    pub amm: AccountInfo<'info>,
+
+    /// CHECK: must be the pool authority stored on vault_manager
+    pub amm_authority: AccountInfo<'info>,
+
+    /// CHECK: fees go here, checked by yield_source
+    #[account(mut)]
+    pub pool_fee: AccountInfo<'info>,
+
+    /// CHECK: must be the swap program stored on vault_manager
+    pub token_swap_program: AccountInfo<'info>,
+}
+
+impl<'info> SwapPool<'info> {
+    // yield source the vault tokens are swapped through
+    // fails unless these are the pool accounts stored on vault_manager
+    fn yield_source(
+        &self,
+        vault_manager: &Account<'info, VaultManager>,
+        deposit_vault: &Account<'info, token::TokenAccount>,
+        yield_vault: &Account<'info, token::TokenAccount>,
+        token_program: &Program<'info, token::Token>,
+    ) -> Result<SplTokenSwap<'info>> {
+        SwapPoolView {
+            swap_yield_vault: self.swap_yield_vault.clone(),
+            swap_deposit_vault: self.swap_deposit_vault.clone(),
+            pool_mint: self.pool_mint.clone(),
+            amm: self.amm.clone(),
+            amm_authority: self.amm_authority.clone(),
+            pool_fee: self.pool_fee.clone(),
+            token_swap_program: self.token_swap_program.clone(),
+        }
+        .yield_source(vault_manager, deposit_vault, yield_vault, token_program)
+    }
+}
+
+// SwapPool accounts for instructions that only quote the pool, none are writable
+#[derive(Accounts)]
+pub struct SwapPoolView<'info> {
+    /// CHECK: pool token account, checked by yield_source
+    pub swap_yield_vault: AccountInfo<'info>,
+    /// CHECK: pool token account, checked by yield_source
+    pub swap_deposit_vault: AccountInfo<'info>,
+
+    /// CHECK: LP mint, checked by yield_source
+    pub pool_mint: AccountInfo<'info>,
+
+    /// CHECK: must be the pool stored on vault_manager
+    pub amm: AccountInfo<'info>,
+
+    /// CHECK: must be the pool authority stored on vault_manager
+    pub amm_authority: AccountInfo<'info>,
+
+    /// CHECK: fees go here, checked by yield_source
+    pub pool_fee: AccountInfo<'info>,
+
+    /// CHECK: must be the swap program stored on vault_manager
+    pub token_swap_program: AccountInfo<'info>,
+}
+
+impl<'info> SwapPoolView<'info> {
+    // yield source the vault tokens are swapped through
+    // fails unless these are the pool accounts stored on vault_manager
+    fn yield_source(
+        &self,
+        vault_manager: &Account<'info, VaultManager>,
+        deposit_vault: &Account<'info, token::TokenAccount>,
+        yield_vault: &Account<'info, token::TokenAccount>,
+        token_program: &Program<'info, token::Token>,
+    ) -> Result<SplTokenSwap<'info>> {
+        // unset until set_yield_source, the default key is never the swap program
+        if self.token_swap_program.key() != vault_manager.token_swap_program
+            || self.token_swap_program.key() != spl_token_swap::id()
+            || self.amm.key() != vault_manager.amm
+            || self.amm_authority.key() != vault_manager.amm_authority
+            || self.swap_deposit_vault.key() != vault_manager.swap_deposit_vault
+            || self.swap_yield_vault.key() != vault_manager.swap_yield_vault
+            || self.pool_mint.key() != vault_manager.pool_mint
+            || self.pool_fee.key() != vault_manager.pool_fee
+        {
+            return Err(error!(ErrorCode::InvalidYieldSource));
+        }
+
+        Ok(SplTokenSwap {
+            token_swap_program: self.token_swap_program.clone(),
+            token_program: token_program.to_account_info(),
+            amm: self.amm.clone(),
+            amm_authority: self.amm_authority.clone(),
+            pool_mint: self.pool_mint.clone(),
+            pool_fee: self.pool_fee.clone(),
+            swap_deposit_vault: Account::try_from(&self.swap_deposit_vault)?,
+            swap_yield_vault: Account::try_from(&self.swap_yield_vault)?,
+            deposit_vault: deposit_vault.to_account_info(),
+            yield_vault: yield_vault.to_account_info(),
+            vault_manager: vault_manager.to_account_info(),
+            swap_state: spl_token_swap::state::SwapVersion::unpack(&self.amm.try_borrow_data()?)?,
+        })
+    }
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        has_one = treasury,
+        has_one = authority @ ErrorCode::Unauthorized,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    #[account(mut, seeds = [TREASURY_SEED, vault_manager.key().as_ref()], bump)]
+    pub treasury: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut, token::mint = deposit_mint)]
+    pub destination: Box<Account<'info, token::TokenAccount>>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, token::Token>,
+}
+
+#[derive(Accounts)]
+pub struct Pause<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        constraint = vault_manager.authority == signer.key() || vault_manager.guardian == signer.key() @ ErrorCode::Unauthorized,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    // authority or guardian
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub deposit_mint: Box<Account<'info, token::Mint>>,
+
+    pub yield_mint: Box<Account<'info, token::Mint>>,
+
+    #[account(seeds = [deposit_mint.key().as_ref()], bump)]
+    pub deposit_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(seeds = [yield_mint.key().as_ref()], bump)]
+    pub yield_vault: Box<Account<'info, token::TokenAccount>>,
+
+    #[account(mut,
+        has_one = deposit_vault,
+        has_one = deposit_mint,
+        has_one = yield_vault,
+        has_one = yield_mint,
+        constraint = vault_manager.pending_authority == pending_authority.key() @ ErrorCode::InvalidPendingAuthority,
+        seeds = [deposit_mint.key().as_ref(), yield_mint.key().as_ref(), deposit_vault.key().as_ref(), yield_vault.key().as_ref()],
+        bump)]
+    pub vault_manager: Box<Account<'info, VaultManager>>,
+
+    pub pending_authority: Signer<'info>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct VaultManager {
+    pub deposit_mint: Pubkey,
+    pub deposit_vault: Pubkey,
+    pub yield_mint: Pubkey,
+    pub yield_vault: Pubkey,
+    pub tickets: Pubkey,
+    pub cutoff_time: u64,   // in seconds, cutoff time for next draw
+    pub draw_duration: u64, // in seconds, duration until next draw time
+    pub ticket_price: u64,
+    pub winning_numbers: [u8; 6],
+    pub locked: bool, // when draw is called, lock the program until dispense is called
+    pub deposit_token_reserve: u64, // amount of tokens to keep in deposit_vault at all times
+    pub randomness_oracle: Pubkey, // ed25519 key whose signature over a request fulfills it
+    pub randomness_request: [u8; 32], // seed of the pending randomness request
+    pub randomness_pending: bool, // set by draw, cleared when the randomness is fulfilled or revealed
+    pub randomness_mode: RandomnessMode,
+    pub reveal_duration: u64, // in seconds, time the committer has to reveal after draw
+    pub commitment: [u8; 32], // hash of the committed secret, commit-reveal mode only
+    pub committer: Pubkey,    // posted the commitment and its bond
+    pub reveal_slot: u64,     // secret is mixed with the hash of this slot
+    pub reveal_deadline: u64, // in seconds, after this the draw can be expired
+    pub draw_id: u64,         // id of the current draw, incremented by dispense
+    pub claim_duration: u64,  // in seconds, time a winner has to claim a prize after registration
+    pub unclaimed_prizes: u64, // deposit tokens reserved for prize claims
+    pub registration_duration: u64, // in seconds, time ticket holders have to register a match after dispense
+    pub prize_tiers: [u16; PRIZE_TIER_COUNT], // share of the prize in bps for 3, 4, 5 and 6 matching numbers
+    pub randomness: [u8; 32], // randomness the winning numbers of the current draw are derived from
+    pub randomness_proof: [u8; 32], // request signed by the oracle or revealed secret of the current draw
+    pub drawn_at: u64,              // in seconds, when draw was called
+    pub fulfilled_at: u64,          // in seconds, when the randomness was fulfilled or revealed
+    pub draw_ticket_supply: u64,    // tickets taking part in the current draw
+    pub authority: Pubkey,          // allowed to update the config
+    pub pending_authority: Pubkey,  // set by transfer_authority, takes over once it accepts
+    pub guardian: Pubkey,           // allowed to pause alongside the authority
+    pub paused: bool, // emergency stop for buy, stake and dispense, redeem stays open during a draw
+    pub fee_bps: u16, // protocol fee taken from each prize
+    pub treasury: Pubkey, // token account collecting the protocol fee
+    pub total_fees_collected: u64, // fees and forfeited commit bonds moved to the treasury
+    pub total_fees_withdrawn: u64, // fees withdrawn from the treasury by the authority
+    pub slippage_bps: u16, // swap output may be this far below the pool price
+    pub token_swap_program: Pubkey, // swap program of the yield source
+    pub amm: Pubkey,  // swap pool of the yield source
+    pub amm_authority: Pubkey,
+    pub swap_deposit_vault: Pubkey, // pool token account of the deposit mint
+    pub swap_yield_vault: Pubkey,   // pool token account of the yield mint
+    pub pool_mint: Pubkey,
+    pub pool_fee: Pubkey,
+    pub total_principal: u64,    // ticket price of every outstanding ticket
+    pub insolvent: bool,         // pool was worth less than total_principal when last valued
+    pub min_holding_period: u64, // in seconds, time a ticket must be held before it can be redeemed
+    pub period_start: u64,       // in seconds, start of the current draw period
+    pub lottery_mode: LotteryMode,
+    pub ticket_count: u64, // registered tickets, registry slots 0..ticket_count are filled
+    pub jackpot: u64,      // prize of the last draw, including rolled over prizes
+    pub rolled_over: u64, // prizes released or left unreserved since the last draw, part of the next prize
+    pub rollover_count: u64, // consecutive draws dispensed without a jackpot winner
+    pub max_rollovers: u64, // rollovers before a consolation draw, 0 disables it
+    pub number_domain: NumberDomain,
+    pub fulfill_duration: u64, // in seconds, time the oracle has to fulfill after draw
+    pub oracle_nonce: [u8; 32], // nonce point R of the oracle signature over the next request
+    pub oracle_nonce_slot: u64, // slot the oracle nonce was committed in
+    pub commit_bond: u64,      // deposit tokens the committer posts in commit-reveal mode
+    pub commit_authority: Pubkey, // only key allowed to commit in commit-reveal mode
+    pub committed_bond: u64,   // bond of the pending commitment, held in the treasury
+}
+
+// where draw randomness comes from
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RandomnessMode {
+    Oracle,       // external oracle calls fulfill_randomness
+    CommitReveal, // crank commits a secret before cutoff_time and reveals it after draw
+}
+
+impl Default for RandomnessMode {
+    fn default() -> Self {
+        RandomnessMode::Oracle
+    }
+}
+
+// how the winners of a draw are picked
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LotteryMode {
+    Numbers, // tickets matching 3 or more winning numbers share the prize tiers
+    Ticket,  // one registered ticket is drawn and wins the whole prize
+}
+
+impl Default for LotteryMode {
+    fn default() -> Self {
+        LotteryMode::Numbers
+    }
+}
+
+// numbers a ticket can pick and the draw can derive
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NumberDomain {
+    pub pick_count: u8, // numbers picked by a ticket, slots after them are 0
+    pub max_number: u8, // numbers are in 1..=max_number
+    pub unique: bool,   // a number cannot be picked twice
+}
+
+impl Default for NumberDomain {
+    fn default() -> Self {
+        NumberDomain {
+            pick_count: MAX_PICK_COUNT,
+            max_number: DEFAULT_MAX_NUMBER,
+            unique: false,
+        }
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Ticket {
+    pub deposit_mint: Pubkey,
+    pub yield_mint: Pubkey,
+    pub vault: Pubkey,
+    pub tickets: Pubkey,
+    pub owner: Pubkey,
+    pub numbers: [u8; 6],
+    pub vault_manager: Pubkey,
+    pub index: u64,          // position within the tickets of its number combination
+    pub draw_id: u64,        // first draw the ticket takes part in
+    pub purchased_at: u64,   // in seconds, when the ticket was bought
+    pub registry_index: u64, // slot of the ticket in the ticket registry
+}
+
+// ticket registry slot, seeded by vault pubkey and slot index
+#[account]
+#[derive(Default)]
+pub struct TicketEntry {
+    pub vault_manager: Pubkey,
+    pub ticket: Pubkey,
+}
+
+// tickets below an entry of a registry tree node
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RegistryEntry {
+    pub count: u32,
+    pub new_count: u32,         // bought during the node's draw period
+    pub new_purchased_sum: u64, // sum of their purchased_at, in seconds
+}
+
+// node of the ticket registry tree, seeded by vault pubkey, level and index
+// a level 0 entry is a registry slot, a higher level entry sums a node of the level below,
+// so a ticket is drawn by weight reading one node per level
+#[account]
+pub struct RegistryNode {
+    pub vault_manager: Pubkey,
+    pub level: u8,
+    pub index: u64,
+    pub draw_id: u64, // draw period of the new counts and sums, older ones are stale
+    pub entries: [RegistryEntry; REGISTRY_FANOUT],
+}
+
+impl Default for RegistryNode {
+    fn default() -> Self {
+        RegistryNode {
+            vault_manager: Pubkey::default(),
+            level: 0,
+            index: 0,
+            draw_id: 0,
+            entries: [RegistryEntry::default(); REGISTRY_FANOUT],
+        }
+    }
+}
+
+// all tickets sharing a number combination
+#[account]
+#[derive(Default)]
+pub struct Combination {
+    pub vault_manager: Pubkey,
+    pub numbers: [u8; 6],
+    pub count: u64,      // tickets currently holding this combination
+    pub next_index: u64, // index given to the next ticket bought with this combination
+}
+
+// history of a draw, also shares out its prize between the tickets registered in each tier
+#[account]
+#[derive(Default)]
+pub struct DrawRecord {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub winning_numbers: [u8; 6],
+    pub randomness_mode: RandomnessMode,
+    pub randomness: [u8; 32],       // winning numbers are derived from it
+    pub randomness_proof: [u8; 32], // request signed by the oracle or revealed secret
+    pub ticket_supply: u64,         // tickets taking part in the draw
+    pub drawn_at: u64,              // in seconds
+    pub fulfilled_at: u64,          // in seconds
+    pub dispensed_at: u64,          // in seconds
+    pub prize_amount: u64,          // in deposit tokens, before being shared out between the tiers
+    pub tier_prizes: [u64; PRIZE_TIER_COUNT], // prize of each tier, split between its winners
+    pub tier_winners: [u64; PRIZE_TIER_COUNT], // tickets registered in each tier
+    pub reserved: u64,              // sum of the tier prizes
+    pub claimed: u64,
+    pub registration_deadline: u64, // in seconds, after this tickets cannot register and prizes can be claimed
+    pub claim_deadline: u64, // in seconds, after this unclaimed prizes roll back into the pool
+    pub expired: bool,       // unclaimed prizes released back into the pool
+    pub fee_amount: u64,     // protocol fee moved to the treasury
+    pub period_start: u64, // in seconds, start of the draw period, tickets held since then have full weight
+    pub tier_weights: [u64; PRIZE_TIER_COUNT], // sum of the weights of the tickets registered in each tier
+    pub lottery_mode: LotteryMode,
+    pub winning_index: u64,     // registry slot drawn in ticket lottery mode
+    pub winning_ticket: Pubkey, // ticket drawn in ticket lottery mode
+    pub rolled_over: u64,       // prizes of earlier draws included in prize_amount
+    pub consolation: bool,      // rollover cap reached, one registered ticket was drawn
+    pub number_domain: NumberDomain, // domain the winning numbers were drawn from
+    pub tier_claimed: [u64; PRIZE_TIER_COUNT], // paid out of each tier prize
+    pub tier_claimed_weights: [u64; PRIZE_TIER_COUNT], // weights of the tickets paid in each tier
+    pub registry_weight: u64,   // ticket weights the winning ticket was drawn out of
+}
+
+// ticket registered for a prize tier of a draw
+#[account]
+#[derive(Default)]
+pub struct TicketClaim {
+    pub draw_record: Pubkey,
+    pub ticket: Pubkey,
+    pub owner: Pubkey, // ticket owner at registration
+    pub tier: u8,      // index into the draw record tiers
+    pub weight: u64,   // in seconds, time the ticket was held during the draw period
+}
+
+#[event]
+pub struct TicketPurchased {
+    pub vault_manager: Pubkey,
+    pub ticket: Pubkey,
+    pub owner: Pubkey,
+    pub numbers: [u8; 6],
+    pub draw_id: u64, // first draw the ticket takes part in
+    pub price: u64,
+}
+
+#[event]
+pub struct TicketRedeemed {
+    pub vault_manager: Pubkey,
+    pub ticket: Pubkey,
+    pub owner: Pubkey,
+    pub numbers: [u8; 6],
+    pub amount: u64, // deposit tokens returned to the owner
+}
+
+#[event]
+pub struct DrawStarted {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub randomness_mode: RandomnessMode,
+    pub ticket_supply: u64,
+    pub drawn_at: u64,
+}
+
+#[event]
+pub struct BondForfeited {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub committer: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct OracleNonceCommitted {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub nonce: [u8; 32],
+    pub slot: u64,
+}
+
+#[event]
+pub struct WinningNumbersDrawn {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub winning_numbers: [u8; 6],
+}
+
+#[event]
+pub struct DrawCancelled {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub cutoff_time: u64, // next cutoff time
+}
+
+#[event]
+pub struct Committed {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub committer: Pubkey,
+    pub commitment: [u8; 32],
+}
+
+// no commitment was made before the cutoff, the draw waits for one
+#[event]
+pub struct DrawPostponed {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub cutoff_time: u64, // next cutoff time
+}
+
+#[event]
+pub struct WinningTicketDrawn {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub winning_index: u64,
+    pub ticket: Pubkey,
+}
+
+#[event]
+pub struct DrawDispensed {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub draw_record: Pubkey,
+    pub prize_amount: u64, // after the fee
+    pub fee_amount: u64,
+    pub tier_prizes: [u64; PRIZE_TIER_COUNT],
+    pub rolled_over: u64, // prizes of earlier draws included in prize_amount
+    pub consolation: bool,
+}
+
+#[event]
+pub struct PoolInsolvent {
+    pub vault_manager: Pubkey,
+    pub total_principal: u64,
+    pub pool_value: u64,
+    pub shortfall: u64, // deposits not covered by the pool value
+}
+
+// also set as return data by pool_health
+#[event]
+pub struct PoolHealthReported {
+    pub vault_manager: Pubkey,
+    pub total_principal: u64,
+    pub deposit_amount: u64, // deposit vault less unclaimed prizes
+    pub position_value: u64, // yield vault valued in deposit tokens
+    pub pool_value: u64,
+    pub insolvent: bool,
+    pub jackpot: u64, // prize if the draw was dispensed now, before the fee
+    pub rolled_over: u64,
+    pub rollover_count: u64,
+}
+
+#[event]
+pub struct MatchRegistered {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub ticket: Pubkey,
+    pub owner: Pubkey,
+    pub tier: u8,
+    pub weight: u64,
+}
+
+#[event]
+pub struct WinnerPaid {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub ticket: Pubkey,
+    pub winner: Pubkey,
+    pub tier: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct NoWinner {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub released_amount: u64, // prize rolled back into the pool
+}
+
+#[event]
+pub struct JackpotRolledOver {
+    pub vault_manager: Pubkey,
+    pub draw_id: u64,
+    pub amount: u64,      // unclaimed prize released by this draw
+    pub rolled_over: u64, // total carried into the next prize
+    pub rollover_count: u64,
+}
+
+#[event]
+pub struct Staked {
+    pub vault_manager: Pubkey,
+    pub amount_in: u64,  // deposit tokens staked
+    pub amount_out: u64, // yield tokens received
+}
+
+#[event]
+pub struct SwapExecuted {
+    pub vault_manager: Pubkey,
+    pub source_vault: Pubkey,
+    pub destination_vault: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub vault_manager: Pubkey,
+    pub draw_duration: u64,
+    pub ticket_price: u64,
+    pub deposit_token_reserve: u64,
+    pub reveal_duration: u64,
+    pub registration_duration: u64,
+    pub claim_duration: u64,
+    pub prize_tiers: [u16; PRIZE_TIER_COUNT],
+    pub fee_bps: u16,
+    pub slippage_bps: u16,
+    pub min_holding_period: u64,
+    pub max_rollovers: u64,
+    pub number_domain: NumberDomain,
+    pub fulfill_duration: u64,
+    pub commit_bond: u64,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub vault_manager: Pubkey,
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub vault_manager: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey, // takes over once it calls accept_authority
+}
+
+#[event]
+pub struct GuardianUpdated {
+    pub vault_manager: Pubkey,
+    pub previous_guardian: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct CommitAuthorityUpdated {
+    pub vault_manager: Pubkey,
+    pub previous_commit_authority: Pubkey,
+    pub commit_authority: Pubkey,
+}
+
+#[event]
+pub struct YieldSourceUpdated {
+    pub vault_manager: Pubkey,
+    pub token_swap_program: Pubkey,
+    pub amm: Pubkey,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub vault_manager: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PauseChanged {
+    pub vault_manager: Pubkey,
+    pub paused: bool,
+    pub signer: Pubkey, // authority or guardian
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("TimeRemaining")]
+    TimeRemaining,
+
+    #[msg("Must call Dispense")]
+    CallDispense,
+
+    #[msg("Invalid Numbers")]
+    InvalidNumbers,
+
+    #[msg("No Tickets Purchased")]
+    NoTicketsPurchased,
+
+    #[msg("Must Pass in Winning PDA to Dispense")]
+    PassInWinningPDA,
+
+    #[msg("Not enough tokens for swap")]
+    NotEnoughTokens,
+
+    #[msg("Invalid ticket price")]
+    InvalidTicketPrice,
+
+    #[msg("Invalid draw duration")]
+    InvalidDrawDuration,
+
+    #[msg("Invalid randomness oracle")]
+    InvalidOracle,
+
+    #[msg("No randomness requested")]
+    RandomnessNotRequested,
+
+    #[msg("Invalid randomness proof")]
+    InvalidRandomnessProof,
+
+    #[msg("Winning numbers not drawn yet")]
+    RandomnessNotFulfilled,
+
+    #[msg("Invalid randomness mode for this instruction")]
+    InvalidRandomnessMode,
+
+    #[msg("Invalid reveal duration")]
+    InvalidRevealDuration,
+
+    #[msg("No commitment made for this draw")]
+    NoCommitment,
+
+    #[msg("Commitment must be made before cutoff time")]
+    CommitmentTooLate,
+
+    #[msg("Commitment already made for this draw")]
+    CommitmentExists,
+
+    #[msg("Invalid commitment")]
+    InvalidCommitment,
+
+    #[msg("Only the committer can reveal")]
+    InvalidCommitter,
+
+    #[msg("Secret does not match commitment")]
+    InvalidReveal,
+
+    #[msg("Reveal slot has not been produced yet")]
+    RevealTooEarly,
+
+    #[msg("Reveal window has passed, call expire_draw")]
+    RevealExpired,
+
+    #[msg("Draw has not expired")]
+    DrawNotExpired,
+
+    #[msg("Ticket is not owned by user")]
+    InvalidTicketOwner,
+
+    #[msg("Invalid claim duration")]
+    InvalidClaimDuration,
+
+    #[msg("Prize can only be claimed by the winner")]
+    NotPrizeWinner,
+
+    #[msg("Prize claim has expired")]
+    PrizeClaimExpired,
+
+    #[msg("Prize claim has not expired")]
+    PrizeClaimNotExpired,
+
+    #[msg("Prize tiers add up to more than the whole prize")]
+    InvalidPrizeTiers,
+
+    #[msg("Invalid registration duration")]
+    InvalidRegistrationDuration,
+
+    #[msg("Registration for this draw has closed")]
+    RegistrationClosed,
+
+    #[msg("Prizes can be claimed once registration has closed")]
+    RegistrationOpen,
+
+    #[msg("Ticket was bought after this draw")]
+    TicketNotInDraw,
+
+    #[msg("Ticket does not match enough numbers for a prize")]
+    NoPrizeMatch,
+
+    #[msg("Signer is not the vault authority")]
+    Unauthorized,
+
+    #[msg("Ticket price cannot change while tickets are outstanding")]
+    TicketsOutstanding,
+
+    #[msg("Signer is not the pending vault authority")]
+    InvalidPendingAuthority,
+
+    #[msg("Lottery is paused")]
+    Paused,
+
+    #[msg("Lottery is not paused")]
+    NotPaused,
+
+    #[msg("Fee cannot exceed 10000 bps")]
+    InvalidFee,
+
+    #[msg("Swap output is below the allowed slippage")]
+    SlippageExceeded,
+
+    #[msg("Slippage cannot exceed 10000 bps")]
+    InvalidSlippage,
+
+    #[msg("Swap program or pool accounts do not match the yield source")]
+    InvalidYieldSource,
+
+    #[msg("Math overflow")]
+    MathOverflow,
+
+    #[msg("Tickets cannot be redeemed while a draw is in progress")]
+    DrawInProgress,
+
+    #[msg("Ticket has not been held for the minimum holding period")]
+    HoldingPeriodNotElapsed,
+
+    #[msg("Ticket registry entry does not match")]
+    InvalidTicketEntry,
+
+    #[msg("Number domain is invalid")]
+    InvalidNumberDomain,
+
+    #[msg("Numbers do not match the quick pick")]
+    InvalidQuickPick,
+
+    #[msg("Batch must hold between 1 and MAX_BATCH_SIZE tickets with 3 accounts each")]
+    InvalidBatchSize,
+
+    #[msg("Batch account is not the expected PDA")]
+    InvalidBatchAccount,
+
+    #[msg("Holding period cannot exceed MAX_HOLDING_PERIOD")]
+    InvalidHoldingPeriod,
+
+    #[msg("Invalid fulfill duration")]
+    InvalidFulfillDuration,
+
+    #[msg("Oracle has not committed a nonce before the draw")]
+    NoOracleNonce,
+
+    #[msg("Invalid commit bond")]
+    InvalidCommitBond,
+
+    #[msg("Ticket registry is full")]
+    RegistryFull,
+
+    #[msg("Ticket registry tree node is missing or does not match")]
+    InvalidRegistryNode,
+}
+
+fn get_current_time() -> u64 {
+    Clock::get().unwrap().unix_timestamp as u64
+}
+
+// order of the ed25519 base point, little endian
+pub const ED25519_ORDER: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+];
+
+// number of slots after draw whose hash is mixed with the revealed secret
+pub const REVEAL_SLOT_DELAY: u64 = 2;
+
+// SlotHashes holds the last 512 slots, keep the reveal window well within it
+pub const MAX_REVEAL_DURATION: u64 = 150;
+
+// longest min_holding_period, tickets cannot be locked in for longer, in seconds
+pub const MAX_HOLDING_PERIOD: u64 = 30 * 24 * 3600;
+
+// prefix of draw record PDA seeds
+pub const DRAW_RECORD_SEED: &[u8] = b"draw_record";
+
+// prefix of ticket claim PDA seeds
+pub const TICKET_CLAIM_SEED: &[u8] = b"ticket_claim";
+
+// prefix of ticket registry entry PDA seeds
+pub const TICKET_ENTRY_SEED: &[u8] = b"ticket_entry";
+
+// prefix of the treasury token account PDA seeds
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+// prefix of ticket registry tree node PDA seeds
+pub const REGISTRY_NODE_SEED: &[u8] = b"registry_node";
+
+// entries of a ticket registry tree node
+pub const REGISTRY_FANOUT: usize = 64;
+
+// levels of the ticket registry tree, level 0 entries are registry slots
+pub const REGISTRY_DEPTH: u8 = 3;
+
+// tickets the registry tree can hold, one slot per level 0 entry
+pub const REGISTRY_CAPACITY: u64 = (REGISTRY_FANOUT as u64).pow(REGISTRY_DEPTH as u32);
+
+// prize tiers for 3, 4, 5 and 6 matching numbers
+pub const PRIZE_TIER_COUNT: usize = 4;
+
+// tickets hold up to 6 numbers
+pub const MAX_PICK_COUNT: u8 = 6;
+
+// quick picks re-roll at most this many times past existing combinations
+pub const MAX_QUICK_PICK_ROLLS: u8 = 8;
+
+// most tickets bought by buy_many
+// each ticket takes 3 accounts next to the registry tree nodes, more do not fit in a transaction
+pub const MAX_BATCH_SIZE: usize = 4;
+
+// prize tiers are in basis points of the prize
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// default slippage allowed on swaps, 1%
+pub const DEFAULT_SLIPPAGE_BPS: u16 = 100;
+
+// highest number of the default number domain
+pub const DEFAULT_MAX_NUMBER: u8 = 49;
+
+// config set by initialize and update_config
+fn validate_config(vault_mgr: &VaultManager) -> Result<()> {
+    // ticket_price must be > 0
+    if vault_mgr.ticket_price == 0 {
+        return Err(error!(ErrorCode::InvalidTicketPrice));
+    }
+
+    // draw_duration must be > 0
+    if vault_mgr.draw_duration == 0 {
+        return Err(error!(ErrorCode::InvalidDrawDuration));
+    }
+
+    // reveal must happen while the draw slot is still in the SlotHashes sysvar
+    if vault_mgr.randomness_mode == RandomnessMode::CommitReveal
+        && (vault_mgr.reveal_duration == 0 || vault_mgr.reveal_duration > MAX_REVEAL_DURATION)
+    {
+        return Err(error!(ErrorCode::InvalidRevealDuration));
+    }
+
+    // a committer that never reveals would cancel draws for free
+    if vault_mgr.randomness_mode == RandomnessMode::CommitReveal && vault_mgr.commit_bond == 0 {
+        return Err(error!(ErrorCode::InvalidCommitBond));
+    }
+
+    // an oracle draw could be expired as soon as it starts
+    if vault_mgr.randomness_mode == RandomnessMode::Oracle && vault_mgr.fulfill_duration == 0 {
+        return Err(error!(ErrorCode::InvalidFulfillDuration));
+    }
+
+    // registration_duration must be > 0
+    if vault_mgr.registration_duration == 0 {
+        return Err(error!(ErrorCode::InvalidRegistrationDuration));
+    }
+
+    // claim_duration must be > 0
+    if vault_mgr.claim_duration == 0 {
+        return Err(error!(ErrorCode::InvalidClaimDuration));
+    }
+
+    // tiers cannot share out more than the whole prize
+    let prize_tiers_total: u64 = vault_mgr.prize_tiers.iter().map(|bps| *bps as u64).sum();
+    if prize_tiers_total > BPS_DENOMINATOR {
+        return Err(error!(ErrorCode::InvalidPrizeTiers));
+    }
+
+    // fee cannot take more than the whole prize
+    if vault_mgr.fee_bps as u64 > BPS_DENOMINATOR {
+        return Err(error!(ErrorCode::InvalidFee));
+    }
+
+    if vault_mgr.slippage_bps as u64 > BPS_DENOMINATOR {
+        return Err(error!(ErrorCode::InvalidSlippage));
+    }
+
+    // tickets cannot be locked in for longer than the cap
+    if vault_mgr.min_holding_period > MAX_HOLDING_PERIOD {
+        return Err(error!(ErrorCode::InvalidHoldingPeriod));
+    }
+
+    validate_number_domain(&vault_mgr.number_domain)
+}
+
+// pick count must fit in a ticket, unique picks need enough numbers
+fn validate_number_domain(domain: &NumberDomain) -> Result<()> {
+    if domain.pick_count == 0
+        || domain.pick_count > MAX_PICK_COUNT
+        || domain.max_number == 0
+        || (domain.unique && domain.max_number < domain.pick_count)
+    {
+        return Err(error!(ErrorCode::InvalidNumberDomain));
+    }
+    Ok(())
+}
+
+// ticket numbers must be the canonical form of a pick in the domain
+// picks are sorted ascending and unused slots are 0
+fn validate_numbers(numbers: &[u8; 6], domain: &NumberDomain) -> Result<()> {
+    for (i, number) in numbers.iter().enumerate() {
+        let valid = if i >= domain.pick_count as usize {
+            *number == 0
+        } else if *number == 0 || *number > domain.max_number {
+            false
+        } else if i > 0 && domain.unique {
+            numbers[i - 1] < *number
+        } else if i > 0 {
+            numbers[i - 1] <= *number
+        } else {
+            true
+        };
+        if !valid {
+            return Err(error!(ErrorCode::InvalidNumbers));
+        }
+    }
+    Ok(())
+}
+
+// derive the winning numbers of the domain from 32 bytes of randomness
+// each number uses 4 bytes of randomness, slots after the pick count are 0
+fn derive_winning_numbers(randomness: &[u8; 32], domain: &NumberDomain) -> [u8; 6] {
+    let mut numbers = [0u8; 6];
+    for i in 0..domain.pick_count as usize {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&randomness[i * 4..i * 4 + 4]);
+        let value = u32::from_le_bytes(bytes);
+
+        if domain.unique {
+            // pick among the numbers not drawn yet, then skip the drawn ones below it
+            let mut number = (value % (domain.max_number as u32 - i as u32) + 1) as u8;
+            let mut drawn = numbers[..i].to_vec();
+            drawn.sort();
+            for drawn_number in drawn {
+                if drawn_number <= number {
+                    number += 1;
+                }
+            }
+            numbers[i] = number;
+        } else {
+            numbers[i] = (value % domain.max_number as u32 + 1) as u8;
+        }
+    }
+    numbers
+}
+
+// weight the winning ticket is found at, out of the total weight of the registry
+// winning numbers use the first 24 bytes of randomness, the ticket draw uses the last 8
+fn derive_winning_weight(randomness: &[u8; 32], total_weight: u64) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&randomness[24..32]);
+    u64::from_le_bytes(bytes) % total_weight
+}
+
+// registry slot of the winning ticket and the total weight it was drawn from
+// a ticket's odds are its weight, the time it was held during the draw period as in register_match
+// walks down the registry tree from the root, reading one node per level from remaining_accounts
+fn draw_registry_slot<'info>(
+    remaining_accounts: &[AccountInfo<'info>],
+    vault_manager: &Pubkey,
+    draw_record: &DrawRecord,
+    program_id: &Pubkey,
+) -> Result<(u64, u64)> {
+    let mut index = 0;
+    let mut target = 0;
+    let mut total_weight = 0;
+    for level in (0..REGISTRY_DEPTH).rev() {
+        let node = load_registry_node(
+            remaining_accounts,
+            vault_manager,
+            level,
+            index,
+            None,
+            program_id,
+        )?;
+        let weights = registry_weights(&node, draw_record)?;
+
+        // the root sums the whole registry, the winning weight is drawn out of it
+        if level == REGISTRY_DEPTH - 1 {
+            total_weight = weights
+                .iter()
+                .try_fold(0u64, |total, weight| total.checked_add(*weight))
+                .ok_or(error!(ErrorCode::MathOverflow))?;
+            if total_weight == 0 {
+                return Err(error!(ErrorCode::InvalidRegistryNode));
+            }
+            target = derive_winning_weight(&draw_record.randomness, total_weight);
+        }
+
+        // go down to the entry whose range of weight holds the target
+        let mut child = None;
+        for (position, weight) in weights.iter().enumerate() {
+            if target < *weight {
+                child = Some(position as u64);
+                break;
+            }
+            target -= weight;
+        }
+        let child = child.ok_or(error!(ErrorCode::InvalidRegistryNode))?;
+        index = index * REGISTRY_FANOUT as u64 + child;
+    }
+    Ok((index, total_weight))
+}
+
+// weight of the tickets below each entry of a registry tree node in the draw of draw_record
+// tickets held since before the period all weigh the same, tickets bought during it are
+// summed by purchase time and each weighs that much less
+fn registry_weights(
+    node: &RegistryNode,
+    draw_record: &DrawRecord,
+) -> Result<[u64; REGISTRY_FANOUT]> {
+    // sums of tickets bought during the period are stale if the node was last updated before it
+    let current = node.draw_id == draw_record.draw_id;
+    let period_start = draw_record.period_start as u128;
+    let full_weight = ticket_weight(
+        draw_record.period_start,
+        draw_record.period_start,
+        draw_record.drawn_at,
+    );
+
+    let mut weights = [0u64; REGISTRY_FANOUT];
+    for (weight, entry) in weights.iter_mut().zip(node.entries.iter()) {
+        let (new_count, new_purchased_sum) = if current {
+            (entry.new_count as u128, entry.new_purchased_sum as u128)
+        } else {
+            (0, 0)
+        };
+        // a ticket bought during the period weighs full_weight - (purchased_at - period_start)
+        let total = (full_weight as u128 * entry.count as u128 + period_start * new_count)
+            .checked_sub(new_purchased_sum)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+        if total > u64::MAX as u128 {
+            return Err(error!(ErrorCode::MathOverflow));
+        }
+        *weight = total as u64;
+    }
+    Ok(weights)
+}
+
+// ticket registry tree node at level and index, found by address in remaining_accounts
+// a missing node is created when a payer and the system program are given
+fn load_registry_node<'info>(
+    remaining_accounts: &[AccountInfo<'info>],
+    vault_manager: &Pubkey,
+    level: u8,
+    index: u64,
+    payer: Option<(&AccountInfo<'info>, &AccountInfo<'info>)>,
+    program_id: &Pubkey,
+) -> Result<Account<'info, RegistryNode>> {
+    let level_seed = [level];
+    let index_seed = index.to_le_bytes();
+    let seeds: &[&[u8]] = &[
+        REGISTRY_NODE_SEED,
+        vault_manager.as_ref(),
+        &level_seed,
+        &index_seed,
+    ];
+    let (address, _bump) = Pubkey::find_program_address(seeds, program_id);
+    let info = remaining_accounts
+        .iter()
+        .find(|info| info.key() == address)
+        .ok_or(error!(ErrorCode::InvalidRegistryNode))?;
+
+    match payer {
+        Some((payer, system_program)) if info.data_is_empty() => {
+            create_pda_account(
+                payer,
+                info,
+                system_program,
+                program_id,
+                seeds,
+                account_space::<RegistryNode>()?,
+            )?;
+            let mut node: Account<RegistryNode> = Account::try_from_unchecked(info)?;
+            node.vault_manager = *vault_manager;
+            node.level = level;
+            node.index = index;
+            Ok(node)
+        }
+        _ => Account::try_from(info),
+    }
+}
+
+// too many draws in a row without a winner, one ticket from the registry takes the prize
+fn consolation_due(vault_manager: &VaultManager) -> bool {
+    vault_manager.lottery_mode == LotteryMode::Numbers
+        && vault_manager.max_rollovers > 0
+        && vault_manager.rollover_count >= vault_manager.max_rollovers
+}
+
+// winner of the current draw is picked from the ticket registry at dispense
+fn draws_from_registry(vault_manager: &VaultManager) -> bool {
+    vault_manager.lottery_mode == LotteryMode::Ticket || consolation_due(vault_manager)
+}
+
+// cancel the current draw, tickets roll over to the next draw
+// a pending commitment is left to the caller
+fn cancel_draw(vault_manager: &mut Account<VaultManager>, now: u64) -> Result<()> {
+    vault_manager.locked = false;
+    vault_manager.randomness_pending = false;
+    vault_manager.randomness_request = [0u8; 32];
+    vault_manager.winning_numbers = [0u8; 6];
+    // the oracle may have published a signature with the nonce in a failed fulfillment,
+    // it commits a new one for the next request
+    vault_manager.oracle_nonce = [0u8; 32];
+    vault_manager.oracle_nonce_slot = 0;
+    vault_manager.cutoff_time = now
+        .checked_add(vault_manager.draw_duration)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+
+    emit!(DrawCancelled {
+        vault_manager: vault_manager.key(),
+        draw_id: vault_manager.draw_id,
+        cutoff_time: vault_manager.cutoff_time,
+    });
+    Ok(())
+}
+
+// ticket registry entry at index, passed as the first remaining account
+fn load_ticket_entry<'info>(
+    remaining_accounts: &[AccountInfo<'info>],
+    vault_manager: &Pubkey,
+    index: u64,
+    program_id: &Pubkey,
+) -> Result<Account<'info, TicketEntry>> {
+    let info = remaining_accounts
+        .get(0)
+        .ok_or(error!(ErrorCode::InvalidTicketEntry))?;
+
+    let (address, _bump) = Pubkey::find_program_address(
+        &[
+            TICKET_ENTRY_SEED,
+            vault_manager.as_ref(),
+            &index.to_le_bytes(),
+        ],
+        program_id,
+    );
+    if info.key() != address {
+        return Err(error!(ErrorCode::InvalidTicketEntry));
+    }
+    Account::try_from(info)
+}
+
+// point the redeemed ticket's registry slot at the ticket in the last slot
+// when the redeemed ticket is in the last slot the accounts alias each other and nothing moves,
+// loading them again as mutable would write the closed accounts back on exit
+// the registry tree follows, the caller writes it back
+fn move_last_ticket_entry<'a, 'info>(
+    ticket: &Account<'info, Ticket>,
+    ticket_entry: &AccountInfo<'info>,
+    last_ticket_entry: &Account<'info, TicketEntry>,
+    last_ticket: &AccountInfo<'info>,
+    registry: &mut RegistryTree<'a, 'info>,
+    program_id: &Pubkey,
+) -> Result<()> {
+    if ticket_entry.key() == last_ticket_entry.key() {
+        if last_ticket_entry.ticket != ticket.key() {
+            return Err(error!(ErrorCode::InvalidTicketEntry));
+        }
+        return registry.remove(ticket, program_id);
+    }
+
+    let mut entry: Account<'info, TicketEntry> = Account::try_from(ticket_entry)?;
+    if entry.vault_manager != ticket.vault_manager || entry.ticket != ticket.key() {
+        return Err(error!(ErrorCode::InvalidTicketEntry));
+    }
+    let mut moved: Account<'info, Ticket> = Account::try_from(last_ticket)?;
+    if moved.vault_manager != ticket.vault_manager {
+        return Err(error!(ErrorCode::InvalidTicketEntry));
+    }
+
+    registry.remove(ticket, program_id)?;
+    registry.remove(&moved, program_id)?;
+    entry.ticket = last_ticket_entry.ticket;
+    moved.registry_index = ticket.registry_index;
+    registry.add(&moved, program_id)?;
+    entry.exit(program_id)?;
+    moved.exit(program_id)
+}
+
+// ticket registry tree nodes passed in remaining_accounts, each loaded once and written by exit
+// a ticket is added or removed on the entry holding its slot at every level
+struct RegistryTree<'a, 'info> {
+    remaining_accounts: &'a [AccountInfo<'info>],
+    vault_manager: Pubkey,
+    draw_id: u64,                                            // current draw period
+    payer: Option<(AccountInfo<'info>, AccountInfo<'info>)>, // and system program, to create nodes
+    nodes: Vec<Account<'info, RegistryNode>>,
+}
+
+impl<'a, 'info> RegistryTree<'a, 'info> {
+    fn new(
+        remaining_accounts: &'a [AccountInfo<'info>],
+        vault_manager: Pubkey,
+        draw_id: u64,
+        payer: Option<(AccountInfo<'info>, AccountInfo<'info>)>,
+    ) -> Self {
+        RegistryTree {
+            remaining_accounts,
+            vault_manager,
+            draw_id,
+            payer,
+            nodes: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, ticket: &Ticket, program_id: &Pubkey) -> Result<()> {
+        self.update(ticket, true, program_id)
+    }
+
+    fn remove(&mut self, ticket: &Ticket, program_id: &Pubkey) -> Result<()> {
+        self.update(ticket, false, program_id)
+    }
+
+    fn update(&mut self, ticket: &Ticket, add: bool, program_id: &Pubkey) -> Result<()> {
+        let draw_id = self.draw_id;
+
+        // tickets bought during the current period are also summed by purchase time
+        let purchased_at = if ticket.draw_id == draw_id {
+            Some(ticket.purchased_at)
+        } else {
+            None
+        };
+
+        let mut span: u64 = 1;
+        for level in 0..REGISTRY_DEPTH {
+            let position = (ticket.registry_index / span % REGISTRY_FANOUT as u64) as usize;
+            span *= REGISTRY_FANOUT as u64;
+            let node = self.node(level, ticket.registry_index / span, program_id)?;
+
+            // sums left from an earlier period are stale, its tickets are now held for a whole one
+            if node.draw_id != draw_id {
+                node.draw_id = draw_id;
+                for entry in node.entries.iter_mut() {
+                    entry.new_count = 0;
+                    entry.new_purchased_sum = 0;
+                }
+            }
+
+            let entry = &mut node.entries[position];
+            if add {
+                entry.count = entry
+                    .count
+                    .checked_add(1)
+                    .ok_or(error!(ErrorCode::MathOverflow))?;
+                if let Some(purchased_at) = purchased_at {
+                    entry.new_count = entry
+                        .new_count
+                        .checked_add(1)
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                    entry.new_purchased_sum = entry
+                        .new_purchased_sum
+                        .checked_add(purchased_at)
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                }
+            } else {
+                entry.count = entry
+                    .count
+                    .checked_sub(1)
+                    .ok_or(error!(ErrorCode::MathOverflow))?;
+                if let Some(purchased_at) = purchased_at {
+                    entry.new_count = entry
+                        .new_count
+                        .checked_sub(1)
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                    entry.new_purchased_sum = entry
+                        .new_purchased_sum
+                        .checked_sub(purchased_at)
+                        .ok_or(error!(ErrorCode::MathOverflow))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // node at level and index, loaded on first use
+    fn node(
+        &mut self,
+        level: u8,
+        index: u64,
+        program_id: &Pubkey,
+    ) -> Result<&mut Account<'info, RegistryNode>> {
+        let loaded = self
+            .nodes
+            .iter()
+            .position(|node| node.level == level && node.index == index);
+        let position = match loaded {
+            Some(position) => position,
+            None => {
+                let payer = self
+                    .payer
+                    .as_ref()
+                    .map(|(payer, system_program)| (payer, system_program));
+                let node = load_registry_node(
+                    self.remaining_accounts,
+                    &self.vault_manager,
+                    level,
+                    index,
+                    payer,
+                    program_id,
+                )?;
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        Ok(&mut self.nodes[position])
+    }
+
+    // write the loaded nodes back to their accounts
+    fn exit(&self, program_id: &Pubkey) -> Result<()> {
+        for node in self.nodes.iter() {
+            node.exit(program_id)?;
+        }
+        Ok(())
+    }
+}
+
+// tickets holding the winning numbers, from their combination PDA passed as the first remaining account
+// the PDA only exists once a ticket has been bought with these numbers
+fn load_jackpot_holders<'info>(
+    remaining_accounts: &[AccountInfo<'info>],
+    vault_manager: &Pubkey,
+    winning_numbers: &[u8; 6],
+    program_id: &Pubkey,
+) -> Result<u64> {
+    let info = remaining_accounts
+        .get(0)
+        .ok_or(error!(ErrorCode::PassInWinningPDA))?;
+
+    // winning numbers are drawn in any order, combinations are stored sorted
+    let (address, _bump) = Pubkey::find_program_address(
+        &[&canonical_numbers(winning_numbers), vault_manager.as_ref()],
+        program_id,
+    );
+    if info.key() != address {
+        return Err(error!(ErrorCode::PassInWinningPDA));
+    }
+
+    if info.data_is_empty() {
+        return Ok(0);
+    }
+    let combination: Account<'info, Combination> = Account::try_from(info)?;
+    Ok(combination.count)
+}
+
+// picks sorted ascending with the unused 0 slots last, equivalent picks share a combination
+fn canonical_numbers(numbers: &[u8; 6]) -> [u8; 6] {
+    let mut canonical = *numbers;
+    canonical.sort_by_key(|number| (*number == 0, *number));
+    canonical
+}
+
+// oracle signature from the Ed25519 program instruction right before the current one
+// the instruction must verify one signature by the oracle over the pending request
+fn verify_oracle_signature(
+    instructions: &AccountInfo,
+    oracle: &Pubkey,
+    request: &[u8; 32],
+) -> Result<[u8; 64]> {
+    let current_index = sysvar::instructions::load_current_index_checked(instructions)? as usize;
+    if current_index == 0 {
+        return Err(error!(ErrorCode::InvalidRandomnessProof));
+    }
+
+    let instruction =
+        sysvar::instructions::load_instruction_at_checked(current_index - 1, instructions)?;
+    if instruction.program_id != ed25519_program::id() {
+        return Err(error!(ErrorCode::InvalidRandomnessProof));
+    }
+
+    // signature count, padding and one offsets entry of 7 u16 values
+    let data = &instruction.data;
+    if data.len() < 16 || data[0] != 1 {
+        return Err(error!(ErrorCode::InvalidRandomnessProof));
+    }
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+
+    // signature, public key and message must be inlined in the Ed25519 instruction
+    // u16::MAX points at the instruction itself
+    if read_u16(4) != u16::MAX || read_u16(8) != u16::MAX || read_u16(14) != u16::MAX {
+        return Err(error!(ErrorCode::InvalidRandomnessProof));
+    }
+    let signature_offset = read_u16(2) as usize;
+    let public_key_offset = read_u16(6) as usize;
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(error!(ErrorCode::InvalidRandomnessProof))?;
+    if public_key != oracle.as_ref() {
+        return Err(error!(ErrorCode::InvalidOracle));
+    }
+
+    let message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(error!(ErrorCode::InvalidRandomnessProof))?;
+    if message != request {
+        return Err(error!(ErrorCode::InvalidRandomnessProof));
+    }
+
+    let mut signature = [0u8; 64];
+    signature.copy_from_slice(
+        data.get(signature_offset..signature_offset + 64)
+            .ok_or(error!(ErrorCode::InvalidRandomnessProof))?,
+    );
+    Ok(signature)
+}
+
+// find the hash of the first block produced at or after `slot` in the SlotHashes sysvar
+// sysvar layout is a u64 length followed by (u64 slot, [u8; 32] hash) entries, newest first
+fn find_slot_hash(slot_hashes: &AccountInfo, slot: u64) -> Result<[u8; 32]> {
+    let data = slot_hashes.try_borrow_data()?;
+
+    let mut len_bytes = [0u8; 8];
+    len_bytes.copy_from_slice(&data[..8]);
+    let len = u64::from_le_bytes(len_bytes) as usize;
+
+    let mut found: Option<[u8; 32]> = None;
+    for i in 0..len {
+        let offset = 8 + i * 40;
+
+        let mut slot_bytes = [0u8; 8];
+        slot_bytes.copy_from_slice(&data[offset..offset + 8]);
+        let entry_slot = u64::from_le_bytes(slot_bytes);
+
+        // passed `slot`, the previous entry is the first block after it
+        if entry_slot < slot {
+            return found.ok_or_else(|| error!(ErrorCode::RevealTooEarly));
+        }
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&data[offset + 8..offset + 40]);
+        if entry_slot == slot {
+            return Ok(hash);
+        }
+        found = Some(hash);
+    }
+
+    // `slot` is older than every entry, it has been evicted from the sysvar
+    // an empty sysvar means no block has been produced after `slot` yet
+    match found {
+        Some(_) => Err(error!(ErrorCode::RevealExpired)),
+        None => Err(error!(ErrorCode::RevealTooEarly)),
+    }
+}
+
+// newest entry of the SlotHashes sysvar, the hash of the last block before this one
+fn latest_slot_hash(slot_hashes: &AccountInfo) -> Result<(u64, [u8; 32])> {
+    let data = slot_hashes.try_borrow_data()?;
+    if data.len() < 48 || data[..8] == [0u8; 8] {
+        return Err(error!(ErrorCode::NoOracleNonce));
+    }
+
+    let mut slot_bytes = [0u8; 8];
+    slot_bytes.copy_from_slice(&data[8..16]);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&data[16..48]);
+    Ok((u64::from_le_bytes(slot_bytes), hash))
+}
+
+// little endian scalar is below the group order, S + l verifies against the same R under
+// lenient ed25519 verifiers and would hash to different randomness
+fn is_reduced_scalar(scalar: &[u8]) -> bool {
+    for (byte, order_byte) in scalar.iter().zip(ED25519_ORDER.iter()).rev() {
+        if byte != order_byte {
+            return byte < order_byte;
+        }
+    }
+    false
+}
+
+// first ticket starts the draw, buys are rejected while paused or locked
+fn check_can_buy(vault_manager: &mut Account<VaultManager>) -> Result<()> {
+    // if cutoff_time is 0, drawing has never started
+    if vault_manager.cutoff_time == 0 {
+        // get current timestamp from Clock program
+        let now = get_current_time();
+
+        // set last draw time to now
+        vault_manager.cutoff_time = now
+            .checked_add(vault_manager.draw_duration)
+            .ok_or(error!(ErrorCode::MathOverflow))?;
+
+        // first draw period starts with the first ticket
+        vault_manager.period_start = now;
+    };
+
+    // no new deposits while paused
+    if vault_manager.paused {
+        return Err(error!(ErrorCode::Paused));
+    }
+
+    // if buy is locked, call find
+    if vault_manager.locked {
+        return Err(error!(ErrorCode::CallDispense));
+    }
+    Ok(())
+}
+
+// fill a new ticket, add it to the registry and to the holders of its number combination
+fn record_ticket(
+    vault_manager: &mut Account<VaultManager>,
+    combination: &mut Combination,
+    ticket: &mut Ticket,
+    ticket_key: Pubkey,
+    ticket_entry: &mut TicketEntry,
+    owner: Pubkey,
+    numbers: [u8; 6],
+) -> Result<()> {
+    ticket.deposit_mint = vault_manager.deposit_mint;
+    ticket.yield_mint = vault_manager.yield_mint;
+    ticket.vault = vault_manager.deposit_vault;
+    ticket.tickets = vault_manager.tickets;
+    ticket.owner = owner;
+    ticket.numbers = numbers;
+    ticket.vault_manager = vault_manager.key();
+    ticket.draw_id = vault_manager.draw_id;
+    ticket.purchased_at = get_current_time();
+
+    // add ticket to the registry, tickets are drawn by their slot in ticket lottery mode
+    // the registry tree has a slot for every ticket up to its capacity
+    if vault_manager.ticket_count >= REGISTRY_CAPACITY {
+        return Err(error!(ErrorCode::RegistryFull));
+    }
+    ticket.registry_index = vault_manager.ticket_count;
+    ticket_entry.vault_manager = vault_manager.key();
+    ticket_entry.ticket = ticket_key;
+    vault_manager.ticket_count = vault_manager
+        .ticket_count
+        .checked_add(1)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+
+    // add ticket to the holders of its number combination
+    combination.vault_manager = vault_manager.key();
+    combination.numbers = numbers;
+    ticket.index = combination.next_index;
+    combination.next_index = combination
+        .next_index
+        .checked_add(1)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    combination.count = combination
+        .count
+        .checked_add(1)
+        .ok_or(error!(ErrorCode::MathOverflow))?;
+    Ok(())
+}
+
+// space of an account sized like `init` without `space` does
+fn account_space<T: AnchorSerialize + Default>() -> Result<usize> {
+    let data = T::default()
+        .try_to_vec()
+        .map_err(|_| anchor_lang::error::ErrorCode::AccountDidNotSerialize)?;
+    Ok(8 + data.len())
+}
+
+// check `account` is the PDA of `seeds` and create it owned by this program if it does not exist
+// returns false if the account already exists
+// lamports sent to the address beforehand are kept, like `init` does
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    program_id: &Pubkey,
+    seeds: &[&[u8]],
+    space: usize,
+) -> Result<bool> {
+    let (address, bump) = Pubkey::find_program_address(seeds, program_id);
+    if account.key() != address {
+        return Err(error!(ErrorCode::InvalidBatchAccount));
+    }
+    if !account.data_is_empty() {
+        return Ok(false);
+    }
+
+    let bump_seed = [bump];
+    let mut signer_seeds = seeds.to_vec();
+    signer_seeds.push(&bump_seed);
+
+    let rent = Rent::get()?.minimum_balance(space);
+    if account.lamports() == 0 {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                rent,
+                space as u64,
+                program_id,
+            ),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[&signer_seeds],
+        )?;
+    } else {
+        // top up to rent exemption, then allocate and assign the funded account
+        let top_up = rent.saturating_sub(account.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, account.key, top_up),
+                &[payer.clone(), account.clone(), system_program.clone()],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(account.key, space as u64),
+            &[account.clone(), system_program.clone()],
+            &[&signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(account.key, program_id),
+            &[account.clone(), system_program.clone()],
+            &[&signer_seeds],
+        )?;
+    }
+    Ok(true)
+}
+
+// seconds a ticket took part in the draw period ending at drawn_at, counting the second it was
+// drawn so every ticket weighs at least 1
+// tickets held since before the period get the whole period
+fn ticket_weight(purchased_at: u64, period_start: u64, drawn_at: u64) -> u64 {
+    drawn_at
+        .saturating_sub(std::cmp::max(purchased_at, period_start))
+        .saturating_add(1)
+}
+
+// share of amount for weight out of total_weight, rounded down
+fn weighted_share(amount: u64, weight: u64, total_weight: u64) -> Result<u64> {
+    if total_weight == 0 {
+        return Err(error!(ErrorCode::MathOverflow));
+    }
+    Ok((amount as u128 * weight as u128 / total_weight as u128) as u64)
+}
+
+// count ticket numbers found in the winning numbers, order does not matter
+// each winning number can only be matched once
+fn count_matches(numbers: &[u8; 6], winning_numbers: &[u8; 6]) -> u8 {
+    let mut used = [false; 6];
+    let mut matches = 0;
+    for number in numbers.iter() {
+        for (i, winning_number) in winning_numbers.iter().enumerate() {
+            // unused slots are 0 and never match
+            if !used[i] && *number != 0 && number == winning_number {
+                used[i] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    matches
+}
+
+// quick pick numbers of a roll, in canonical form
+// derived like winning numbers from a hash of the slot hash, user, nonce and roll
+fn quick_pick_numbers(
+    slot_hash: &[u8; 32],
+    user: &Pubkey,
+    nonce: u64,
+    roll: u8,
+    domain: &NumberDomain,
+) -> [u8; 6] {
+    let randomness = hashv(&[slot_hash, user.as_ref(), &nonce.to_le_bytes(), &[roll]]).to_bytes();
+    let mut numbers = derive_winning_numbers(&randomness, domain);
+    numbers[..domain.pick_count as usize].sort();
+    numbers
+}
+
+// prize tier of a ticket matching `matches` of the `pick_count` winning numbers
+// matching every pick wins the top tier, each tier below takes one match less
+fn match_tier(matches: u8, pick_count: u8) -> Option<u8> {
+    let missed = pick_count.checked_sub(matches)? as usize;
+    if matches == 0 || missed >= PRIZE_TIER_COUNT {
+        return None;
+    }
+    Some((PRIZE_TIER_COUNT - 1 - missed) as u8)
+}
+
+// fails if the pool quotes more than slippage_bps below the caller's quote
+// stake and dispense are permissionless, the pool price alone could be moved by whoever sandwiches them
+fn check_quote(pool_amount_out: u64, slippage_bps: u16, expected_amount_out: u64) -> Result<()> {
+    let slippage = (BPS_DENOMINATOR - slippage_bps as u64) as u128;
+    if (pool_amount_out as u128) < expected_amount_out as u128 * slippage / BPS_DENOMINATOR as u128
+    {
+        return Err(error!(ErrorCode::SlippageExceeded));
+    }
+    Ok(())
+}
+
+// pool output less slippage_bps
+fn minimum_amount_out(pool_amount_out: u64, slippage_bps: u16) -> Result<u64> {
+    // nothing would come out of the swap
+    if pool_amount_out == 0 {
+        return Err(error!(ErrorCode::SlippageExceeded));
+    }
+
+    let slippage = (BPS_DENOMINATOR - slippage_bps as u64) as u128;
+    Ok((pool_amount_out as u128 * slippage / BPS_DENOMINATOR as u128) as u64)
+}
+
+// pool output whose minimum_amount_out still covers amount, u64::MAX if no output can
+fn amount_out_before_slippage(amount: u64, slippage_bps: u16) -> u64 {
+    let slippage = (BPS_DENOMINATOR - slippage_bps as u64) as u128;
+    if slippage == 0 {
+        return u64::MAX;
+    }
+
+    // rounded up so the rounded down minimum is not short
+    let value = (amount as u128 * BPS_DENOMINATOR as u128 + slippage - 1) / slippage;
+    std::cmp::min(value, u64::MAX as u128) as u64
+}
+
+// bps basis points of amount, rounded down
+fn bps_of(amount: u64, bps: u16) -> Result<u64> {
+    let value = amount as u128 * bps as u128 / BPS_DENOMINATOR as u128;
+    if value > u64::MAX as u128 {
+        return Err(error!(ErrorCode::MathOverflow));
+    }
+    Ok(value as u64)
+}
+
+// calculate prize to send to winner
+// this function is expected to be called after swapping all yield tokens back to deposit tokens
+fn calculate_prize(total_principal: u64, deposit_vault_amount: u64) -> u64 {
+    // deposit_vault amount - principal of all tickets = prize amount
+    // not enough tokens to pay back every ticket, the pool is insolvent
+    // prize is 0, so we can unlock the vault and continue the lottery
+    deposit_vault_amount.saturating_sub(total_principal)
+}
+
+// deposit tokens paid for a ticket, its share of the pool value when under-collateralized
+fn redemption_amount(ticket_price: u64, total_principal: u64, pool_value: u64) -> u64 {
+    if pool_value >= total_principal {
+        return ticket_price;
+    }
+
+    // total_principal > pool_value >= 0 here
+    (ticket_price as u128 * pool_value as u128 / total_principal as u128) as u64
+}
+
+// flag the pool as insolvent while it is worth less than the deposits
+// PoolInsolvent is emitted when it enters that state
+fn update_solvency(vault_manager: &mut Account<VaultManager>, pool_value: u64) {
+    let insolvent = pool_value < vault_manager.total_principal;
+    if insolvent && !vault_manager.insolvent {
+        emit!(PoolInsolvent {
+            vault_manager: vault_manager.key(),
+            total_principal: vault_manager.total_principal,
+            pool_value,
+            shortfall: vault_manager.total_principal - pool_value,
+        });
+    }
+    vault_manager.insolvent = insolvent;
 }
@@ -0,0 +1,252 @@
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_spl::token::TokenAccount;
+use spl_token_swap::curve::calculator::TradeDirection;
+use spl_token_swap::instruction::{swap, Swap};
+use spl_token_swap::state::{SwapState, SwapVersion};
+use std::sync::Arc;
+
+// where deposit tokens are put to work between draws
+// deposit moves tokens from deposit_vault into the position held in yield_vault,
+// withdraw moves them back, amounts out are bounded by minimum_amount_out
+pub trait YieldSource {
+    fn deposit(
+        &self,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()>;
+
+    fn withdraw(
+        &self,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()>;
+
+    // yield tokens received for depositing amount_in deposit tokens
+    // amounts too small to swap out anything quote 0
+    fn deposit_quote(&self, amount_in: u64) -> Result<u64>;
+
+    // deposit tokens received for withdrawing amount_in yield tokens
+    // amounts too small to swap out anything quote 0
+    fn withdraw_quote(&self, amount_in: u64) -> Result<u64>;
+
+    // fewest yield tokens, at most max_amount_in, to withdraw for at least amount_out deposit tokens
+    // max_amount_in if even that falls short
+    fn withdraw_amount_in(&self, amount_out: u64, max_amount_in: u64) -> Result<u64> {
+        if amount_out == 0 {
+            return Ok(0);
+        }
+        if self.withdraw_quote(max_amount_in)? < amount_out {
+            return Ok(max_amount_in);
+        }
+
+        // quotes grow with amount_in, search the curve for the inverse
+        // quote(low) < amount_out <= quote(high)
+        let (mut low, mut high) = (0, max_amount_in);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if self.withdraw_quote(mid)? < amount_out {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(high)
+    }
+
+    // value of yield_amount yield tokens in deposit tokens
+    fn position_value(&self, yield_amount: u64) -> Result<u64> {
+        if yield_amount == 0 {
+            return Ok(0);
+        }
+        self.withdraw_quote(yield_amount)
+    }
+}
+
+// swaps between deposit and yield tokens through an spl-token-swap pool
+pub struct SplTokenSwap<'info> {
+    pub token_swap_program: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub amm: AccountInfo<'info>,
+    pub amm_authority: AccountInfo<'info>,
+    pub pool_mint: AccountInfo<'info>,
+    pub pool_fee: AccountInfo<'info>,
+    pub swap_deposit_vault: Account<'info, TokenAccount>,
+    pub swap_yield_vault: Account<'info, TokenAccount>,
+    pub deposit_vault: AccountInfo<'info>,
+    pub yield_vault: AccountInfo<'info>,
+    pub vault_manager: AccountInfo<'info>, // owner of the vaults, signs the swap
+    pub swap_state: Arc<dyn SwapState>,    // amm unpacked once, quotes reuse its curve and fees
+}
+
+impl<'info> SplTokenSwap<'info> {
+    // swap from deposit_vault to yield_vault for deposits, the other way for withdrawals
+    fn swap(
+        &self,
+        is_deposit: bool,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let (source, swap_source, swap_destination, destination) = if is_deposit {
+            (
+                self.deposit_vault.clone(),
+                self.swap_deposit_vault.to_account_info(),
+                self.swap_yield_vault.to_account_info(),
+                self.yield_vault.clone(),
+            )
+        } else {
+            (
+                self.yield_vault.clone(),
+                self.swap_yield_vault.to_account_info(),
+                self.swap_deposit_vault.to_account_info(),
+                self.deposit_vault.clone(),
+            )
+        };
+
+        // accounts array
+        let accounts = [
+            self.token_swap_program.clone(),
+            self.token_program.clone(),
+            self.amm.clone(),
+            self.amm_authority.clone(),
+            self.vault_manager.clone(),
+            source.clone(),
+            swap_source.clone(),
+            swap_destination.clone(),
+            destination.clone(),
+            self.pool_mint.clone(),
+            self.pool_fee.clone(),
+        ];
+
+        // set data for swap instruction
+        let data = Swap {
+            amount_in,
+            minimum_amount_out,
+        };
+
+        // create swap instruction
+        let ix = swap(
+            &self.token_swap_program.key(),
+            &self.token_program.key(),
+            &self.amm.key(),
+            &self.amm_authority.key(),
+            &self.vault_manager.key(),
+            &source.key(),
+            &swap_source.key(),
+            &swap_destination.key(),
+            &destination.key(),
+            &self.pool_mint.key(),
+            &self.pool_fee.key(),
+            None,
+            data,
+        )?;
+
+        // vault manager is the swap transfer authority, no tokens are delegated
+        invoke_signed(&ix, &accounts, signer_seeds).map_err(|e| e.into())
+    }
+
+    // output of swapping amount_in through the pool, from its reserves, curve and fees
+    fn quote(
+        &self,
+        swap_source: &Account<'info, TokenAccount>,
+        swap_destination: &Account<'info, TokenAccount>,
+        amount_in: u64,
+    ) -> Result<u64> {
+        let trade_direction = if *self.swap_state.token_a_account() == swap_source.key() {
+            TradeDirection::AtoB
+        } else {
+            TradeDirection::BtoA
+        };
+
+        // the curve math is done in u128 and cannot overflow on u64 amounts, no result means
+        // fees take all of amount_in or nothing is left to swap out
+        let result = self.swap_state.swap_curve().swap(
+            amount_in as u128,
+            swap_source.amount as u128,
+            swap_destination.amount as u128,
+            trade_direction,
+            self.swap_state.fees(),
+        );
+
+        Ok(result.map_or(0, |result| result.destination_amount_swapped as u64))
+    }
+}
+
+impl<'info> YieldSource for SplTokenSwap<'info> {
+    fn deposit(
+        &self,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        self.swap(true, amount_in, minimum_amount_out, signer_seeds)
+    }
+
+    fn withdraw(
+        &self,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        self.swap(false, amount_in, minimum_amount_out, signer_seeds)
+    }
+
+    fn deposit_quote(&self, amount_in: u64) -> Result<u64> {
+        self.quote(&self.swap_deposit_vault, &self.swap_yield_vault, amount_in)
+    }
+
+    fn withdraw_quote(&self, amount_in: u64) -> Result<u64> {
+        self.quote(&self.swap_yield_vault, &self.swap_deposit_vault, amount_in)
+    }
+}
+
+// pool is an initialized spl-token-swap pool trading through these accounts
+pub fn check_spl_token_swap_pool(
+    token_swap_program: &AccountInfo,
+    amm: &AccountInfo,
+    amm_authority: &AccountInfo,
+    swap_deposit_vault: &Account<TokenAccount>,
+    swap_yield_vault: &Account<TokenAccount>,
+    pool_mint: &AccountInfo,
+    pool_fee: &AccountInfo,
+) -> Result<()> {
+    // pools of other swap programs could report any state
+    if token_swap_program.key() != spl_token_swap::id() {
+        return Err(error!(ErrorCode::InvalidYieldSource));
+    }
+
+    let swap_state = SwapVersion::unpack(&amm.try_borrow_data()?)?;
+    if !swap_state.is_initialized() {
+        return Err(error!(ErrorCode::InvalidYieldSource));
+    }
+
+    // pool token accounts can be in either order
+    let token_accounts = [*swap_state.token_a_account(), *swap_state.token_b_account()];
+    if token_accounts != [swap_deposit_vault.key(), swap_yield_vault.key()]
+        && token_accounts != [swap_yield_vault.key(), swap_deposit_vault.key()]
+    {
+        return Err(error!(ErrorCode::InvalidYieldSource));
+    }
+
+    if *swap_state.pool_mint() != pool_mint.key()
+        || *swap_state.pool_fee_account() != pool_fee.key()
+    {
+        return Err(error!(ErrorCode::InvalidYieldSource));
+    }
+
+    // pool authority is a PDA of the swap program
+    let authority = Pubkey::create_program_address(
+        &[&amm.key().to_bytes()[..], &[swap_state.nonce()]],
+        &token_swap_program.key(),
+    )
+    .map_err(|_| error!(ErrorCode::InvalidYieldSource))?;
+    if authority != amm_authority.key() {
+        return Err(error!(ErrorCode::InvalidYieldSource));
+    }
+
+    Ok(())
+}
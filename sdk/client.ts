@@ -3,9 +3,15 @@ import * as spl from "@solana/spl-token";
 import * as tokenSwap from "@solana/spl-token-swap";
 import { Program } from "@project-serum/anchor";
 import { NoLossLottery } from "../target/types/no_loss_lottery";
+import { MockOracle } from "../target/types/mock_oracle";
+import { canonicalNumbers } from "./numbers";
+import { nonceSignature, oracleNonce } from "./oracle";
+import { registryNodeAccounts } from "./registry";
+import { swapQuote } from "./quote";
 import * as dotenv from "dotenv";
 import * as envfile from "envfile";
 import * as fs from "fs";
+import { randomBytes } from "crypto";
 
 interface ClientAccounts {
   depositMint: anchor.web3.PublicKey;
@@ -22,6 +28,65 @@ interface ClientAccounts {
   ammAuthority: anchor.web3.PublicKey;
   poolFee: anchor.web3.PublicKey;
   mintAuthority: anchor.web3.Account;
+  oracle: anchor.web3.Keypair;
+}
+
+// ticket account field offsets, including the 8 byte discriminator
+const TICKET_OWNER_OFFSET = 136;
+const TICKET_VAULT_MANAGER_OFFSET = 174;
+
+// ticket claim account field offsets, including the 8 byte discriminator
+const TICKET_CLAIM_OWNER_OFFSET = 72;
+
+// prize tiers, the top tier matches every pick
+const PRIZE_TIER_COUNT = 4;
+
+// tickets bought by one buy_many instruction
+const MAX_BATCH_SIZE = 4;
+
+// 6 numbers picked from 1..=49, a number can be picked more than once
+const NUMBER_DOMAIN = { pickCount: 6, maxNumber: 49, unique: false };
+
+// time the oracle has to fulfill a draw
+const FULFILL_DURATION_SECONDS = 60 * 60;
+
+// time ticket holders have to register a match after dispense
+const REGISTRATION_DURATION_SECONDS = 24 * 60 * 60;
+
+// time winners have to claim a prize before it rolls back into the pool
+const CLAIM_DURATION_SECONDS = 7 * 24 * 60 * 60;
+
+// share of the prize in bps for 3, 4, 5 and 6 matching numbers
+const PRIZE_TIERS = [500, 1500, 3000, 5000];
+
+// protocol fee in bps taken from each prize
+const FEE_BPS = 1000;
+
+// count ticket numbers found in the winning numbers, order does not matter
+function countMatches(numbers: Array<number>, winningNumbers: Array<number>) {
+  let remaining = [...winningNumbers];
+  let matches = 0;
+  for (const number of numbers) {
+    // unused slots are 0 and never match
+    if (number == 0) {
+      continue;
+    }
+    const i = remaining.indexOf(number);
+    if (i >= 0) {
+      remaining.splice(i, 1);
+      matches++;
+    }
+  }
+  return matches;
+}
+
+// prize tier of a ticket, each tier below the top one takes one match less
+function matchTier(matches: number, pickCount: number): number | null {
+  const missed = pickCount - matches;
+  if (matches == 0 || missed < 0 || missed >= PRIZE_TIER_COUNT) {
+    return null;
+  }
+  return PRIZE_TIER_COUNT - 1 - missed;
 }
 
 // filepath where env file lives
@@ -29,10 +94,12 @@ const envFilePath: string = "clientaccounts.env";
 
 export class Client {
   private program: Program<NoLossLottery>;
+  private oracleProgram: Program<MockOracle>;
 
   constructor() {
     const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
     this.program = program;
+    this.oracleProgram = anchor.workspace.MockOracle as Program<MockOracle>;
   }
 
   // initialize lottery
@@ -46,10 +113,26 @@ export class Client {
       new anchor.web3.PublicKey(userDepositAta)
     );
 
+    // local oracle key signs randomness requests
+    const randomnessOracle = accounts.oracle.publicKey;
+
+    // protocol fees are collected here
+    const treasury = await this.treasuryAddress(accounts.vaultManager);
+
     // init lottery
     await this.program.rpc.initialize(
       new anchor.BN(drawDurationSeconds),
       new anchor.BN(ticketPrice),
+      { oracle: {} },
+      new anchor.BN(0),
+      new anchor.BN(0),
+      new anchor.BN(FULFILL_DURATION_SECONDS),
+      new anchor.BN(REGISTRATION_DURATION_SECONDS),
+      new anchor.BN(CLAIM_DURATION_SECONDS),
+      PRIZE_TIERS,
+      FEE_BPS,
+      { numbers: {} },
+      NUMBER_DOMAIN,
       {
         accounts: {
           depositMint: accounts.depositMint,
@@ -58,6 +141,8 @@ export class Client {
           yieldVault: accounts.yieldVault,
           vaultManager: accounts.vaultManager,
           tickets: accounts.tickets,
+          treasury: treasury,
+          randomnessOracle: randomnessOracle,
           user: this.program.provider.wallet.publicKey,
           systemProgram: anchor.web3.SystemProgram.programId,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
@@ -66,6 +151,28 @@ export class Client {
       }
     );
 
+    // only this pool is accepted by stake, redeem and dispense
+    await this.program.rpc.setYieldSource({
+      accounts: {
+        depositMint: accounts.depositMint,
+        yieldMint: accounts.yieldMint,
+        depositVault: accounts.depositVault,
+        yieldVault: accounts.yieldVault,
+        vaultManager: accounts.vaultManager,
+        swapDepositVault: accounts.swapDepositVault,
+        swapYieldVault: accounts.swapYieldVault,
+        poolMint: accounts.poolMint,
+        poolFee: accounts.poolFee,
+        amm: accounts.amm,
+        ammAuthority: accounts.ammAuthority,
+        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
+        authority: this.program.provider.wallet.publicKey,
+      },
+    });
+
+    // the first draw needs a committed oracle nonce
+    await this.commitOracleNonce();
+
     await spl.mintTo(
       this.program.provider.connection,
       accounts.mintAuthority,
@@ -86,17 +193,69 @@ export class Client {
       this.program.provider.wallet.publicKey
     );
 
-    for (let i = 1; i <= count; i++) {
-      let numbers: Array<number> = [i, 12, 2, 3, 4, 5];
+    // buy in batches, one buy_many instruction per batch
+    for (let first = 1; first <= count; first += MAX_BATCH_SIZE) {
+      const vaultMgrAccount = await this.program.account.vaultManager.fetch(
+        accounts.vaultManager
+      );
 
-      // create ticket PDA
-      const [ticket, _ticketBump] =
-        await anchor.web3.PublicKey.findProgramAddress(
-          [Uint8Array.from(numbers), accounts.vaultManager.toBuffer()],
-          this.program.programId
+      let numbersList = [];
+      let remainingAccounts = [];
+      let slots = [];
+      const last = Math.min(first + MAX_BATCH_SIZE - 1, count);
+      for (let i = first; i <= last; i++) {
+        let numbers: Array<number> = canonicalNumbers([i, 12, 2, 3, 4, 5]);
+        numbersList.push(numbers);
+
+        // ticket is added to the holders of its number combination
+        const combination = await this.combinationAddress(
+          accounts.vaultManager,
+          numbers
         );
+        const combinationAccount =
+          await this.program.account.combination.fetchNullable(combination);
+        const index = combinationAccount
+          ? combinationAccount.nextIndex
+          : new anchor.BN(0);
+
+        // create ticket PDA
+        const [ticket, _ticketBump] =
+          await anchor.web3.PublicKey.findProgramAddress(
+            [
+              Uint8Array.from(numbers),
+              accounts.vaultManager.toBuffer(),
+              index.toArrayLike(Buffer, "le", 8),
+            ],
+            this.program.programId
+          );
+
+        // ticket takes the next registry slot
+        const slot = vaultMgrAccount.ticketCount.addn(i - first);
+        slots.push(slot);
+        const ticketEntry = await this.ticketEntryAddress(
+          accounts.vaultManager,
+          slot
+        );
+
+        for (const pubkey of [combination, ticket, ticketEntry]) {
+          remainingAccounts.push({
+            pubkey: pubkey,
+            isWritable: true,
+            isSigner: false,
+          });
+        }
+      }
 
-      await this.program.rpc.buy(numbers, {
+      // registry tree nodes the new slots weigh in
+      remainingAccounts = remainingAccounts.concat(
+        await registryNodeAccounts(
+          this.program.programId,
+          accounts.vaultManager,
+          slots
+        )
+      );
+
+      await this.program.rpc.buyMany(numbersList, {
         accounts: {
           depositMint: accounts.depositMint,
           depositVault: accounts.depositVault,
@@ -104,7 +263,6 @@ export class Client {
           yieldVault: accounts.yieldVault,
           vaultManager: accounts.vaultManager,
           tickets: accounts.tickets,
-          ticket: ticket,
           userTicketsAta: userTicketsAta,
           user: this.program.provider.wallet.publicKey,
           userDepositAta: accounts.userDepositAta,
@@ -113,6 +271,7 @@ export class Client {
           tokenProgram: spl.TOKEN_PROGRAM_ID,
           rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         },
+        remainingAccounts: remainingAccounts,
       });
     }
     console.log("%d tickets purchased", count);
@@ -131,6 +290,7 @@ export class Client {
         tickets: accounts.tickets,
         vaultManager: accounts.vaultManager,
         user: this.program.provider.wallet.publicKey,
+        slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
@@ -138,75 +298,400 @@ export class Client {
     });
   }
 
-  // stake deposit tokens
-  public async stake() {
+  // commit the nonce of the oracle signature over the next request
+  // the nonce seed is kept in the env file until the request is fulfilled
+  public async commitOracleNonce(): Promise<string> {
     const accounts = await this.readClientAccounts();
 
-    return this.program.rpc.stake({
+    const nonceSeed = randomBytes(32);
+    const env = dotenv.parse(fs.readFileSync(envFilePath));
+    const envFileString = await envfile.stringify({
+      ...env,
+      oracleNonceSeed: nonceSeed.toString("hex"),
+    });
+    fs.writeFileSync(envFilePath, envFileString, "utf-8");
+
+    return this.program.rpc.commitOracleNonce(
+      Array.from(oracleNonce(nonceSeed)),
+      {
+        accounts: {
+          depositMint: accounts.depositMint,
+          depositVault: accounts.depositVault,
+          yieldMint: accounts.yieldMint,
+          yieldVault: accounts.yieldVault,
+          vaultManager: accounts.vaultManager,
+          oracle: accounts.oracle.publicKey,
+        },
+        signers: [accounts.oracle],
+      }
+    );
+  }
+
+  // fulfill the randomness requested by draw using the local mock oracle
+  // the oracle key signs the request with the committed nonce, the lottery
+  // checks the signature, then the nonce of the next request is committed
+  public async fulfill(): Promise<string> {
+    const accounts = await this.readClientAccounts();
+
+    const vaultMgr = await this.program.account.vaultManager.fetch(
+      accounts.vaultManager
+    );
+    const env = dotenv.parse(fs.readFileSync(envFilePath));
+    const signatureIx =
+      anchor.web3.Ed25519Program.createInstructionWithPublicKey({
+        publicKey: accounts.oracle.publicKey.toBytes(),
+        message: Buffer.from(vaultMgr.randomnessRequest),
+        signature: nonceSignature(
+          accounts.oracle,
+          Buffer.from(env.oracleNonceSeed, "hex"),
+          Buffer.from(vaultMgr.randomnessRequest)
+        ),
+      });
+
+    const fulfillTxSig = await this.oracleProgram.rpc.fulfill({
       accounts: {
         depositMint: accounts.depositMint,
         depositVault: accounts.depositVault,
         yieldMint: accounts.yieldMint,
         yieldVault: accounts.yieldVault,
         vaultManager: accounts.vaultManager,
-        swapYieldVault: accounts.swapYieldVault,
-        swapDepositVault: accounts.swapDepositVault,
-        poolMint: accounts.poolMint,
-        amm: accounts.amm,
-        ammAuthority: accounts.ammAuthority,
-        poolFee: accounts.poolFee,
-        user: this.program.provider.wallet.publicKey,
-        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
-        systemProgram: anchor.web3.SystemProgram.programId,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        lotteryProgram: this.program.programId,
       },
+      instructions: [signatureIx],
     });
+
+    await this.commitOracleNonce();
+    return fulfillTxSig;
   }
 
-  // dispense prize to winner
-  public async dispense() {
+  // stake deposit tokens
+  public async stake() {
     const accounts = await this.readClientAccounts();
 
-    // fetch winning numbers
+    // reserve and unclaimed prizes stay in the deposit vault
     const vaultMgrAccount = await this.program.account.vaultManager.fetch(
       accounts.vaultManager
     );
-
-    // create winning ticket PDA
-    const [ticket, ticketBump] = await anchor.web3.PublicKey.findProgramAddress(
-      [
-        Uint8Array.from(vaultMgrAccount.winningNumbers),
-        accounts.vaultManager.toBuffer(),
-      ],
-      this.program.programId
+    const reserved = vaultMgrAccount.depositTokenReserve.add(
+      vaultMgrAccount.unclaimedPrizes
     );
+    const depositAmount = await this.tokenAmount(accounts.depositVault);
+    const amountIn = depositAmount.gt(reserved)
+      ? depositAmount.sub(reserved)
+      : new anchor.BN(0);
 
-    // dispense prize to winner
-    return this.program.rpc.dispense(vaultMgrAccount.winningNumbers, {
+    // the swap fails if the pool moved too far from this quote
+    const expectedAmountOut = await this.poolQuote(accounts, amountIn, true);
+
+    return this.program.rpc.stake(expectedAmountOut, {
       accounts: {
         depositMint: accounts.depositMint,
         depositVault: accounts.depositVault,
         yieldMint: accounts.yieldMint,
         yieldVault: accounts.yieldVault,
-        tickets: accounts.tickets,
         vaultManager: accounts.vaultManager,
-        ticket: ticket,
-        swapYieldVault: accounts.swapYieldVault,
-        swapDepositVault: accounts.swapDepositVault,
-        poolMint: accounts.poolMint,
-        amm: accounts.amm,
-        ammAuthority: accounts.ammAuthority,
-        poolFee: accounts.poolFee,
+        swapPool: this.swapPoolAccounts(accounts),
         user: this.program.provider.wallet.publicKey,
-        userDepositAta: accounts.userDepositAta,
-        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
       },
     });
   }
 
+  // dispense prize to winner
+  public async dispense() {
+    const accounts = await this.readClientAccounts();
+
+    // fetch winning numbers and current draw id
+    const vaultMgrAccount = await this.program.account.vaultManager.fetch(
+      accounts.vaultManager
+    );
+
+    // draw record PDA of the current draw
+    const drawRecord = await this.drawRecordAddress(
+      accounts.vaultManager,
+      vaultMgrAccount.drawId
+    );
+
+    // protocol fee goes to the treasury
+    const treasury = await this.treasuryAddress(accounts.vaultManager);
+
+    // the draw rolls over unless a ticket holds the winning combination
+    const combination = await this.combinationAddress(
+      accounts.vaultManager,
+      canonicalNumbers(vaultMgrAccount.winningNumbers)
+    );
+
+    // the swap of the whole yield position fails if the pool moved too far
+    // from this quote
+    const expectedAmountOut = await this.poolQuote(
+      accounts,
+      await this.tokenAmount(accounts.yieldVault),
+      false
+    );
+
+    // record prizes of the draw
+    return this.program.rpc.dispense(
+      vaultMgrAccount.winningNumbers,
+      expectedAmountOut,
+      {
+        accounts: {
+          depositMint: accounts.depositMint,
+          depositVault: accounts.depositVault,
+          yieldMint: accounts.yieldMint,
+          yieldVault: accounts.yieldVault,
+          tickets: accounts.tickets,
+          vaultManager: accounts.vaultManager,
+          drawRecord: drawRecord,
+          treasury: treasury,
+          swapPool: this.swapPoolAccounts(accounts),
+          user: this.program.provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          { pubkey: combination, isWritable: false, isSigner: false },
+        ],
+      }
+    );
+  }
+
+  // register every ticket of our wallet matching enough numbers in the last draw
+  public async registerMatches(): Promise<Array<string>> {
+    const accounts = await this.readClientAccounts();
+
+    // last dispensed draw
+    const vaultMgrAccount = await this.program.account.vaultManager.fetch(
+      accounts.vaultManager
+    );
+    const drawId = vaultMgrAccount.drawId.subn(1);
+    const drawRecord = await this.drawRecordAddress(
+      accounts.vaultManager,
+      drawId
+    );
+    const drawRecordAccount = await this.program.account.drawRecord.fetch(
+      drawRecord
+    );
+
+    const tickets = await this.program.account.ticket.all([
+      {
+        memcmp: {
+          offset: TICKET_OWNER_OFFSET,
+          bytes: this.program.provider.wallet.publicKey.toBase58(),
+        },
+      },
+      {
+        memcmp: {
+          offset: TICKET_VAULT_MANAGER_OFFSET,
+          bytes: accounts.vaultManager.toBase58(),
+        },
+      },
+    ]);
+
+    let txSigs = [];
+    for (const ticket of tickets) {
+      // skip tickets bought after the draw or without a prize
+      if (ticket.account.drawId.gt(drawId)) {
+        continue;
+      }
+      const matches = countMatches(
+        ticket.account.numbers,
+        drawRecordAccount.winningNumbers
+      );
+      if (
+        matchTier(matches, drawRecordAccount.numberDomain.pickCount) === null
+      ) {
+        continue;
+      }
+
+      const [ticketClaim, _ticketClaimBump] =
+        await anchor.web3.PublicKey.findProgramAddress(
+          [
+            Buffer.from("ticket_claim"),
+            drawRecord.toBuffer(),
+            ticket.publicKey.toBuffer(),
+          ],
+          this.program.programId
+        );
+
+      const txSig = await this.program.rpc.registerMatch({
+        accounts: {
+          depositMint: accounts.depositMint,
+          yieldMint: accounts.yieldMint,
+          depositVault: accounts.depositVault,
+          yieldVault: accounts.yieldVault,
+          vaultManager: accounts.vaultManager,
+          drawRecord: drawRecord,
+          ticket: ticket.publicKey,
+          ticketClaim: ticketClaim,
+          user: this.program.provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+      });
+      txSigs.push(txSig);
+    }
+    return txSigs;
+  }
+
+  // claim every prize registered by our wallet once registration has closed
+  public async claimPrizes(): Promise<Array<string>> {
+    const accounts = await this.readClientAccounts();
+
+    const ticketClaims = await this.program.account.ticketClaim.all([
+      {
+        memcmp: {
+          offset: TICKET_CLAIM_OWNER_OFFSET,
+          bytes: this.program.provider.wallet.publicKey.toBase58(),
+        },
+      },
+    ]);
+
+    // prize is sent to our wallet deposit token ATA
+    const winnerDepositAta = await spl.getAssociatedTokenAddress(
+      accounts.depositMint,
+      this.program.provider.wallet.publicKey
+    );
+
+    const now = Date.now() / 1000;
+    let txSigs = [];
+    for (const ticketClaim of ticketClaims) {
+      const drawRecordAccount =
+        await this.program.account.drawRecord.fetchNullable(
+          ticketClaim.account.drawRecord
+        );
+
+      // skip other lotteries, expired draws and draws still registering
+      if (
+        !drawRecordAccount ||
+        !drawRecordAccount.vaultManager.equals(accounts.vaultManager) ||
+        drawRecordAccount.registrationDeadline.toNumber() >= now
+      ) {
+        continue;
+      }
+
+      const txSig = await this.program.rpc.claimPrize({
+        accounts: {
+          depositMint: accounts.depositMint,
+          yieldMint: accounts.yieldMint,
+          depositVault: accounts.depositVault,
+          yieldVault: accounts.yieldVault,
+          vaultManager: accounts.vaultManager,
+          drawRecord: ticketClaim.account.drawRecord,
+          ticketClaim: ticketClaim.publicKey,
+          winnerDepositAta: winnerDepositAta,
+          winner: this.program.provider.wallet.publicKey,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+      });
+      txSigs.push(txSig);
+    }
+    return txSigs;
+  }
+
+  // draw record PDA of draw `drawId`
+  private async drawRecordAddress(
+    vaultManager: anchor.web3.PublicKey,
+    drawId: anchor.BN
+  ): Promise<anchor.web3.PublicKey> {
+    const [drawRecord, _drawRecordBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          Buffer.from("draw_record"),
+          vaultManager.toBuffer(),
+          drawId.toArrayLike(Buffer, "le", 8),
+        ],
+        this.program.programId
+      );
+    return drawRecord;
+  }
+
+  // treasury token account PDA collecting the protocol fee
+  private async treasuryAddress(
+    vaultManager: anchor.web3.PublicKey
+  ): Promise<anchor.web3.PublicKey> {
+    const [treasury, _treasuryBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Buffer.from("treasury"), vaultManager.toBuffer()],
+        this.program.programId
+      );
+    return treasury;
+  }
+
+  // combination PDA shared by every ticket with the same numbers
+  private async combinationAddress(
+    vaultManager: anchor.web3.PublicKey,
+    numbers: Array<number>
+  ): Promise<anchor.web3.PublicKey> {
+    const [combination, _combinationBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [Uint8Array.from(numbers), vaultManager.toBuffer()],
+        this.program.programId
+      );
+    return combination;
+  }
+
+  private async ticketEntryAddress(
+    vaultManager: anchor.web3.PublicKey,
+    index: anchor.BN
+  ): Promise<anchor.web3.PublicKey> {
+    const [ticketEntry, _ticketEntryBump] =
+      await anchor.web3.PublicKey.findProgramAddress(
+        [
+          Buffer.from("ticket_entry"),
+          vaultManager.toBuffer(),
+          index.toArrayLike(Buffer, "le", 8),
+        ],
+        this.program.programId
+      );
+    return ticketEntry;
+  }
+
+  // balance of a token account
+  private async tokenAmount(
+    tokenAccount: anchor.web3.PublicKey
+  ): Promise<anchor.BN> {
+    const balance =
+      await this.program.provider.connection.getTokenAccountBalance(
+        tokenAccount
+      );
+    return new anchor.BN(balance.value.amount);
+  }
+
+  // output of swapping amountIn deposit tokens, or yield tokens unless
+  // `deposit`, through the pool at its current reserves
+  private async poolQuote(
+    accounts: ClientAccounts,
+    amountIn: anchor.BN,
+    deposit: boolean
+  ): Promise<anchor.BN> {
+    const pool = await tokenSwap.TokenSwap.loadTokenSwap(
+      this.program.provider.connection,
+      accounts.amm,
+      tokenSwap.TOKEN_SWAP_PROGRAM_ID,
+      accounts.mintAuthority
+    );
+    const depositReserve = await this.tokenAmount(accounts.swapDepositVault);
+    const yieldReserve = await this.tokenAmount(accounts.swapYieldVault);
+    return deposit
+      ? swapQuote(amountIn, depositReserve, yieldReserve, pool)
+      : swapQuote(amountIn, yieldReserve, depositReserve, pool);
+  }
+
+  // accounts of the pool stored by set_yield_source, nested as `swap_pool`
+  private swapPoolAccounts(accounts: ClientAccounts) {
+    return {
+      swapYieldVault: accounts.swapYieldVault,
+      swapDepositVault: accounts.swapDepositVault,
+      poolMint: accounts.poolMint,
+      amm: accounts.amm,
+      ammAuthority: accounts.ammAuthority,
+      poolFee: accounts.poolFee,
+      tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
+    };
+  }
+
   // create no loss lottery program accounts
   private async createClientAccounts(
     userDepositAtaAddress?: anchor.web3.PublicKey
@@ -425,9 +910,14 @@ export class Client {
       ammAuthority: tokenSwapAccountAuthority,
       poolFee: feeAccount.address,
       mintAuthority: mintAuthority,
+      oracle: anchor.web3.Keypair.generate(),
     };
 
-    const envFileString = await envfile.stringify(accounts);
+    // oracle secret key is kept for local fulfillments only
+    const envFileString = await envfile.stringify({
+      ...accounts,
+      oracle: JSON.stringify(Array.from(accounts.oracle.secretKey)),
+    });
     fs.writeFileSync(envFilePath, envFileString, "utf-8");
     console.log(envFileString);
 
@@ -473,6 +963,9 @@ export class Client {
       ammAuthority: new anchor.web3.PublicKey(process.env.ammAuthority),
       poolFee: new anchor.web3.PublicKey(process.env.poolFee),
       mintAuthority: new anchor.web3.Account,
+      oracle: anchor.web3.Keypair.fromSecretKey(
+        Uint8Array.from(JSON.parse(process.env.oracle))
+      ),
     };
 
     return accounts;
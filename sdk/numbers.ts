@@ -0,0 +1,6 @@
+// picks sorted ascending with the unused 0 slots last, equivalent picks share
+// a combination
+export function canonicalNumbers(numbers: Array<number>): Array<number> {
+  const picks = numbers.filter((n) => n != 0).sort((a, b) => a - b);
+  return picks.concat(numbers.filter((n) => n == 0));
+}
@@ -0,0 +1,47 @@
+import * as anchor from "@project-serum/anchor";
+import { createHash } from "crypto";
+
+// order of the ed25519 base point
+const ED25519_ORDER = new anchor.BN(
+  "1000000000000000000000000000000014def9dea2f79cd65812631a5cf5d3ed",
+  16
+);
+
+// clamped secret scalar ed25519 derives from a 32 byte seed
+function secretScalar(seed: Uint8Array): anchor.BN {
+  const hash = createHash("sha512").update(seed).digest();
+  hash[0] &= 248;
+  hash[31] &= 127;
+  hash[31] |= 64;
+  return new anchor.BN(hash.subarray(0, 32), "le");
+}
+
+// nonce point R the oracle commits to before a draw, the public key of the
+// nonce seed
+export function oracleNonce(nonceSeed: Uint8Array): Buffer {
+  return anchor.web3.Keypair.fromSeed(nonceSeed).publicKey.toBuffer();
+}
+
+// ed25519 signature of message by the oracle with the committed nonce
+// instead of one derived from the message, a nonce seed must never sign
+// two messages or the oracle key can be recovered from the signatures
+export function nonceSignature(
+  oracle: anchor.web3.Keypair,
+  nonceSeed: Uint8Array,
+  message: Uint8Array
+): Buffer {
+  const nonce = oracleNonce(nonceSeed);
+  const challenge = new anchor.BN(
+    createHash("sha512")
+      .update(nonce)
+      .update(oracle.publicKey.toBuffer())
+      .update(message)
+      .digest(),
+    "le"
+  ).umod(ED25519_ORDER);
+
+  const s = secretScalar(nonceSeed)
+    .add(challenge.mul(secretScalar(oracle.secretKey.subarray(0, 32))))
+    .umod(ED25519_ORDER);
+  return Buffer.concat([nonce, s.toArrayLike(Buffer, "le", 32)]);
+}
@@ -0,0 +1,56 @@
+import * as anchor from "@project-serum/anchor";
+
+// trading and owner fees of an spl-token-swap pool
+export interface SwapFees {
+  tradeFeeNumerator: anchor.BN;
+  tradeFeeDenominator: anchor.BN;
+  ownerTradeFeeNumerator: anchor.BN;
+  ownerTradeFeeDenominator: anchor.BN;
+}
+
+// fee taken by the pool, at least 1 token unless the fee is 0
+function swapFee(
+  amount: anchor.BN,
+  numerator: anchor.BN,
+  denominator: anchor.BN
+): anchor.BN {
+  if (numerator.isZero() || amount.isZero()) {
+    return new anchor.BN(0);
+  }
+  const fee = amount.mul(numerator).div(denominator);
+  return fee.isZero() ? new anchor.BN(1) : fee;
+}
+
+// output of swapping amountIn through a constant product spl-token-swap pool,
+// 0 if nothing would be swapped out
+// stake, dispense and redeem fail if the pool quotes too far below it
+export function swapQuote(
+  amountIn: anchor.BN,
+  sourceReserve: anchor.BN,
+  destinationReserve: anchor.BN,
+  fees: SwapFees
+): anchor.BN {
+  const tradeFee = swapFee(
+    amountIn,
+    fees.tradeFeeNumerator,
+    fees.tradeFeeDenominator
+  );
+  const ownerTradeFee = swapFee(
+    amountIn,
+    fees.ownerTradeFeeNumerator,
+    fees.ownerTradeFeeDenominator
+  );
+  const totalFees = tradeFee.add(ownerTradeFee);
+  if (totalFees.gte(amountIn)) {
+    return new anchor.BN(0);
+  }
+
+  // destination reserve left by the invariant is rounded up
+  const invariant = sourceReserve.mul(destinationReserve);
+  const newSourceReserve = sourceReserve.add(amountIn.sub(totalFees));
+  const quotient = invariant.div(newSourceReserve);
+  const newDestinationReserve = invariant.mod(newSourceReserve).isZero()
+    ? quotient
+    : quotient.addn(1);
+  return destinationReserve.sub(newDestinationReserve);
+}
@@ -0,0 +1,117 @@
+import * as anchor from "@project-serum/anchor";
+import { Program } from "@project-serum/anchor";
+import { NoLossLottery } from "../target/types/no_loss_lottery";
+
+// entries of a registry tree node
+const REGISTRY_FANOUT = 64;
+
+// levels of the registry tree, level 0 entries are registry slots
+const REGISTRY_DEPTH = 3;
+
+// registry tree node PDA at level and index
+export async function registryNodeAddress(
+  programId: anchor.web3.PublicKey,
+  vaultManager: anchor.web3.PublicKey,
+  level: number,
+  index: anchor.BN
+): Promise<anchor.web3.PublicKey> {
+  const [node, _nodeBump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("registry_node"),
+      vaultManager.toBuffer(),
+      Buffer.from([level]),
+      index.toArrayLike(Buffer, "le", 8),
+    ],
+    programId
+  );
+  return node;
+}
+
+// registry tree nodes on the paths from the slots up to the root, each once,
+// as remaining accounts of buy, buy_many and redeem
+export async function registryNodeAccounts(
+  programId: anchor.web3.PublicKey,
+  vaultManager: anchor.web3.PublicKey,
+  slots: Array<anchor.BN>
+) {
+  const nodes = new Map<string, anchor.web3.PublicKey>();
+  for (const slot of slots) {
+    let span = new anchor.BN(1);
+    for (let level = 0; level < REGISTRY_DEPTH; level++) {
+      span = span.muln(REGISTRY_FANOUT);
+      const node = await registryNodeAddress(
+        programId,
+        vaultManager,
+        level,
+        slot.div(span)
+      );
+      nodes.set(node.toBase58(), node);
+    }
+  }
+  return Array.from(nodes.values()).map((node) => ({
+    pubkey: node,
+    isWritable: true,
+    isSigner: false,
+  }));
+}
+
+// registry slot dispense draws from the randomness of the pending draw and
+// the tree nodes it reads on the way down from the root, a ticket's odds are
+// the seconds it was held during the draw period
+export async function drawRegistrySlot(
+  program: Program<NoLossLottery>,
+  vaultManager: anchor.web3.PublicKey
+): Promise<[anchor.BN, Array<anchor.web3.PublicKey>]> {
+  const vaultMgrAccount = await program.account.vaultManager.fetch(
+    vaultManager
+  );
+  const periodStart: anchor.BN = vaultMgrAccount.periodStart;
+  const fullWeight = vaultMgrAccount.drawnAt.sub(periodStart).addn(1);
+
+  let index = new anchor.BN(0);
+  let target = new anchor.BN(0);
+  const nodes = [];
+  for (let level = REGISTRY_DEPTH - 1; level >= 0; level--) {
+    const node = await registryNodeAddress(
+      program.programId,
+      vaultManager,
+      level,
+      index
+    );
+    nodes.push(node);
+    const nodeAccount = await program.account.registryNode.fetch(node);
+
+    // counts and sums of tickets bought during the period are stale if the
+    // node was last updated in an earlier one
+    const current = nodeAccount.drawId.eq(vaultMgrAccount.drawId);
+    const weights = nodeAccount.entries.map((entry) => {
+      const weight = fullWeight.muln(entry.count);
+      return current
+        ? weight
+            .add(periodStart.muln(entry.newCount))
+            .sub(entry.newPurchasedSum)
+        : weight;
+    });
+
+    // the winning weight is drawn out of the total the root sums
+    if (level == REGISTRY_DEPTH - 1) {
+      const total = weights.reduce(
+        (sum: anchor.BN, weight: anchor.BN) => sum.add(weight),
+        new anchor.BN(0)
+      );
+      target = new anchor.BN(
+        vaultMgrAccount.randomness.slice(24, 32),
+        "le"
+      ).mod(total);
+    }
+
+    // go down to the entry whose range of weight holds the target
+    let child = 0;
+    while (target.gte(weights[child])) {
+      target = target.sub(weights[child]);
+      child++;
+    }
+    index = index.muln(REGISTRY_FANOUT).addn(child);
+  }
+  return [index, nodes];
+}
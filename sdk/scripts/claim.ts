@@ -0,0 +1,10 @@
+import * as client from "../client";
+
+async function claim() {
+  const lotteryClient = new client.Client();
+
+  const claimTxSigs = await lotteryClient.claimPrizes();
+  console.log("claimTxSigs:", claimTxSigs);
+}
+
+claim();
@@ -0,0 +1,10 @@
+import * as client from "../client";
+
+async function fulfill() {
+  const lotteryClient = new client.Client();
+
+  const fulfillTxSig = await lotteryClient.fulfill();
+  console.log("fulfillTxSig:", fulfillTxSig);
+}
+
+fulfill();
@@ -0,0 +1,10 @@
+import * as client from "../client";
+
+async function register() {
+  const lotteryClient = new client.Client();
+
+  const registerTxSigs = await lotteryClient.registerMatches();
+  console.log("registerTxSigs:", registerTxSigs);
+}
+
+register();
@@ -2,8 +2,17 @@ import * as anchor from "@project-serum/anchor";
 import * as spl from "@solana/spl-token";
 import * as assert from "assert";
 import * as tokenSwap from "@solana/spl-token-swap";
+import * as crypto from "crypto";
 import { Program } from "@project-serum/anchor";
 import { NoLossLottery } from "../target/types/no_loss_lottery";
+import { MockOracle } from "../target/types/mock_oracle";
+import { nonceSignature, oracleNonce } from "../sdk/oracle";
+import {
+  drawRegistrySlot,
+  registryNodeAccounts,
+  registryNodeAddress,
+} from "../sdk/registry";
+import { swapQuote } from "../sdk/quote";
 
 const DEPOSIT_VAULT = "DEPOSIT_VAULT";
 const DEPOSIT_MINT = "DEPOSIT_MINT";
@@ -20,10 +29,55 @@ const POOL_MINT = "POOL_MINT";
 const TOKEN_SWAP_ACCOUNT = "TOKEN_SWAP_ACCOUNT";
 const TOKEN_SWAP_ACCOUNT_AUTHORITY = "TOKEN_SWAP_ACCOUNT_AUTHORITY";
 const POOL_FEE = "POOL_FEE";
+const RANDOMNESS_ORACLE = "RANDOMNESS_ORACLE";
+const TREASURY = "TREASURY";
+
+// one pick from a single number, every ticket matches every draw
+const SURE_WIN_DOMAIN = { pickCount: 1, maxNumber: 1, unique: false };
+const SURE_WIN_NUMBERS = [1, 0, 0, 0, 0, 0];
+
+// trading fees of the test pool
+const TRADING_FEE_NUMERATOR = 25;
+const TRADING_FEE_DENOMINATOR = 10000;
+const OWNER_TRADING_FEE_NUMERATOR = 5;
+const OWNER_TRADING_FEE_DENOMINATOR = 10000;
+
+// deposit tokens the committer posts in commit-reveal mode
+const COMMIT_BOND = 2;
 
 interface Config {
   keys: Map<String, anchor.web3.PublicKey>;
   mintAuthority: anchor.web3.Account;
+  oracle: anchor.web3.Keypair;
+  oracleNonceSeed: Uint8Array | null; // seed of the committed oracle nonce
+}
+
+// additional wallet taking part in a lottery
+interface User {
+  keypair: anchor.web3.Account;
+  depositAta: anchor.web3.PublicKey;
+  ticketsAta: anchor.web3.PublicKey;
+}
+
+// optional lottery settings passed to initialize
+interface InitializeOptions {
+  randomnessMode?: object;
+  revealDurationSeconds?: number;
+  commitBond?: number;
+  fulfillDurationSeconds?: number;
+  registrationDurationSeconds?: number;
+  claimDurationSeconds?: number;
+  prizeTiers?: Array<number>;
+  feeBps?: number;
+  lotteryMode?: object;
+  numberDomain?: NumberDomain;
+}
+
+// numbers a ticket can pick and the draw can derive
+interface NumberDomain {
+  pickCount: number;
+  maxNumber: number;
+  unique: boolean;
 }
 
 describe("Buy", () => {
@@ -83,23 +137,55 @@ describe("Buy", () => {
     assert.rejects(async () => await buy(program, numbers, config, null));
   });
 
-  it("Buy ticket with duplicate ticket numbers", async () => {
+  it("Buy two tickets with the same numbers", async () => {
     const drawDurationSeconds = 1;
 
     const config = await initialize(program, drawDurationSeconds);
     const numbers = [1, 2, 3, 4, 5, 6];
 
-    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    const [ticketA, ticketBumpA] = await buy(program, numbers, config, null);
     await assertBalance(program, config.keys.get(USER_TICKET_ATA), 1);
 
-    const userKey = await program.account.ticket.fetch(ticket);
+    const [ticketB, ticketBumpB] = await buy(program, numbers, config, null);
+    await assertBalance(program, config.keys.get(USER_TICKET_ATA), 2);
+
+    assertPublicKey(assert.notEqual, ticketA, ticketB);
+    const ticketAccountA = await program.account.ticket.fetch(ticketA);
+    const ticketAccountB = await program.account.ticket.fetch(ticketB);
+    assert.equal(ticketAccountA.index.toNumber(), 0);
+    assert.equal(ticketAccountB.index.toNumber(), 1);
+
+    const combinationAccount = await program.account.combination.fetch(
+      await combinationAddress(program, config, numbers)
+    );
+    assert.equal(combinationAccount.count.toNumber(), 2);
+    assert.equal(combinationAccount.nextIndex.toNumber(), 2);
+  });
+
+  it("Buy ticket with the same numbers as another user", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const numbers = [1, 2, 3, 4, 5, 6];
+
+    const [ticketA, ticketBumpA] = await buy(program, numbers, config, null);
+
+    const otherUser = await newUser(program, config, 1);
+    const [ticketB, ticketBumpB] = await buy(
+      program,
+      numbers,
+      config,
+      null,
+      otherUser
+    );
+    await assertBalance(program, otherUser.ticketsAta, 1);
+
+    const ticketAccountB = await program.account.ticket.fetch(ticketB);
     assertPublicKey(
       assert.equal,
-      program.provider.wallet.publicKey,
-      userKey.owner
+      otherUser.keypair.publicKey,
+      ticketAccountB.owner
     );
-
-    assert.rejects(async () => await buy(program, numbers, config, null));
   });
 
   it("Buy ticket with different numbers", async () => {
@@ -148,6 +234,285 @@ describe("Buy", () => {
 
     assert.rejects(async () => await buy(program, numbersB, config, null));
   });
+
+  it("Buy tickets with the same numbers in any order", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds, 2);
+
+    // numbers are sorted on-chain, the ticket holds the sorted pick
+    const [ticket, _ticketBump] = await buy(
+      program,
+      [6, 5, 4, 3, 2, 1],
+      config,
+      null
+    );
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const ticketAccount = await program.account.ticket.fetch(ticket);
+    assert.deepEqual(ticketAccount.numbers, [1, 2, 3, 4, 5, 6]);
+
+    const combinationAccount = await program.account.combination.fetch(
+      await combinationAddress(program, config, [1, 2, 3, 4, 5, 6])
+    );
+    assert.equal(combinationAccount.count.toNumber(), 2);
+  });
+
+  it("Buy ticket with a number above the max number", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+
+    await buy(
+      program,
+      [1, 2, 3, 4, 5, 50],
+      config,
+      program.idl.errors[2].code
+    );
+    await buy(program, [1, 2, 3, 4, 5, 49], config, null);
+  });
+
+  it("Buy ticket with duplicate numbers", async () => {
+    const drawDurationSeconds = 1;
+
+    // duplicates are only rejected by unique domains
+    const config = await initialize(program, drawDurationSeconds, 2);
+    await buy(program, [1, 1, 2, 3, 4, 5], config, null);
+
+    const uniqueConfig = await initialize(program, drawDurationSeconds, 2, 0, {
+      numberDomain: { pickCount: 6, maxNumber: 49, unique: true },
+    });
+    await buy(
+      program,
+      [1, 1, 2, 3, 4, 5],
+      uniqueConfig,
+      program.idl.errors[2].code
+    );
+    await buy(program, [1, 2, 3, 4, 5, 6], uniqueConfig, null);
+  });
+
+  it("Buy ticket with more numbers than the pick count", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      numberDomain: { pickCount: 5, maxNumber: 49, unique: false },
+    });
+
+    await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      program.idl.errors[2].code
+    );
+    await buy(program, [1, 2, 3, 4, 5, 0], config, null);
+  });
+});
+
+describe("Buy many", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  // tickets bought by one buy_many instruction
+  const MAX_BATCH_SIZE = 4;
+
+  it("Buy the max batch of tickets in one instruction", async () => {
+    const drawDurationSeconds = 60;
+
+    const config = await initialize(program, drawDurationSeconds, 10);
+
+    // two tickets of the batch share a combination
+    const numbersList = [
+      [1, 2, 3, 4, 5, 6],
+      [6, 5, 4, 3, 2, 1],
+      [7, 8, 9, 10, 11, 12],
+      [13, 14, 15, 16, 17, 18],
+    ];
+    assert.equal(numbersList.length, MAX_BATCH_SIZE);
+    const tickets = await buyMany(program, config, numbersList, null);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_TICKET_ATA),
+      MAX_BATCH_SIZE
+    );
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      10 - MAX_BATCH_SIZE
+    );
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.ticketCount.toNumber(), MAX_BATCH_SIZE);
+    assert.equal(vaultMgrAccount.totalPrincipal.toNumber(), MAX_BATCH_SIZE);
+
+    for (let i = 0; i < tickets.length; i++) {
+      const [ticket, _ticketBump] = tickets[i];
+      const ticketAccount = await program.account.ticket.fetch(ticket);
+      assert.ok(ticketAccount.owner.equals(program.provider.wallet.publicKey));
+      assert.deepEqual(
+        ticketAccount.numbers,
+        canonicalNumbers(numbersList[i])
+      );
+      assert.equal(ticketAccount.registryIndex.toNumber(), i);
+    }
+
+    const combinationAccount = await program.account.combination.fetch(
+      await combinationAddress(program, config, [1, 2, 3, 4, 5, 6])
+    );
+    assert.equal(combinationAccount.count.toNumber(), 2);
+    assert.equal(combinationAccount.nextIndex.toNumber(), 2);
+
+    // batch tickets redeem like any other ticket
+    const [ticket, ticketBump] = tickets[0];
+    await redeem(program, config, ticket, ticketBump, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_TICKET_ATA),
+      MAX_BATCH_SIZE - 1
+    );
+  });
+
+  it("Buy many after single buys", async () => {
+    const drawDurationSeconds = 60;
+
+    const config = await initialize(program, drawDurationSeconds, 10);
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const tickets = await buyMany(
+      program,
+      config,
+      [
+        [1, 2, 3, 4, 5, 6],
+        [7, 8, 9, 10, 11, 12],
+      ],
+      null
+    );
+    const ticketAccount = await program.account.ticket.fetch(tickets[0][0]);
+    assert.equal(ticketAccount.index.toNumber(), 1);
+    assert.equal(ticketAccount.registryIndex.toNumber(), 1);
+  });
+
+  it("Buy many rejects invalid batches", async () => {
+    const drawDurationSeconds = 60;
+
+    const config = await initialize(program, drawDurationSeconds, 10);
+
+    // empty batch
+    await buyMany(program, config, [], program.idl.errors[49].code);
+
+    // one ticket more than the max batch
+    await buyMany(
+      program,
+      config,
+      [
+        [1, 2, 3, 4, 5, 6],
+        [7, 8, 9, 10, 11, 12],
+        [13, 14, 15, 16, 17, 18],
+        [19, 20, 21, 22, 23, 24],
+        [25, 26, 27, 28, 29, 30],
+      ],
+      program.idl.errors[49].code
+    );
+
+    // invalid numbers fail the whole batch
+    await buyMany(
+      program,
+      config,
+      [
+        [1, 2, 3, 4, 5, 6],
+        [1, 2, 3, 4, 5, 50],
+      ],
+      program.idl.errors[2].code
+    );
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 10);
+  });
+});
+
+describe("Quick pick", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Buy a quick pick ticket", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const [ticket, numbers] = await buyQuickPick(
+      program,
+      config,
+      new anchor.BN(0),
+      null
+    );
+    await assertBalance(program, config.keys.get(USER_TICKET_ATA), 1);
+
+    const ticketAccount = await program.account.ticket.fetch(ticket);
+    assert.deepEqual(ticketAccount.numbers, numbers);
+    assert.ok(ticketAccount.owner.equals(program.provider.wallet.publicKey));
+    numbers.forEach((n, i) => {
+      assert.ok(n >= 1 && n <= 49);
+      assert.ok(i == 0 || numbers[i - 1] <= n);
+    });
+  });
+
+  it("Quick pick re-rolls past an existing combination", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const slotAndHash = await latestSlotHash(program);
+    const nonce = new anchor.BN(7);
+
+    // same slot and nonce pick the same first roll
+    const [firstTicket, firstNumbers] = await buyQuickPick(
+      program,
+      config,
+      nonce,
+      null,
+      null,
+      slotAndHash
+    );
+    const [secondTicket, secondNumbers] = await buyQuickPick(
+      program,
+      config,
+      nonce,
+      null,
+      null,
+      slotAndHash
+    );
+    assert.notDeepEqual(firstNumbers, secondNumbers);
+    assert.ok(!firstTicket.equals(secondTicket));
+
+    const combinationAccount = await program.account.combination.fetch(
+      await combinationAddress(program, config, secondNumbers)
+    );
+    assert.equal(combinationAccount.count.toNumber(), 1);
+  });
+
+  it("Quick pick rejects numbers it did not derive", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const [slot, _slotHash] = await latestSlotHash(program);
+    const numbers = [1, 2, 3, 4, 5, 6];
+    const [accounts, _ticket, _ticketBump] = await buyAccounts(
+      program,
+      config,
+      numbers,
+      null
+    );
+
+    try {
+      await program.rpc.buyQuickPick(numbers, slot, new anchor.BN(0), {
+        accounts: {
+          buy: accounts,
+          slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+        },
+      });
+      assert.fail("numbers were not the quick pick");
+    } catch (e) {
+      assert.equal(e.code, program.idl.errors[48].code);
+    }
+  });
 });
 
 describe("Redeem", () => {
@@ -175,6 +540,42 @@ describe("Redeem", () => {
     await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 1);
   });
 
+  it("Redeem ticket before the yield source is set", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+
+    // pool accounts stored on vault_manager are still unset, nothing is staked
+    for (const key of [
+      SWAP_YIELD_VAULT,
+      SWAP_DEPOSIT_VAULT,
+      POOL_MINT,
+      TOKEN_SWAP_ACCOUNT,
+      TOKEN_SWAP_ACCOUNT_AUTHORITY,
+      POOL_FEE,
+    ]) {
+      config.keys.set(key, anchor.web3.PublicKey.default);
+    }
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await redeem(program, config, ticket, ticketBump, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance
+    );
+  });
+
   it("Redeem 2 tickets", async () => {
     const drawDurationSeconds = 1;
     const config = await initialize(program, drawDurationSeconds, 2);
@@ -197,6 +598,28 @@ describe("Redeem", () => {
     await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 2);
   });
 
+  it("Redeem ticket sharing numbers with another ticket", async () => {
+    const drawDurationSeconds = 1;
+    const config = await initialize(program, drawDurationSeconds, 2);
+    await tokenSwapInit(program, config);
+
+    // choose your lucky numbers!
+    const numbers = [1, 2, 3, 4, 5, 6];
+
+    const [ticket1, ticketBump1] = await buy(program, numbers, config, null);
+    const [ticket2, ticketBump2] = await buy(program, numbers, config, null);
+
+    await redeem(program, config, ticket1, ticketBump1, null);
+
+    // second ticket is still held
+    const combinationAccount = await program.account.combination.fetch(
+      await combinationAddress(program, config, numbers)
+    );
+    assert.equal(combinationAccount.count.toNumber(), 1);
+    await program.account.ticket.fetch(ticket2);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 1);
+  });
+
   it("Redeem same ticket twice", async () => {
     const drawDurationSeconds = 1;
     const config = await initialize(program, drawDurationSeconds);
@@ -258,563 +681,5327 @@ describe("Redeem", () => {
       totalTicketsPurchased + 1
     );
   });
-});
 
-describe("Draw", () => {
-  // Configure the client to use the local cluster.
-  anchor.setProvider(anchor.Provider.env());
-
-  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
-
-  it("Draw numbers", async () => {
+  it("Redeem ticket owned by another user", async () => {
     const drawDurationSeconds = 1;
-
     const config = await initialize(program, drawDurationSeconds, 1);
+    await tokenSwapInit(program, config);
 
-    // choose your lucky numbers!
+    // victim buys a ticket
     const numbers = [1, 2, 3, 4, 5, 6];
-
     const [ticket, ticketBump] = await buy(program, numbers, config, null);
 
-    // wait for cutoff_time to expire
-    await sleep(drawDurationSeconds + 1);
+    // attacker holds a ticket token of the same lottery
+    const attacker = await newUser(program, config, 1);
+    await buy(program, [7, 8, 9, 10, 11, 12], config, null, attacker);
 
-    await draw(program, config, null);
+    // attacker tries to redeem the victim ticket
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[23].code,
+      attacker
+    );
+
+    // victim ticket is still open and attacker did not get tokens
+    const ticketAccount = await program.account.ticket.fetch(ticket);
+    assertPublicKey(
+      assert.equal,
+      program.provider.wallet.publicKey,
+      ticketAccount.owner
+    );
+    await assertBalance(program, attacker.depositAta, 0);
+    await assertBalance(program, attacker.ticketsAta, 1);
   });
 
-  it("Draw without any tickets purchased", async () => {
+  it("Redeem ticket from another lottery", async () => {
     const drawDurationSeconds = 1;
-
     const config = await initialize(program, drawDurationSeconds, 1);
+    await tokenSwapInit(program, config);
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
 
-    await draw(program, config, program.idl.errors[3].code);
+    // ticket bought in a second lottery
+    const otherConfig = await initialize(program, drawDurationSeconds, 1);
+    const [otherTicket, otherTicketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      otherConfig,
+      null
+    );
+
+    // redeem the second lottery ticket against the first lottery vault
+    await assert.rejects(
+      async () =>
+        await redeem(program, config, otherTicket, otherTicketBump, null)
+    );
+
+    // ticket is still open in the second lottery
+    const ticketAccount = await program.account.ticket.fetch(otherTicket);
+    assertPublicKey(
+      assert.equal,
+      otherConfig.keys.get(VAULT_MANAGER),
+      ticketAccount.vaultManager
+    );
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 0);
   });
 
-  it("Draw before cutoff_time", async () => {
-    const drawDurationSeconds = 1;
+  it("Redeem ticket before the holding period", async () => {
+    const drawDurationSeconds = 60;
+    const minHoldingPeriodSeconds = 2;
 
-    const config = await initialize(program, drawDurationSeconds, 1);
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+    await updateConfig(
+      program,
+      config,
+      { minHoldingPeriod: new anchor.BN(minHoldingPeriodSeconds) },
+      null
+    );
 
-    // choose your lucky numbers!
     const numbers = [1, 2, 3, 4, 5, 6];
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
 
-    await buy(program, numbers, config, null);
+    const ticketAccount = await program.account.ticket.fetch(ticket);
+    assert.ok(ticketAccount.purchasedAt.toNumber() > 0);
 
-    await draw(program, config, program.idl.errors[0].code);
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[45].code
+    );
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 99);
+
+    await sleep(minHoldingPeriodSeconds + 1);
+    await redeem(program, config, ticket, ticketBump, null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 100);
   });
 
-  it("Draw multiple times", async () => {
+  it("Redeem ticket while a draw is in progress", async () => {
     const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
 
-    const config = await initialize(program, drawDurationSeconds, 1);
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance
+    );
+    await tokenSwapInit(program, config);
 
-    // choose your lucky numbers!
     const numbers = [1, 2, 3, 4, 5, 6];
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
 
-    await buy(program, numbers, config, null);
-
-    // wait for cutoff_time to expire
+    // locked from draw until dispense
     await sleep(drawDurationSeconds + 1);
-
     await draw(program, config, null);
-    await draw(program, config, program.idl.errors[1].code);
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[44].code
+    );
+
+    const winningNumbers = await fulfill(program, config);
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[44].code
+    );
+
+    await dispense(program, config, winningNumbers, null);
+    await redeem(program, config, ticket, ticketBump, null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 1);
   });
+});
 
-  it("Attempt to buy ticket between draw and dispense", async () => {
+describe("Draw", () => {
+  // Configure the client to use the local cluster.
+  anchor.setProvider(anchor.Provider.env());
+
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Draw numbers", async () => {
     const drawDurationSeconds = 1;
 
     const config = await initialize(program, drawDurationSeconds, 1);
 
     // choose your lucky numbers!
-    const numbers1 = [1, 2, 3, 4, 5, 6];
+    const numbers = [1, 2, 3, 4, 5, 6];
 
-    await buy(program, numbers1, config, null);
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
 
     // wait for cutoff_time to expire
     await sleep(drawDurationSeconds + 1);
 
     await draw(program, config, null);
 
-    // choose your lucky numbers!
-    const numbers2 = [1, 2, 3, 4, 5, 7];
-
-    // call buy without calling dispense
-    await buy(program, numbers2, config, program.idl.errors[1].code);
+    // numbers are not set until the oracle responds
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.deepEqual(vaultMgrAccount.winningNumbers, [0, 0, 0, 0, 0, 0]);
+    assert.equal(vaultMgrAccount.randomnessPending, true);
   });
-});
-
-describe("Dispense", () => {
-  anchor.setProvider(anchor.Provider.env());
-  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
 
-  it("Call dispense after draw, winner found", async () => {
+  it("Fulfill randomness after draw", async () => {
     const drawDurationSeconds = 1;
-    const userDepositAtaBalance = 1;
-    const yieldVaultInitBalance = 10;
 
-    const config = await initialize(
-      program,
-      drawDurationSeconds,
-      userDepositAtaBalance,
-      yieldVaultInitBalance
-    );
-    await tokenSwapInit(program, config);
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    // buy winning ticket
+    // choose your lucky numbers!
     const numbers = [1, 2, 3, 4, 5, 6];
+
     await buy(program, numbers, config, null);
 
+    // wait for cutoff_time to expire
     await sleep(drawDurationSeconds + 1);
 
-    // draw winning ticket
     await draw(program, config, null);
 
-    // dispense prize to winner
-    await dispense(program, config, numbers, null);
+    // the randomness is the hash of the oracle signature over the request
+    const request = (
+      await program.account.vaultManager.fetch(config.keys.get(VAULT_MANAGER))
+    ).randomnessRequest;
+    const randomness = oracleRandomness(config, request);
 
-    // check the deposit vault only contains the amount from the ticket purchase
-    await assertBalance(
-      program,
-      config.keys.get(DEPOSIT_VAULT),
-      userDepositAtaBalance
-    );
+    const winningNumbers = await fulfill(program, config);
 
-    // check user received prize amount - fees
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      yieldVaultInitBalance - 3 // swap fees reduce amount returned as prize
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.deepEqual(vaultMgrAccount.randomness, randomness);
+    assert.deepEqual(vaultMgrAccount.randomnessProof, request);
+    assert.deepEqual(
+      winningNumbers,
+      deriveNumbers(randomness, { pickCount: 6, maxNumber: 49, unique: false })
     );
+    assert.equal(vaultMgrAccount.randomnessPending, false);
   });
 
-  it("Call dispense after draw, no winner", async () => {
+  it("Fulfill randomness without draw", async () => {
     const drawDurationSeconds = 1;
-    const userDepositAtaBalance = 10;
-    const config = await initialize(
-      program,
-      drawDurationSeconds,
-      userDepositAtaBalance
-    );
-    await tokenSwapInit(program, config);
-
-    // deliberatly choose a non winning combination
-    const numbers = [7, 8, 9, 10, 11, 12];
-
-    const [ticket, ticketBump] = await buy(program, numbers, config, null);
 
-    // wait for cutoff_time to expire
-    await sleep(drawDurationSeconds + 1);
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    await draw(program, config, null);
+    // choose your lucky numbers!
+    const numbers = [1, 2, 3, 4, 5, 6];
 
-    await dispense(program, config, numbers, program.idl.errors[4].code);
+    await buy(program, numbers, config, null);
 
-    await assertBalance(program, config.keys.get(USER_TICKET_ATA), 1);
-    // subtract 1 to account for a ticket purchase
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      userDepositAtaBalance - 1
-    );
+    await fulfill(program, config, program.idl.errors[9].code);
   });
 
-  it("Call dispense with no draw", async () => {
+  it("Fulfill randomness twice", async () => {
     const drawDurationSeconds = 1;
-    const userDepositAtaBalance = 10;
-    const config = await initialize(
-      program,
-      drawDurationSeconds,
-      userDepositAtaBalance
-    );
-    await tokenSwapInit(program, config);
 
-    // deliberatly choose a non winning combination
-    const numbers = [7, 8, 9, 10, 11, 12];
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    // choose your lucky numbers!
+    const numbers = [1, 2, 3, 4, 5, 6];
+
+    await buy(program, numbers, config, null);
 
     // wait for cutoff_time to expire
     await sleep(drawDurationSeconds + 1);
 
-    await dispense(program, config, numbers, program.idl.errors[4].code);
-
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      userDepositAtaBalance - 1
-    );
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await fulfill(program, config, program.idl.errors[9].code);
   });
 
-  it("Call dispense twice in a row without winning PDA", async () => {
+  it("Fulfill randomness with an unknown oracle", async () => {
     const drawDurationSeconds = 1;
-    const userDepositAtaBalance = 10;
-    const config = await initialize(
-      program,
-      drawDurationSeconds,
-      userDepositAtaBalance
-    );
-    await tokenSwapInit(program, config);
 
-    // deliberatly choose a non winning combination
-    const numbers = [7, 8, 9, 10, 11, 12];
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    // choose your lucky numbers!
+    const numbers = [1, 2, 3, 4, 5, 6];
+
+    await buy(program, numbers, config, null);
 
     // wait for cutoff_time to expire
     await sleep(drawDurationSeconds + 1);
 
-    // calling dispense passing in non winning PDA
-    await dispense(program, config, numbers, program.idl.errors[4].code);
-
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      userDepositAtaBalance - 1
-    );
-
-    // call it again
-    await dispense(program, config, numbers, program.idl.errors[4].code);
+    await draw(program, config, null);
 
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      userDepositAtaBalance - 1
-    );
+    // sign with a keypair that is not the configured oracle
+    const fakeOracle = anchor.web3.Keypair.generate();
+    await fulfill(program, config, program.idl.errors[8].code, fakeOracle);
   });
 
-  it("Call dispense twice, first without winning PDA second time with winning PDA", async () => {
+  it("Fulfill randomness with a signature over another message", async () => {
     const drawDurationSeconds = 1;
-    const userDepositAtaBalance = 10;
-    const config = await initialize(
-      program,
-      drawDurationSeconds,
-      userDepositAtaBalance
-    );
-    await tokenSwapInit(program, config);
 
-    // deliberatly choose a non winning combination
-    const numbers = [7, 8, 9, 10, 11, 12];
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    // choose your lucky numbers!
+    const numbers = [1, 2, 3, 4, 5, 6];
+
+    await buy(program, numbers, config, null);
 
     // wait for cutoff_time to expire
     await sleep(drawDurationSeconds + 1);
 
     await draw(program, config, null);
 
-    // calling dispense passing in non winning PDA
-    await dispense(program, config, numbers, program.idl.errors[4].code);
-
-    await assertBalance(
+    // the oracle cannot choose what it signs
+    const message = new Array(32).fill(7);
+    await fulfill(
       program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      userDepositAtaBalance - 1
+      config,
+      program.idl.errors[10].code,
+      config.oracle,
+      message
     );
+  });
 
-    // get winning numbers from vault_manager in prod
-    const winningNumbers = [1, 2, 3, 4, 5, 6];
+  it("Draw without any tickets purchased", async () => {
+    const drawDurationSeconds = 1;
 
-    await dispense(program, config, winningNumbers, null);
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      userDepositAtaBalance - 1
-    );
+    await draw(program, config, program.idl.errors[3].code);
   });
 
-  it("Winning ticket chosen twice dispense prize twice", async () => {
+  it("Draw before cutoff_time", async () => {
     const drawDurationSeconds = 1;
-    const userDepositAtaBalance = 10;
-    const yieldVaultInitBalance = 100;
-    const config = await initialize(
-      program,
-      drawDurationSeconds,
-      userDepositAtaBalance,
-      yieldVaultInitBalance
-    );
-    await tokenSwapInit(program, config);
 
-    // choose winning numbers
+    const config = await initialize(program, drawDurationSeconds, 1);
+
+    // choose your lucky numbers!
     const numbers = [1, 2, 3, 4, 5, 6];
 
-    // buy winning ticket
     await buy(program, numbers, config, null);
 
-    // wait for cutoff_time to expire
-    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, program.idl.errors[0].code);
+  });
 
-    await draw(program, config, null);
+  it("Draw multiple times", async () => {
+    const drawDurationSeconds = 1;
 
-    // call dispense with winning pda
-    await dispense(program, config, numbers, program.idl.errors[4].code);
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    // assert winning user got the prize
-    // subtract 1 for the ticket purchase
-    // subtract 3 for swap fees
-    const expectedUserDepositATABalance =
-      yieldVaultInitBalance + userDepositAtaBalance - 1 - 3;
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      expectedUserDepositATABalance
-    );
+    // choose your lucky numbers!
+    const numbers = [1, 2, 3, 4, 5, 6];
 
-    // wait for cutoff_time to expire again
+    await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
     await sleep(drawDurationSeconds + 1);
 
-    // draw for a second time, picking the same winning numbers
     await draw(program, config, null);
+    await draw(program, config, program.idl.errors[1].code);
+  });
 
-    // at this point, there is no prize money left
-    await dispense(program, config, numbers, null);
+  it("Attempt to buy ticket between draw and dispense", async () => {
+    const drawDurationSeconds = 1;
 
-    // balance should remain the same because there is no prize left
-    await assertBalance(
-      program,
-      config.keys.get(USER_DEPOSIT_ATA),
-      expectedUserDepositATABalance
+    const config = await initialize(program, drawDurationSeconds, 1);
+
+    // choose your lucky numbers!
+    const numbers1 = [1, 2, 3, 4, 5, 6];
+
+    await buy(program, numbers1, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    // choose your lucky numbers!
+    const numbers2 = [1, 2, 3, 4, 5, 7];
+
+    // call buy without calling dispense
+    await buy(program, numbers2, config, program.idl.errors[1].code);
+  });
+
+  it("Expire draw when the oracle never fulfills", async () => {
+    const drawDurationSeconds = 1;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      fulfillDurationSeconds: 1,
+    });
+
+    const numbers = [1, 2, 3, 4, 5, 6];
+    await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    // oracle still has time to fulfill
+    await expireDraw(program, config, program.idl.errors[22].code);
+
+    // let the fulfill deadline pass
+    await sleep(3);
+    await expireDraw(program, config, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
     );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+    assert.deepEqual(vaultMgrAccount.randomnessRequest, new Array(32).fill(0));
+
+    // late fulfillment is rejected, buy is unlocked
+    await fulfill(program, config, program.idl.errors[9].code);
+    await buy(program, numbers, config, null);
   });
-});
 
-describe("Stake", () => {
-  anchor.setProvider(anchor.Provider.env());
-  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+  it("Fulfill randomness with a nonce the oracle did not commit", async () => {
+    const drawDurationSeconds = 1;
 
-  it("Stake successfully after 20 ticket purchases", async () => {
+    const config = await initialize(program, drawDurationSeconds, 1);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    // a valid signature by the oracle, but with a nonce picked after the
+    // request was known
+    await fulfill(
+      program,
+      config,
+      program.idl.errors[10].code,
+      config.oracle,
+      null,
+      crypto.randomBytes(32)
+    );
+
+    // only the committed nonce fulfills the request
+    await fulfill(program, config);
+  });
+
+  it("Draw waits for the oracle to commit a nonce", async () => {
     const drawDurationSeconds = 1;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      fulfillDurationSeconds: 1,
+    });
 
-    const config = await initialize(program, drawDurationSeconds);
-    await tokenSwapInit(program, config);
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
 
-    // buy several tickets to add funds to deposit_vault
-    await buyNTickets(program, config, 20);
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
 
-    // swap tokens to yield bearing tokens and put in yield vault
-    await stake(program, config, null);
+    // the unfulfilled request may have been signed, its nonce is dropped
+    await sleep(3);
+    await expireDraw(program, config, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.deepEqual(vaultMgrAccount.oracleNonce, new Array(32).fill(0));
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, program.idl.errors[53].code);
+
+    // the request mixes in a slot hash produced after the commitment
+    await commitOracleNonce(program, config, null);
+    await sleep(1);
+    await draw(program, config, null);
+    await fulfill(program, config);
   });
 
-  it("Stake unsuccessful, not enough tokens in reserve", async () => {
+  it("Only the oracle commits nonces, never during a draw", async () => {
     const drawDurationSeconds = 1;
 
-    const config = await initialize(program, drawDurationSeconds);
-    await tokenSwapInit(program, config);
+    const config = await initialize(program, drawDurationSeconds, 1);
 
-    // buy several tickets to add funds to deposit_vault
-    await buyNTickets(program, config, 2);
+    await commitOracleNonce(
+      program,
+      config,
+      program.idl.errors[8].code,
+      anchor.web3.Keypair.generate()
+    );
 
-    // swap tokens to yield bearing tokens and put in yield vault
-    await stake(program, config, program.idl.errors[5].code);
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    // the nonce cannot be swapped once the request is known
+    await commitOracleNonce(program, config, program.idl.errors[1].code);
   });
 });
 
-// create new Account and seed with lamports
-async function newAccountWithLamports(
-  connection: anchor.web3.Connection,
-  lamports: number = 100_000_000
-): Promise<anchor.web3.Account> {
-  // generate keypair
-  const account = new anchor.web3.Account();
+describe("Commit reveal", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
 
-  // airdrop lamports
-  let txSig = await connection.requestAirdrop(account.publicKey, lamports);
-  await connection.confirmTransaction(txSig);
-  console.log("airdropTxSig:", txSig);
+  const commitReveal = {
+    randomnessMode: { commitReveal: {} },
+    revealDurationSeconds: 10,
+  };
 
-  // check account balance
-  const lamportsBalance = await connection.getBalance(account.publicKey);
-  console.log("lamports balance:", lamportsBalance);
+  it("Commit, draw and reveal", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
 
-  return account;
-}
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
 
-// sleep current thread in seconds
-async function sleep(seconds: number) {
-  const ms = seconds * 1000;
-  return new Promise((resolve) => setTimeout(resolve, ms));
-}
+    // commit before cutoff_time
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
 
-async function initialize(
-  program: Program<NoLossLottery>,
-  drawDurationSeconds: number,
-  userDepositAtaBalance = 100,
-  yieldVaultInitBalance = 0
-): Promise<Config> {
-  const mintAuthority = await newAccountWithLamports(
-    program.provider.connection
-  );
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
 
-  // create deposit mint for testing
-  const depositMint = await spl.createMint(
-    program.provider.connection,
-    mintAuthority,
-    mintAuthority.publicKey,
-    null,
-    9
-  );
-  console.log("deposit test mint created");
+    await draw(program, config, null);
 
-  // create yield mint for testing
-  const yieldMint = await spl.createMint(
-    program.provider.connection,
-    mintAuthority,
-    mintAuthority.publicKey,
-    null,
-    9
-  );
-  console.log("yield test mint created");
+    // wait for the reveal slot to be produced
+    await sleep(2);
 
-  // get PDAs
+    await reveal(program, config, secret, null);
 
-  const [depositVault, depositVaultBump] =
-    await anchor.web3.PublicKey.findProgramAddress(
-      [depositMint.toBuffer()],
-      program.programId
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
     );
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+    assert.deepEqual(vaultMgrAccount.commitment, new Array(32).fill(0));
+    vaultMgrAccount.winningNumbers.forEach((n: number) => {
+      assert.ok(n >= 1 && n <= 49);
+    });
+  });
 
-  const [yieldVault, yieldVaultBump] =
-    await anchor.web3.PublicKey.findProgramAddress(
-      [yieldMint.toBuffer()],
-      program.programId
-    );
+  it("Reveal with wrong secret", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
 
-  const [vaultMgr, vaultMgrBump] =
-    await anchor.web3.PublicKey.findProgramAddress(
-      [
-        depositMint.toBuffer(),
-        yieldMint.toBuffer(),
-        depositVault.toBuffer(),
-        yieldVault.toBuffer(),
-      ],
-      program.programId
-    );
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
 
-  const [tickets, ticketsBump] = await anchor.web3.PublicKey.findProgramAddress(
-    [
-      depositMint.toBuffer(),
-      yieldMint.toBuffer(),
-      depositVault.toBuffer(),
-      yieldVault.toBuffer(),
-      vaultMgr.toBuffer(),
-    ],
-    program.programId
-  );
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
 
-  // ticket price in tokens
-  const ticketPrice = new anchor.BN(1);
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
 
-  // init vault
-  const initTxSig = await program.rpc.initialize(
-    new anchor.BN(drawDurationSeconds),
-    ticketPrice,
-    {
-      accounts: {
-        depositMint: depositMint,
-        yieldMint: yieldMint,
-        depositVault: depositVault,
-        yieldVault: yieldVault,
-        vaultManager: vaultMgr,
-        tickets: tickets,
-        user: program.provider.wallet.publicKey,
-        systemProgram: anchor.web3.SystemProgram.programId,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
-      },
-    }
-  );
-  console.log("initTxSig:", initTxSig);
+    await draw(program, config, null);
+    await sleep(2);
 
-  // get user ata
-  const userDepositAta = await spl.getOrCreateAssociatedTokenAccount(
-    program.provider.connection,
-    mintAuthority,
-    depositMint,
-    program.provider.wallet.publicKey
-  );
+    const wrongSecret = Array.from(crypto.randomBytes(32));
+    await reveal(program, config, wrongSecret, program.idl.errors[19].code);
+  });
 
-  // mint tokens to user_ata
-  await spl.mintTo(
-    program.provider.connection,
-    mintAuthority,
-    depositMint,
-    userDepositAta.address,
-    mintAuthority.publicKey,
-    userDepositAtaBalance
-  );
-  console.log("minted %d tokens to user_ata", userDepositAtaBalance);
+  it("Commit twice", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
 
-  // get user tickets ata
-  const userTicketsAta = await spl.getOrCreateAssociatedTokenAccount(
-    program.provider.connection,
-    mintAuthority,
-    tickets,
-    program.provider.wallet.publicKey
-  );
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
 
-  // mint tokens to yield vault for testing
-  await spl.mintTo(
-    program.provider.connection,
-    mintAuthority,
-    yieldMint,
-    yieldVault,
-    mintAuthority.publicKey,
-    yieldVaultInitBalance
-  );
-  console.log("minted %d tokens to yield vault", yieldVaultInitBalance);
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+    await commit(program, config, sha256(secret), program.idl.errors[16].code);
+  });
 
-  let keys = new Map<String, anchor.web3.PublicKey>();
-  keys.set(DEPOSIT_VAULT, depositVault);
-  keys.set(DEPOSIT_MINT, depositMint);
-  keys.set(YIELD_VAULT, yieldVault);
-  keys.set(YIELD_MINT, yieldMint);
-  keys.set(VAULT_MANAGER, vaultMgr);
-  keys.set(MINT_AUTHORITY, mintAuthority.publicKey);
-  keys.set(TICKETS, tickets);
-  keys.set(USER_DEPOSIT_ATA, userDepositAta.address);
-  keys.set(USER_TICKET_ATA, userTicketsAta.address);
+  it("Commit after cutoff time", async () => {
+    const drawDurationSeconds = 1;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), program.idl.errors[15].code);
+  });
+
+  it("Draw without commitment", async () => {
+    const drawDurationSeconds = 1;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, program.idl.errors[14].code);
+
+    // nobody committed, push the draw back
+    await expireDraw(program, config, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.ok(
+      vaultMgrAccount.cutoffTime.toNumber() > Math.floor(Date.now() / 1000)
+    );
+  });
+
+  it("Expire draw when reveal never arrives", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      randomnessMode: { commitReveal: {} },
+      revealDurationSeconds: 1,
+    });
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    // too early to expire
+    await expireDraw(program, config, program.idl.errors[22].code);
+
+    // let the reveal deadline pass
+    await sleep(3);
+
+    // the committer bond is forfeited to the treasury, the crank gets nothing
+    const crank = await newAccountWithLamports(program.provider.connection);
+    const events = await captureEvents(
+      program,
+      ["BondForfeited"],
+      async () => {
+        await expireDraw(program, config, null, crank);
+      }
+    );
+    assert.equal(events.length, 1);
+    assert.ok(
+      events[0].data.committer.equals(program.provider.wallet.publicKey)
+    );
+    assert.equal(events[0].data.amount.toNumber(), COMMIT_BOND);
+    await assertBalance(program, config.keys.get(TREASURY), COMMIT_BOND);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+    assert.equal(vaultMgrAccount.committedBond.toNumber(), 0);
+    assert.equal(vaultMgrAccount.totalFeesCollected.toNumber(), COMMIT_BOND);
+
+    // late reveal is rejected
+    await reveal(program, config, secret, program.idl.errors[9].code);
+  });
+
+  it("Committer cannot reclaim its bond by expiring the draw", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      randomnessMode: { commitReveal: {} },
+      revealDurationSeconds: 1,
+    });
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    // let the reveal deadline pass
+    await sleep(3);
+
+    // the committer expires its own draw instead of revealing
+    await expireDraw(program, config, null);
+
+    // the bond stays in the treasury
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 0);
+    await assertBalance(program, config.keys.get(TREASURY), COMMIT_BOND);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+
+    // and is collected by the authority like the protocol fees
+    const destination = await newUser(program, config, 0);
+    await withdrawTreasury(
+      program,
+      config,
+      COMMIT_BOND,
+      destination.depositAta,
+      null
+    );
+    await assertBalance(program, destination.depositAta, COMMIT_BOND);
+  });
+
+  it("Bond is returned on reveal and held until then", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 0);
+    await assertBalance(program, config.keys.get(TREASURY), COMMIT_BOND);
+
+    // the pending bond is owed to the committer
+    const destination = await newUser(program, config, 0);
+    await withdrawTreasury(
+      program,
+      config,
+      1,
+      destination.depositAta,
+      program.idl.errors[5].code
+    );
+
+    // a later bond change does not touch the posted bond
+    await updateConfig(program, config, { commitBond: new anchor.BN(5) });
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    // wait for the reveal slot to be produced
+    await sleep(2);
+    await reveal(program, config, secret, null);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      COMMIT_BOND
+    );
+    await assertBalance(program, config.keys.get(TREASURY), 0);
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.committedBond.toNumber(), 0);
+    assert.equal(vaultMgrAccount.commitBond.toNumber(), 5);
+  });
+
+  it("Only the commit authority can commit", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // the authority hands committing over to a crank
+    const crank = await newUser(program, config, COMMIT_BOND);
+    await setCommitAuthority(
+      program,
+      config,
+      crank.keypair.publicKey,
+      program.idl.errors[34].code,
+      crank
+    );
+    await setCommitAuthority(program, config, crank.keypair.publicKey);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(
+      program,
+      config,
+      sha256(secret),
+      program.idl.errors[18].code
+    );
+    await commit(program, config, sha256(secret), null, crank);
+    await assertBalance(program, crank.depositAta, 0);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    // only the crank that committed can reveal
+    await sleep(2);
+    await reveal(program, config, secret, program.idl.errors[18].code);
+    await reveal(program, config, secret, null, crank);
+    await assertBalance(program, crank.depositAta, COMMIT_BOND);
+  });
+
+  it("Commit-reveal lottery needs a commit bond", async () => {
+    const drawDurationSeconds = 1;
+
+    try {
+      await initialize(program, drawDurationSeconds, 1, 0, {
+        ...commitReveal,
+        commitBond: 0,
+      });
+      assert.fail("initialize accepted a 0 commit bond");
+    } catch (e) {
+      assert.equal(e.code, program.idl.errors[54].code);
+    }
+
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
+    await updateConfig(
+      program,
+      config,
+      { commitBond: new anchor.BN(0) },
+      program.idl.errors[54].code
+    );
+
+    // oracle lotteries take no bond
+    await initialize(program, drawDurationSeconds, 1, 0, { commitBond: 0 });
+  });
+
+  it("Fulfill randomness in commit-reveal mode", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, commitReveal);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    await fulfill(program, config, program.idl.errors[12].code);
+  });
+});
+
+describe("Dispense", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Call dispense after draw, winner found", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // buy winning ticket
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    // draw winning ticket
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    // record prizes of the draw
+    await dispense(program, config, numbers, null);
+
+    // prize stays in the deposit vault until claimed
+    await registerMatch(program, config, 1, ticket, null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 0);
+
+    // prize can only be claimed once registration closes
+    await claimPrize(program, config, 1, ticket, program.idl.errors[31].code);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+
+    // check the deposit vault only contains the amount from the ticket purchase
+    await assertBalance(
+      program,
+      config.keys.get(DEPOSIT_VAULT),
+      userDepositAtaBalance
+    );
+
+    // check user received prize amount - fees
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      yieldVaultInitBalance - 3 // swap fees reduce amount returned as prize
+    );
+  });
+
+  it("Third party crank dispenses prize to winner", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // buy winning ticket
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    // crank is not the winner
+    const crank = await newUser(program, config, 0);
+    await dispense(program, config, numbers, null, crank);
+
+    await registerMatch(program, config, 1, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+
+    // winner received prize amount - fees, crank received nothing
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      yieldVaultInitBalance - 3 // swap fees reduce amount returned as prize
+    );
+    await assertBalance(program, crank.depositAta, 0);
+  });
+
+  it("Prize split between tickets with the winning numbers", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 2;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // two tickets with the winning numbers bought in the same transaction
+    // are held for the same time and have the same weight
+    const numbers = SURE_WIN_NUMBERS;
+    const [[ticket, _ticketBump], [otherTicket, _otherTicketBump]] =
+      await buyMany(program, config, [numbers, numbers], null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    await dispense(program, config, numbers, null);
+
+    await registerMatch(program, config, 1, ticket, null);
+    await registerMatch(program, config, 1, otherTicket, null);
+    assert.equal(
+      await ticketClaimWeight(program, config, 1, ticket),
+      await ticketClaimWeight(program, config, 1, otherTicket)
+    );
+
+    // prize of 10 - 3 swap fees split in half, the first claim is rounded
+    // down to 3 and the last one takes the 4 left
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 3);
+    await claimPrize(program, config, 1, otherTicket, null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 7);
+
+    // the whole tier is paid out, only the deposits stay in the vault
+    await assertBalance(program, config.keys.get(DEPOSIT_VAULT), 2);
+  });
+
+  it("Late tickets get a smaller share of the prize", async () => {
+    const drawDurationSeconds = 4;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 100;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // first ticket starts the draw period, the other one is bought just before cutoff
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+    await sleep(drawDurationSeconds - 1);
+    const lateUser = await newUser(program, config, userDepositAtaBalance);
+    const [lateTicket, _lateTicketBump] = await buy(
+      program,
+      numbers,
+      config,
+      null,
+      lateUser
+    );
+
+    await sleep(2);
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+
+    await registerMatch(program, config, 1, ticket, null);
+    await registerMatch(program, config, 1, lateTicket, null, lateUser);
+
+    const weight = await ticketClaimWeight(program, config, 1, ticket);
+    const lateWeight = await ticketClaimWeight(program, config, 1, lateTicket);
+    assert.ok(lateWeight < weight);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    let drawRecordAccount = await program.account.drawRecord.fetch(drawRecord);
+    assert.equal(
+      drawRecordAccount.tierWeights[3].toNumber(),
+      weight + lateWeight
+    );
+
+    // first ticket was held for the whole period, the late one for a part of
+    // it, prize of 100 - 3 swap fees
+    const prize = drawRecordAccount.tierPrizes[3].toNumber();
+    assert.equal(prize, 97);
+    assert.equal(
+      weight,
+      drawRecordAccount.drawnAt.toNumber() -
+        drawRecordAccount.periodStart.toNumber()
+    );
+
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+    await claimPrize(program, config, 1, lateTicket, null, lateUser);
+
+    // the first ticket gets its weight's part rounded down, the late one the
+    // rest, together the whole tier
+    const share = Math.floor((97 * weight) / (weight + lateWeight));
+    assert.ok(97 - share < share);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), share);
+    await assertBalance(program, lateUser.depositAta, 97 - share);
+    drawRecordAccount = await program.account.drawRecord.fetch(drawRecord);
+    assert.equal(drawRecordAccount.claimed.toNumber(), 97);
+  });
+
+  it("Single late winner takes the whole tier", async () => {
+    const drawDurationSeconds = 4;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 100;
+    const registrationDurationSeconds = 2;
+    const claimDurationSeconds = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // first ticket starts the draw period and is redeemed before the draw,
+    // the winner is bought just before cutoff
+    const [ticket, ticketBump] = await buy(
+      program,
+      SURE_WIN_NUMBERS,
+      config,
+      null
+    );
+    await sleep(drawDurationSeconds - 1);
+    const lateUser = await newUser(program, config, userDepositAtaBalance);
+    const [lateTicket, _lateTicketBump] = await buy(
+      program,
+      SURE_WIN_NUMBERS,
+      config,
+      null,
+      lateUser
+    );
+    await redeem(program, config, ticket, ticketBump, null);
+
+    await sleep(2);
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, SURE_WIN_NUMBERS, null);
+    await registerMatch(program, config, 1, lateTicket, null, lateUser);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    let drawRecordAccount = await program.account.drawRecord.fetch(drawRecord);
+    const period =
+      drawRecordAccount.drawnAt.toNumber() -
+      drawRecordAccount.periodStart.toNumber();
+    const lateWeight = await ticketClaimWeight(program, config, 1, lateTicket);
+    assert.ok(lateWeight < period);
+
+    // the weight only matters against other winners of the tier, the only
+    // winner takes the whole prize of 100 - 3 swap fees
+    assert.equal(drawRecordAccount.tierPrizes[3].toNumber(), 97);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, lateTicket, null, lateUser);
+    await assertBalance(program, lateUser.depositAta, 97);
+
+    // nothing is left to go back into the pool
+    await sleep(claimDurationSeconds + 1);
+    await expireDrawRecord(program, config, 1, null);
+    drawRecordAccount = await program.account.drawRecord.fetch(drawRecord);
+    assert.equal(drawRecordAccount.claimed.toNumber(), 97);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.rolledOver.toNumber(), 0);
+    assert.equal(vaultMgrAccount.unclaimedPrizes.toNumber(), 0);
+  });
+
+  it("Prize tiers for partial matches", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 7;
+    const yieldVaultInitBalance = 100;
+    const registrationDurationSeconds = 3;
+
+    // 10% for 3 matches, 20% for 4, 30% for 5 and 40% for 6
+    // picks of 1 and 2 only, every ticket matches 6 minus its distance in ones
+    const domain = { pickCount: 6, maxNumber: 2, unique: false };
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        prizeTiers: [1000, 2000, 3000, 4000],
+        numberDomain: domain,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // one ticket for every count of ones
+    const picks = [];
+    const tickets = [];
+    for (let ones = 0; ones <= 6; ones++) {
+      const numbers = [1, 1, 1, 1, 1, 1].map((n, i) => (i < ones ? 1 : 2));
+      const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+      picks.push(numbers);
+      tickets.push(ticket);
+    }
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+    await dispense(program, config, winningNumbers, null);
+
+    // the first ticket registers after registration has closed
+    const tiers = picks.map((numbers) =>
+      matchTier(countMatches(numbers, winningNumbers), domain.pickCount)
+    );
+    const expectedWinners = [0, 0, 0, 0];
+    for (let i = 1; i < tickets.length; i++) {
+      if (tiers[i] === null) {
+        await registerMatch(
+          program,
+          config,
+          1,
+          tickets[i],
+          program.idl.errors[33].code
+        );
+      } else {
+        await registerMatch(program, config, 1, tickets[i], null);
+        expectedWinners[tiers[i]]++;
+      }
+    }
+
+    // a ticket registers once per draw
+    const registered = tickets.findIndex((_t, i) => i > 0 && tiers[i] !== null);
+    try {
+      await registerMatch(program, config, 1, tickets[registered], null);
+      assert.fail("ticket registered twice");
+    } catch (e) {
+      assert.notEqual(e.message, "ticket registered twice");
+    }
+
+    // only the owner can register a ticket
+    const outsider = await newUser(program, config, 0);
+    await registerMatch(
+      program,
+      config,
+      1,
+      tickets[0],
+      program.idl.errors[23].code,
+      outsider
+    );
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(drawRecordAccount.prizeAmount.toNumber(), 97);
+    assert.deepEqual(
+      drawRecordAccount.tierPrizes.map((prize) => prize.toNumber()),
+      [9, 19, 29, 38]
+    );
+    assert.deepEqual(
+      drawRecordAccount.tierWinners.map((winners) => winners.toNumber()),
+      expectedWinners
+    );
+
+    await sleep(registrationDurationSeconds + 1);
+
+    // registration has closed
+    await registerMatch(
+      program,
+      config,
+      1,
+      tickets[0],
+      program.idl.errors[30].code
+    );
+
+    // the winners of a tier split it by weight, together they take all of it
+    const tierPrizes = [9, 19, 29, 38];
+    let expected = 0;
+    for (let tier = 0; tier < tierPrizes.length; tier++) {
+      if (expectedWinners[tier] > 0) {
+        expected += tierPrizes[tier];
+      }
+    }
+    for (let i = 1; i < tickets.length; i++) {
+      if (tiers[i] !== null) {
+        await claimPrize(program, config, 1, tickets[i], null);
+      }
+    }
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), expected);
+  });
+
+  it("Ticket bought after the draw cannot register", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 2;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      { numberDomain: SURE_WIN_DOMAIN }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+
+    // buy the winning numbers once they are known
+    const [lateTicket, _lateTicketBump] = await buy(
+      program,
+      numbers,
+      config,
+      null
+    );
+    await registerMatch(
+      program,
+      config,
+      1,
+      lateTicket,
+      program.idl.errors[32].code
+    );
+  });
+
+  it("Prize can only be claimed by the winner", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // buy winning ticket
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+    await registerMatch(program, config, 1, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+
+    // another user tries to claim the prize
+    const otherUser = await newUser(program, config, 0);
+    await claimPrize(
+      program,
+      config,
+      1,
+      ticket,
+      program.idl.errors[25].code,
+      otherUser
+    );
+    await assertBalance(program, otherUser.depositAta, 0);
+
+    // winner can still claim
+    await claimPrize(program, config, 1, ticket, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      yieldVaultInitBalance - 3 // swap fees reduce amount returned as prize
+    );
+
+    // prize cannot be claimed twice
+    try {
+      await claimPrize(program, config, 1, ticket, null);
+      assert.fail("prize claimed twice");
+    } catch (e) {
+      assert.notEqual(e.message, "prize claimed twice");
+    }
+  });
+
+  it("Unclaimed prize rolls back into the pool after expiry", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+    const claimDurationSeconds = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // buy winning ticket
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+    await registerMatch(program, config, 1, ticket, null);
+
+    // claims have not expired yet
+    await expireDrawRecord(program, config, 1, program.idl.errors[27].code);
+
+    await sleep(registrationDurationSeconds + claimDurationSeconds + 1);
+
+    // winner is too late
+    await claimPrize(program, config, 1, ticket, program.idl.errors[26].code);
+
+    await expireDrawRecord(program, config, 1, null);
+
+    // prizes can only be released once
+    await expireDrawRecord(program, config, 1, program.idl.errors[26].code);
+
+    // prize is back in the pool, nothing reserved anymore
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 0);
+    await assertBalance(
+      program,
+      config.keys.get(DEPOSIT_VAULT),
+      userDepositAtaBalance + yieldVaultInitBalance - 3
+    );
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.unclaimedPrizes.toNumber(), 0);
+
+    // the next draw pays out the rolled back prize
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+    await registerMatch(program, config, 2, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 2, ticket, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      yieldVaultInitBalance - 3
+    );
+  });
+
+  it("Draw records keep the history of every draw", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 2;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance
+    );
+    await tokenSwapInit(program, config);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+    await buy(program, [7, 8, 9, 10, 11, 12], config, null);
+
+    // two draws, each with the numbers drawn by the oracle
+    const drawnNumbers = [];
+    const drawnRandomness = [];
+    for (let i = 0; i < 2; i++) {
+      await sleep(drawDurationSeconds + 1);
+      await draw(program, config, null);
+      const request = (
+        await program.account.vaultManager.fetch(config.keys.get(VAULT_MANAGER))
+      ).randomnessRequest;
+      drawnRandomness.push(oracleRandomness(config, request));
+      const numbers = await fulfill(program, config);
+      await dispense(program, config, numbers, null);
+      drawnNumbers.push(numbers);
+    }
+    const [firstNumbers, secondNumbers] = drawnNumbers;
+
+    const [firstDrawRecord, _firstDrawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const first = await program.account.drawRecord.fetch(firstDrawRecord);
+    assert.equal(first.drawId.toNumber(), 1);
+    assert.deepEqual(first.winningNumbers, firstNumbers);
+    assert.deepEqual(first.randomness, drawnRandomness[0]);
+    assert.deepEqual(
+      deriveNumbers(first.randomness, first.numberDomain),
+      firstNumbers
+    );
+    assert.equal(first.ticketSupply.toNumber(), 2);
+    assert.equal(first.prizeAmount.toNumber(), yieldVaultInitBalance - 3);
+    assert.isAtMost(first.drawnAt.toNumber(), first.fulfilledAt.toNumber());
+    assert.isAtMost(first.fulfilledAt.toNumber(), first.dispensedAt.toNumber());
+
+    // prize of the first draw is still reserved, nothing left for the second
+    const [secondDrawRecord, _secondDrawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(2)
+    );
+    const second = await program.account.drawRecord.fetch(secondDrawRecord);
+    assert.equal(second.drawId.toNumber(), 2);
+    assert.deepEqual(second.winningNumbers, secondNumbers);
+    assert.equal(second.prizeAmount.toNumber(), 0);
+    assert.isAtLeast(
+      second.drawnAt.toNumber(),
+      first.dispensedAt.toNumber()
+    );
+  });
+
+  it("Call dispense after draw, no winner", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    // deliberatly choose a non winning combination
+    const numbers = [7, 8, 9, 10, 11, 12];
+
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    await dispense(program, config, numbers, program.idl.errors[4].code);
+
+    await assertBalance(program, config.keys.get(USER_TICKET_ATA), 1);
+    // subtract 1 to account for a ticket purchase
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 1
+    );
+  });
+
+  it("Call dispense with no draw", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    // deliberatly choose a non winning combination
+    const numbers = [7, 8, 9, 10, 11, 12];
+
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await dispense(program, config, numbers, program.idl.errors[4].code);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 1
+    );
+  });
+
+  it("Call dispense before randomness is fulfilled", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = [1, 2, 3, 4, 5, 6];
+    await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+
+    // winning numbers are still zeroed out until the oracle responds
+    await dispense(
+      program,
+      config,
+      [0, 0, 0, 0, 0, 0],
+      program.idl.errors[11].code
+    );
+  });
+
+  it("Call dispense twice in a row without winning PDA", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    // deliberatly choose a non winning combination
+    const numbers = [7, 8, 9, 10, 11, 12];
+
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    // calling dispense passing in non winning PDA
+    await dispense(program, config, numbers, program.idl.errors[4].code);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 1
+    );
+
+    // call it again
+    await dispense(program, config, numbers, program.idl.errors[4].code);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 1
+    );
+  });
+
+  it("Call dispense twice, first without winning PDA second time with winning PDA", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    // deliberatly choose a non winning combination
+    const numbers = [7, 8, 9, 10, 11, 12];
+
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    // calling dispense passing in non winning PDA
+    await dispense(program, config, numbers, program.idl.errors[4].code);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 1
+    );
+
+    await dispense(program, config, winningNumbers, null);
+
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 1
+    );
+  });
+
+  it("Winning ticket chosen twice dispense prize twice", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+    const yieldVaultInitBalance = 100;
+    const registrationDurationSeconds = 2;
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // every ticket of the domain wins
+    const numbers = SURE_WIN_NUMBERS;
+
+    // buy winning ticket
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    // call dispense with winning numbers
+    await dispense(program, config, numbers, null);
+
+    await registerMatch(program, config, 1, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+
+    // assert winning user got the prize
+    // subtract 1 for the ticket purchase
+    // subtract 3 for swap fees
+    const expectedUserDepositATABalance =
+      yieldVaultInitBalance + userDepositAtaBalance - 1 - 3;
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      expectedUserDepositATABalance
+    );
+
+    // wait for cutoff_time to expire again
+    await sleep(drawDurationSeconds + 1);
+
+    // draw for a second time, picking the same winning numbers
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    // at this point, there is no prize money left
+    await dispense(program, config, numbers, null);
+    await registerMatch(program, config, 2, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 2, ticket, null);
+
+    // balance should remain the same because there is no prize left
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      expectedUserDepositATABalance
+    );
+  });
+});
+
+describe("Number domain", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Matching every pick of a small domain wins the top tier", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      { numberDomain: { pickCount: 3, maxNumber: 3, unique: true } }
+    );
+    await tokenSwapInit(program, config);
+
+    const [ticket, _ticketBump] = await buy(
+      program,
+      [3, 1, 2, 0, 0, 0],
+      config,
+      null
+    );
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    // unique domains skip numbers already drawn, 3 of 3 is every number
+    const winningNumbers = await fulfill(program, config);
+    assert.deepEqual(canonicalNumbers(winningNumbers), [1, 2, 3, 0, 0, 0]);
+
+    await dispense(program, config, winningNumbers, null);
+    await registerMatch(program, config, 1, ticket, null);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(drawRecordAccount.numberDomain.pickCount, 3);
+    assert.equal(drawRecordAccount.tierWinners[3].toNumber(), 1);
+  });
+
+  it("Number domain cannot change while tickets are outstanding", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    // unique picks need at least pickCount numbers
+    await updateConfig(
+      program,
+      config,
+      { numberDomain: { pickCount: 6, maxNumber: 5, unique: true } },
+      program.idl.errors[47].code
+    );
+    await updateConfig(
+      program,
+      config,
+      { numberDomain: { pickCount: 7, maxNumber: 49, unique: false } },
+      program.idl.errors[47].code
+    );
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    const numberDomain = { pickCount: 5, maxNumber: 20, unique: true };
+    await updateConfig(
+      program,
+      config,
+      { numberDomain: numberDomain },
+      program.idl.errors[35].code
+    );
+
+    await redeem(program, config, ticket, ticketBump, null);
+    await updateConfig(program, config, { numberDomain: numberDomain }, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.deepEqual(vaultMgrAccount.numberDomain, numberDomain);
+  });
+});
+
+describe("Stake", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Stake successfully after 20 ticket purchases", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    // buy several tickets to add funds to deposit_vault
+    await buyNTickets(program, config, 20);
+
+    // swap tokens to yield bearing tokens and put in yield vault
+    await stake(program, config, null);
+  });
+
+  it("Stake unsuccessful, not enough tokens in reserve", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    // buy several tickets to add funds to deposit_vault
+    await buyNTickets(program, config, 2);
+
+    // swap tokens to yield bearing tokens and put in yield vault
+    await stake(program, config, program.idl.errors[5].code);
+  });
+});
+
+describe("Config", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Authority updates the config", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+
+    let vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.ok(vaultMgrAccount.authority.equals(program.provider.wallet.publicKey));
+
+    await updateConfig(
+      program,
+      config,
+      {
+        drawDuration: new anchor.BN(60),
+        ticketPrice: new anchor.BN(2),
+        depositTokenReserve: new anchor.BN(5),
+      },
+      null
+    );
+
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.drawDuration.toNumber(), 60);
+    assert.equal(vaultMgrAccount.ticketPrice.toNumber(), 2);
+    assert.equal(vaultMgrAccount.depositTokenReserve.toNumber(), 5);
+
+    // fields left out keep their value
+    await updateConfig(program, config, { prizeTiers: [1000, 2000, 3000, 4000] }, null);
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.drawDuration.toNumber(), 60);
+    assert.deepEqual(vaultMgrAccount.prizeTiers, [1000, 2000, 3000, 4000]);
+  });
+
+  it("Update config with invalid values", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+
+    await updateConfig(
+      program,
+      config,
+      { drawDuration: new anchor.BN(0) },
+      program.idl.errors[7].code
+    );
+    await updateConfig(
+      program,
+      config,
+      { ticketPrice: new anchor.BN(0) },
+      program.idl.errors[6].code
+    );
+    await updateConfig(
+      program,
+      config,
+      { prizeTiers: [5000, 5000, 5000, 5000] },
+      program.idl.errors[28].code
+    );
+
+    // an oracle draw could be expired as soon as it starts
+    await updateConfig(
+      program,
+      config,
+      { fulfillDuration: new anchor.BN(0) },
+      program.idl.errors[52].code
+    );
+
+    // tickets cannot be locked in for more than 30 days
+    await updateConfig(
+      program,
+      config,
+      { minHoldingPeriod: new anchor.BN(30 * 24 * 3600 + 1) },
+      program.idl.errors[51].code
+    );
+    await updateConfig(
+      program,
+      config,
+      { minHoldingPeriod: new anchor.BN(30 * 24 * 3600) },
+      null
+    );
+  });
+
+  it("Oracle lottery cannot be initialized without a fulfill duration", async () => {
+    const drawDurationSeconds = 1;
+
+    try {
+      await initialize(program, drawDurationSeconds, 1, 0, {
+        fulfillDurationSeconds: 0,
+      });
+      assert.fail("initialize accepted a 0 fulfill duration");
+    } catch (e) {
+      assert.equal(e.code, program.idl.errors[52].code);
+    }
+
+    // commit-reveal lotteries have no oracle to wait for
+    await initialize(program, drawDurationSeconds, 1, 0, {
+      randomnessMode: { commitReveal: {} },
+      revealDurationSeconds: 10,
+      fulfillDurationSeconds: 0,
+    });
+  });
+
+  it("Update config signed by another user", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const user = await newUser(program, config);
+
+    await updateConfig(
+      program,
+      config,
+      { drawDuration: new anchor.BN(60) },
+      program.idl.errors[34].code,
+      user
+    );
+  });
+
+  it("Ticket price cannot change while tickets are outstanding", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+
+    await updateConfig(
+      program,
+      config,
+      { ticketPrice: new anchor.BN(2) },
+      program.idl.errors[35].code
+    );
+
+    // other fields can still change
+    await updateConfig(
+      program,
+      config,
+      { drawDuration: new anchor.BN(60) },
+      null
+    );
+
+    // once every ticket is redeemed the price can change
+    await redeem(program, config, ticket, ticketBump, null);
+    await updateConfig(
+      program,
+      config,
+      { ticketPrice: new anchor.BN(2) },
+      null
+    );
+  });
+
+  it("Transfer and accept authority", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const newAuthority = await newUser(program, config);
+    const otherUser = await newUser(program, config);
+
+    // only the authority can hand over
+    await transferAuthority(
+      program,
+      config,
+      newAuthority.keypair.publicKey,
+      program.idl.errors[34].code,
+      otherUser
+    );
+
+    await transferAuthority(
+      program,
+      config,
+      newAuthority.keypair.publicKey,
+      null
+    );
+
+    // old authority stays in charge until the handover is accepted
+    await updateConfig(
+      program,
+      config,
+      { drawDuration: new anchor.BN(60) },
+      program.idl.errors[34].code,
+      newAuthority
+    );
+
+    // only the pending authority can accept
+    await acceptAuthority(program, config, program.idl.errors[36].code, otherUser);
+    await acceptAuthority(program, config, null, newAuthority);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.ok(vaultMgrAccount.authority.equals(newAuthority.keypair.publicKey));
+    assert.ok(
+      vaultMgrAccount.pendingAuthority.equals(anchor.web3.PublicKey.default)
+    );
+
+    // new authority is in charge, old one is not
+    await updateConfig(
+      program,
+      config,
+      { drawDuration: new anchor.BN(60) },
+      null,
+      newAuthority
+    );
+    await updateConfig(
+      program,
+      config,
+      { drawDuration: new anchor.BN(30) },
+      program.idl.errors[34].code
+    );
+
+    // handover cannot be accepted twice
+    await acceptAuthority(program, config, program.idl.errors[36].code, newAuthority);
+  });
+});
+
+describe("Yield source", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Yield source is stored once the pool is checked", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.ok(
+      vaultMgrAccount.tokenSwapProgram.equals(tokenSwap.TOKEN_SWAP_PROGRAM_ID)
+    );
+    assert.ok(vaultMgrAccount.amm.equals(config.keys.get(TOKEN_SWAP_ACCOUNT)));
+    assert.ok(
+      vaultMgrAccount.ammAuthority.equals(
+        config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY)
+      )
+    );
+    assert.ok(
+      vaultMgrAccount.swapDepositVault.equals(
+        config.keys.get(SWAP_DEPOSIT_VAULT)
+      )
+    );
+    assert.ok(
+      vaultMgrAccount.swapYieldVault.equals(config.keys.get(SWAP_YIELD_VAULT))
+    );
+    assert.ok(vaultMgrAccount.poolMint.equals(config.keys.get(POOL_MINT)));
+    assert.ok(vaultMgrAccount.poolFee.equals(config.keys.get(POOL_FEE)));
+
+    // only the authority sets the yield source
+    const otherUser = await newUser(program, config);
+    await setYieldSource(
+      program,
+      config,
+      program.idl.errors[34].code,
+      otherUser
+    );
+
+    // pool fee account does not belong to the pool
+    const poolFee = config.keys.get(POOL_FEE);
+    config.keys.set(POOL_FEE, config.keys.get(USER_DEPOSIT_ATA));
+    await setYieldSource(program, config, program.idl.errors[42].code);
+    config.keys.set(POOL_FEE, poolFee);
+
+    // pool authority is not derived from the pool
+    const ammAuthority = config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY);
+    config.keys.set(
+      TOKEN_SWAP_ACCOUNT_AUTHORITY,
+      anchor.web3.Keypair.generate().publicKey
+    );
+    await setYieldSource(program, config, program.idl.errors[42].code);
+    config.keys.set(TOKEN_SWAP_ACCOUNT_AUTHORITY, ammAuthority);
+
+    // only pools of the spl-token-swap program are accepted
+    await setYieldSource(
+      program,
+      config,
+      program.idl.errors[42].code,
+      null,
+      spl.TOKEN_PROGRAM_ID
+    );
+  });
+
+  it("Stake and redeem reject pool accounts other than the yield source", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    const numbers = [1, 2, 3, 4, 5, 6];
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    await buyNTickets(program, config, 20);
+
+    // fake pool
+    const amm = config.keys.get(TOKEN_SWAP_ACCOUNT);
+    config.keys.set(TOKEN_SWAP_ACCOUNT, anchor.web3.Keypair.generate().publicKey);
+    await stake(program, config, program.idl.errors[42].code);
+    config.keys.set(TOKEN_SWAP_ACCOUNT, amm);
+
+    // swap output sent to another account
+    const swapYieldVault = config.keys.get(SWAP_YIELD_VAULT);
+    config.keys.set(SWAP_YIELD_VAULT, config.keys.get(YIELD_VAULT));
+    await stake(program, config, program.idl.errors[42].code);
+    config.keys.set(SWAP_YIELD_VAULT, swapYieldVault);
+
+    await stake(program, config, null);
+
+    // redeem values the staked position through the pool
+    config.keys.set(TOKEN_SWAP_ACCOUNT, anchor.web3.Keypair.generate().publicKey);
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[42].code
+    );
+    config.keys.set(TOKEN_SWAP_ACCOUNT, amm);
+
+    await redeem(program, config, ticket, ticketBump, null);
+  });
+});
+
+describe("Ticket lottery", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Draws exactly one winning ticket", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        lotteryMode: { ticket: {} },
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // numbers do not matter in ticket mode
+    const users = [
+      null,
+      await newUser(program, config, userDepositAtaBalance),
+      await newUser(program, config, userDepositAtaBalance),
+    ];
+    const tickets = [];
+    for (let i = 0; i < users.length; i++) {
+      const [ticket, _ticketBump] = await buy(
+        program,
+        [1 + i, 2, 3, 4, 5, 6],
+        config,
+        null,
+        users[i]
+      );
+      tickets.push(ticket);
+    }
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    const events = await captureEvents(
+      program,
+      ["WinningTicketDrawn"],
+      async () => {
+        await dispense(program, config, winningNumbers, null);
+      }
+    );
+    assert.equal(events.length, 1);
+    const winner = tickets.findIndex((ticket) =>
+      ticket.equals(events[0].data.ticket)
+    );
+    assert.ok(winner >= 0);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.ok(drawRecordAccount.winningTicket.equals(tickets[winner]));
+    assert.equal(
+      drawRecordAccount.tierPrizes[3].toNumber(),
+      drawRecordAccount.prizeAmount.toNumber()
+    );
+
+    // only the drawn ticket can register
+    for (let i = 0; i < tickets.length; i++) {
+      await registerMatch(
+        program,
+        config,
+        1,
+        tickets[i],
+        i == winner ? null : program.idl.errors[33].code,
+        users[i]
+      );
+    }
+
+    // the drawn ticket takes the whole prize
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, tickets[winner], null, users[winner]);
+    await assertBalance(
+      program,
+      users[winner]
+        ? users[winner].depositAta
+        : config.keys.get(USER_DEPOSIT_ATA),
+      drawRecordAccount.prizeAmount.toNumber()
+    );
+  });
+
+  it("Tickets are drawn by the time they were held", async () => {
+    const drawDurationSeconds = 4;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 100;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        lotteryMode: { ticket: {} },
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // first ticket starts the draw period, the other one is bought just
+    // before cutoff
+    const [ticket, _ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await sleep(drawDurationSeconds - 1);
+    const lateUser = await newUser(program, config, userDepositAtaBalance);
+    const [lateTicket, _lateTicketBump] = await buy(
+      program,
+      [7, 8, 9, 10, 11, 12],
+      config,
+      null,
+      lateUser
+    );
+
+    await sleep(2);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    // a ticket weighs the seconds from its purchase to the draw, counting the
+    // second of the draw
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    const drawnAt = vaultMgrAccount.drawnAt.toNumber();
+    const purchasedAt = (await program.account.ticket.fetch(ticket))
+      .purchasedAt;
+    const latePurchasedAt = (await program.account.ticket.fetch(lateTicket))
+      .purchasedAt;
+    assert.ok(vaultMgrAccount.periodStart.eq(purchasedAt));
+    const weight = drawnAt - purchasedAt.toNumber() + 1;
+    const lateWeight = drawnAt - latePurchasedAt.toNumber() + 1;
+    assert.ok(lateWeight < weight);
+
+    // the first slot holds weights 0..weight and the late one the rest
+    const target = new anchor.BN(
+      vaultMgrAccount.randomness.slice(24, 32),
+      "le"
+    ).modn(weight + lateWeight);
+    const [winner, winnerIndex, winnerUser] =
+      target < weight ? [ticket, 0, null] : [lateTicket, 1, lateUser];
+
+    const events = await captureEvents(
+      program,
+      ["WinningTicketDrawn"],
+      async () => {
+        await dispense(program, config, winningNumbers, null);
+      }
+    );
+    assert.equal(events.length, 1);
+    assert.ok(events[0].data.ticket.equals(winner));
+    assert.equal(events[0].data.winningIndex.toNumber(), winnerIndex);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(
+      drawRecordAccount.registryWeight.toNumber(),
+      weight + lateWeight
+    );
+
+    // the drawn ticket takes the whole prize, whatever its weight
+    await registerMatch(program, config, 1, winner, null, winnerUser);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, winner, null, winnerUser);
+    await assertBalance(
+      program,
+      winnerUser ? winnerUser.depositAta : config.keys.get(USER_DEPOSIT_ATA),
+      drawRecordAccount.prizeAmount.toNumber()
+    );
+  });
+
+  it("Registry tree sums the tickets of each draw period", async () => {
+    const drawDurationSeconds = 1;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      100,
+      yieldVaultInitBalance,
+      { lotteryMode: { ticket: {} } }
+    );
+    await tokenSwapInit(program, config);
+
+    // the nodes on the path of the new slot must be passed
+    const [accounts, _ticket, _ticketBump, _registryNodes] = await buyAccounts(
+      program,
+      config,
+      [1, 2, 3, 4, 5, 6],
+      null
+    );
+    try {
+      await program.rpc.buy([1, 2, 3, 4, 5, 6], { accounts: accounts });
+      assert.fail("bought without the registry tree nodes");
+    } catch (e) {
+      assert.equal(e.code, program.idl.errors[56].code);
+    }
+
+    const [first, firstBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    const [second, _secondBump] = await buy(
+      program,
+      [2, 3, 4, 5, 6, 7],
+      config,
+      null
+    );
+    const firstPurchasedAt = (await program.account.ticket.fetch(first))
+      .purchasedAt;
+    const secondPurchasedAt = (await program.account.ticket.fetch(second))
+      .purchasedAt;
+
+    // both tickets were bought during the first period, the root entry sums
+    // the first 64^2 slots
+    let leaf = await registryNode(program, config, 0);
+    let root = await registryNode(program, config, 2);
+    assert.equal(root.drawId.toNumber(), 1);
+    assert.equal(root.entries[0].count, 2);
+    assert.equal(root.entries[0].newCount, 2);
+    assert.ok(
+      root.entries[0].newPurchasedSum.eq(
+        firstPurchasedAt.add(secondPurchasedAt)
+      )
+    );
+    assert.equal(leaf.entries[0].count, 1);
+    assert.ok(leaf.entries[0].newPurchasedSum.eq(firstPurchasedAt));
+    assert.equal(leaf.entries[1].count, 1);
+    assert.ok(leaf.entries[1].newPurchasedSum.eq(secondPurchasedAt));
+
+    // second ticket moves into the slot of the redeemed one
+    await redeem(program, config, first, firstBump, null);
+    leaf = await registryNode(program, config, 0);
+    root = await registryNode(program, config, 2);
+    assert.equal(root.entries[0].count, 1);
+    assert.ok(root.entries[0].newPurchasedSum.eq(secondPurchasedAt));
+    assert.equal(leaf.entries[0].count, 1);
+    assert.ok(leaf.entries[0].newPurchasedSum.eq(secondPurchasedAt));
+    assert.equal(leaf.entries[1].count, 0);
+    assert.equal(leaf.entries[1].newCount, 0);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+    await dispense(program, config, winningNumbers, null);
+
+    // the second ticket is held for the whole next period, only the ticket
+    // bought during it is summed by purchase time
+    const [third, _thirdBump] = await buy(
+      program,
+      [3, 4, 5, 6, 7, 8],
+      config,
+      null
+    );
+    const thirdPurchasedAt = (await program.account.ticket.fetch(third))
+      .purchasedAt;
+    root = await registryNode(program, config, 2);
+    assert.equal(root.drawId.toNumber(), 2);
+    assert.equal(root.entries[0].count, 2);
+    assert.equal(root.entries[0].newCount, 1);
+    assert.ok(root.entries[0].newPurchasedSum.eq(thirdPurchasedAt));
+  });
+
+  it("Ticket redeemed from the last registry slot is closed", async () => {
+    const drawDurationSeconds = 1;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      100,
+      yieldVaultInitBalance,
+      { numberDomain: SURE_WIN_DOMAIN }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    const [first, _firstBump] = await buy(program, numbers, config, null);
+    const [last, lastBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+
+    // ticket and entry of the last slot are the same accounts as the last
+    // ticket and last entry
+    await redeem(program, config, last, lastBump, null);
+
+    const [lastEntry, _lastEntryBump] = await ticketEntryAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    assert.equal(
+      await program.provider.connection.getAccountInfo(last),
+      null
+    );
+    assert.equal(
+      await program.provider.connection.getAccountInfo(lastEntry),
+      null
+    );
+
+    const [firstEntry, _firstEntryBump] = await ticketEntryAddress(
+      program,
+      config,
+      new anchor.BN(0)
+    );
+    const firstEntryAccount = await program.account.ticketEntry.fetch(
+      firstEntry
+    );
+    assert.ok(firstEntryAccount.ticket.equals(first));
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.ticketCount.toNumber(), 1);
+
+    // the redeemed ticket cannot enter the prize draw
+    await assert.rejects(
+      async () => await registerMatch(program, config, 1, last)
+    );
+    await registerMatch(program, config, 1, first);
+  });
+
+  it("Registry stays contiguous when tickets are redeemed", async () => {
+    const drawDurationSeconds = 60;
+
+    const config = await initialize(program, drawDurationSeconds, 100, 0, {
+      lotteryMode: { ticket: {} },
+    });
+    await tokenSwapInit(program, config);
+
+    const [first, firstBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    const [second, _secondBump] = await buy(
+      program,
+      [2, 3, 4, 5, 6, 7],
+      config,
+      null
+    );
+    const [third, thirdBump] = await buy(
+      program,
+      [3, 4, 5, 6, 7, 8],
+      config,
+      null
+    );
+
+    // last ticket moves into the slot of the first one
+    await redeem(program, config, first, firstBump, null);
+
+    let vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.ticketCount.toNumber(), 2);
+
+    const entries = [];
+    for (let i = 0; i < 3; i++) {
+      const [entry, _entryBump] = await ticketEntryAddress(
+        program,
+        config,
+        new anchor.BN(i)
+      );
+      entries.push(await program.account.ticketEntry.fetchNullable(entry));
+    }
+    assert.ok(entries[0].ticket.equals(third));
+    assert.ok(entries[1].ticket.equals(second));
+    assert.equal(entries[2], null);
+
+    const thirdAccount = await program.account.ticket.fetch(third);
+    assert.equal(thirdAccount.registryIndex.toNumber(), 0);
+
+    // second ticket moves into the first slot
+    await redeem(program, config, third, thirdBump, null);
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.ticketCount.toNumber(), 1);
+    const [firstEntry, _firstEntryBump] = await ticketEntryAddress(
+      program,
+      config,
+      new anchor.BN(0)
+    );
+    const firstEntryAccount = await program.account.ticketEntry.fetch(
+      firstEntry
+    );
+    assert.ok(firstEntryAccount.ticket.equals(second));
+  });
+});
+
+describe("Rollover", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Rolls unclaimed prizes into the next jackpot", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 1;
+    const claimDurationSeconds = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    // no cap, the jackpot keeps rolling over
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+
+    const [firstDrawRecord, _firstDrawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const firstDrawRecordAccount = await program.account.drawRecord.fetch(
+      firstDrawRecord
+    );
+    let vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(
+      vaultMgrAccount.jackpot.toNumber(),
+      firstDrawRecordAccount.prizeAmount.toNumber()
+    );
+
+    // nobody holds the winning numbers, counted when the draw is dispensed
+    assert.equal(vaultMgrAccount.rolloverCount.toNumber(), 1);
+
+    await sleep(registrationDurationSeconds + claimDurationSeconds + 1);
+    const events = await captureEvents(
+      program,
+      ["JackpotRolledOver"],
+      async () => {
+        await expireDrawRecord(program, config, 1, null);
+      }
+    );
+    assert.equal(events.length, 1);
+    assert.equal(
+      events[0].data.amount.toNumber(),
+      firstDrawRecordAccount.reserved.toNumber()
+    );
+    assert.equal(events[0].data.rolloverCount.toNumber(), 1);
+
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(
+      vaultMgrAccount.rolledOver.toNumber(),
+      firstDrawRecordAccount.reserved.toNumber()
+    );
+    assert.equal(vaultMgrAccount.rolloverCount.toNumber(), 1);
+
+    // released prize is part of the next draw
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+
+    const [secondDrawRecord, _secondDrawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(2)
+    );
+    const secondDrawRecordAccount = await program.account.drawRecord.fetch(
+      secondDrawRecord
+    );
+    assert.equal(
+      secondDrawRecordAccount.rolledOver.toNumber(),
+      firstDrawRecordAccount.reserved.toNumber()
+    );
+    assert.ok(
+      secondDrawRecordAccount.prizeAmount.gte(
+        secondDrawRecordAccount.rolledOver
+      )
+    );
+    assert.equal(secondDrawRecordAccount.consolation, false);
+
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.rolledOver.toNumber(), 0);
+    assert.equal(vaultMgrAccount.rolloverCount.toNumber(), 2);
+  });
+
+  it("Jackpot holder resets the rollover count", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    // first draw rolls over before its record expires
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+
+    let vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.rolloverCount.toNumber(), 1);
+
+    // every ticket of the domain holds the winning numbers of the next draw
+    await redeem(program, config, ticket, ticketBump, null);
+    await updateConfig(
+      program,
+      config,
+      { numberDomain: SURE_WIN_DOMAIN },
+      null
+    );
+    await buy(program, SURE_WIN_NUMBERS, config, null);
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.rolloverCount.toNumber(), 0);
+  });
+
+  it("Draws a consolation winner after too many rollovers", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 1;
+    const claimDurationSeconds = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+      }
+    );
+    await tokenSwapInit(program, config);
+    await updateConfig(program, config, { maxRollovers: new anchor.BN(1) });
+
+    const [ticket, _ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+    await sleep(registrationDurationSeconds + claimDurationSeconds + 1);
+    await expireDrawRecord(program, config, 1, null);
+
+    // cap reached, the only ticket is drawn even though its numbers miss
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+    const events = await captureEvents(
+      program,
+      ["WinningTicketDrawn", "DrawDispensed"],
+      async () => {
+        await dispense(program, config, winningNumbers, null);
+      }
+    );
+    const drawn = events.filter((e) => e.name == "WinningTicketDrawn");
+    assert.equal(drawn.length, 1);
+    assert.ok(drawn[0].data.ticket.equals(ticket));
+    const dispensed = events.filter((e) => e.name == "DrawDispensed");
+    assert.equal(dispensed[0].data.consolation, true);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(2)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(drawRecordAccount.consolation, true);
+    assert.ok(drawRecordAccount.lotteryMode.ticket);
+    assert.ok(drawRecordAccount.winningTicket.equals(ticket));
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.rolloverCount.toNumber(), 0);
+
+    await registerMatch(program, config, 2, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 2, ticket, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      drawRecordAccount.prizeAmount.toNumber()
+    );
+  });
+});
+
+describe("Insolvency", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Dispenses no prize when the pool lost value", async () => {
+    const drawDurationSeconds = 1;
+
+    // no yield tokens to start with, swap fees leave the pool under water
+    const config = await initialize(program, drawDurationSeconds, 100, 0);
+    await tokenSwapInit(program, config);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+    await buyNTickets(program, config, 20);
+    await stake(program, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    const events = await captureEvents(
+      program,
+      ["PoolInsolvent", "DrawDispensed"],
+      async () => {
+        await dispense(program, config, winningNumbers, null);
+      }
+    );
+    const insolvent = events.filter((e) => e.name == "PoolInsolvent");
+    assert.equal(insolvent.length, 1);
+    assert.equal(insolvent[0].data.totalPrincipal.toNumber(), 21);
+    assert.ok(insolvent[0].data.poolValue.toNumber() < 21);
+    assert.equal(
+      insolvent[0].data.shortfall.toNumber(),
+      21 - insolvent[0].data.poolValue.toNumber()
+    );
+
+    // draw went ahead without a prize
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(drawRecordAccount.prizeAmount.toNumber(), 0);
+    assert.equal(drawRecordAccount.reserved.toNumber(), 0);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.drawId.toNumber(), 2);
+    assert.equal(vaultMgrAccount.insolvent, true);
+  });
+
+  it("Reports pool health", async () => {
+    const drawDurationSeconds = 1;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      100,
+      yieldVaultInitBalance
+    );
+    await tokenSwapInit(program, config);
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // yield tokens are worth something, pool is over-collateralized
+    const report = await poolHealth(program, config);
+    assert.equal(report.totalPrincipal.toNumber(), 1);
+    assert.equal(report.depositAmount.toNumber(), 1);
+    assert.ok(report.positionValue.toNumber() > 0);
+    assert.equal(
+      report.poolValue.toNumber(),
+      report.depositAmount.toNumber() + report.positionValue.toNumber()
+    );
+    assert.equal(report.insolvent, false);
+  });
+
+  it("Reports pool health before the yield source is set", async () => {
+    const drawDurationSeconds = 1;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      100,
+      yieldVaultInitBalance
+    );
+
+    // pool accounts stored on vault_manager are still unset
+    for (const key of [
+      SWAP_YIELD_VAULT,
+      SWAP_DEPOSIT_VAULT,
+      POOL_MINT,
+      TOKEN_SWAP_ACCOUNT,
+      TOKEN_SWAP_ACCOUNT_AUTHORITY,
+      POOL_FEE,
+    ]) {
+      config.keys.set(key, anchor.web3.PublicKey.default);
+    }
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // no position is valued without a pool
+    const report = await poolHealth(program, config);
+    assert.equal(report.totalPrincipal.toNumber(), 1);
+    assert.equal(report.depositAmount.toNumber(), 1);
+    assert.equal(report.positionValue.toNumber(), 0);
+    assert.equal(report.poolValue.toNumber(), 1);
+    assert.equal(report.insolvent, false);
+  });
+
+  it("Redeems pro-rata when under-collateralized", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds, 100, 0);
+    await tokenSwapInit(program, config);
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await buyNTickets(program, config, 20);
+    await stake(program, config, null);
+
+    // swap fees leave the pool worth less than the 21 tickets
+    const before = await poolHealth(program, config);
+    assert.equal(before.totalPrincipal.toNumber(), 21);
+    assert.equal(before.insolvent, true);
+
+    const events = await captureEvents(
+      program,
+      ["PoolInsolvent", "TicketRedeemed"],
+      async () => {
+        await redeem(program, config, ticket, ticketBump, null);
+      }
+    );
+
+    // pool enters the insolvent state and the ticket takes its share of the loss
+    const insolvent = events.filter((e) => e.name == "PoolInsolvent");
+    assert.equal(insolvent.length, 1);
+    const redeemed = events.filter((e) => e.name == "TicketRedeemed");
+    assert.equal(redeemed.length, 1);
+    assert.equal(
+      redeemed[0].data.amount.toNumber(),
+      Math.floor(before.poolValue.toNumber() / 21)
+    );
+
+    const after = await poolHealth(program, config);
+    assert.equal(after.totalPrincipal.toNumber(), 20);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.insolvent, true);
+  });
+});
+
+describe("Delegation", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Swaps leave no delegate on the vaults", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 100;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = [1, 2, 3, 4, 5, 6];
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    await buyNTickets(program, config, 20);
+
+    // stake swaps from deposit_vault
+    await stake(program, config, null);
+    await assertNoDelegate(program, config.keys.get(DEPOSIT_VAULT));
+    await assertNoDelegate(program, config.keys.get(YIELD_VAULT));
+
+    // dispense swaps from yield_vault
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+    await assertNoDelegate(program, config.keys.get(DEPOSIT_VAULT));
+    await assertNoDelegate(program, config.keys.get(YIELD_VAULT));
+
+    // stake everything so redeem has to swap from yield_vault
+    await updateConfig(
+      program,
+      config,
+      { depositTokenReserve: new anchor.BN(0) },
+      null
+    );
+    await stake(program, config, null);
+    await assertBalance(program, config.keys.get(DEPOSIT_VAULT), 0);
+
+    await redeem(program, config, ticket, ticketBump, null);
+    await assertNoDelegate(program, config.keys.get(DEPOSIT_VAULT));
+    await assertNoDelegate(program, config.keys.get(YIELD_VAULT));
+  });
+});
+
+describe("Treasury", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Fee is moved to the treasury on dispense", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+    const feeBps = 3000;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        feeBps: feeBps,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+
+    // 30% of the 7 tokens yield, rounded down
+    const feeAmount = 2;
+    await assertBalance(program, config.keys.get(TREASURY), feeAmount);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.totalFeesCollected.toNumber(), feeAmount);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(drawRecordAccount.feeAmount.toNumber(), feeAmount);
+    assert.equal(
+      drawRecordAccount.prizeAmount.toNumber(),
+      yieldVaultInitBalance - 3 - feeAmount
+    );
+
+    // winner receives the prize after the fee
+    await registerMatch(program, config, 1, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      yieldVaultInitBalance - 3 - feeAmount
+    );
+  });
+
+  it("Fee is not charged again on a rolled over prize", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 1;
+    const claimDurationSeconds = 1;
+    const feeBps = 3000;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+        feeBps: feeBps,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+
+    // 30% of the 7 tokens yield, rounded down
+    const feeAmount = 2;
+    const prizeAmount = yieldVaultInitBalance - 3 - feeAmount;
+    await assertBalance(program, config.keys.get(TREASURY), feeAmount);
+
+    // nobody claims, the prize is released into the next draw
+    await sleep(registrationDurationSeconds + claimDurationSeconds + 1);
+    await expireDrawRecord(program, config, 1, null);
+
+    // no new yield, the whole prize is the rollover
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+    await assertBalance(program, config.keys.get(TREASURY), feeAmount);
+
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(2)
+    );
+    const drawRecordAccount = await program.account.drawRecord.fetch(
+      drawRecord
+    );
+    assert.equal(drawRecordAccount.feeAmount.toNumber(), 0);
+    assert.equal(drawRecordAccount.rolledOver.toNumber(), prizeAmount);
+    assert.equal(drawRecordAccount.prizeAmount.toNumber(), prizeAmount);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.totalFeesCollected.toNumber(), feeAmount);
+  });
+
+  it("Only the authority withdraws the treasury", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const feeBps = 10000;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      { feeBps: feeBps, numberDomain: SURE_WIN_DOMAIN }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+
+    // whole yield is taken as fee
+    const feeAmount = yieldVaultInitBalance - 3;
+    await assertBalance(program, config.keys.get(TREASURY), feeAmount);
+
+    const otherUser = await newUser(program, config, 0);
+    await withdrawTreasury(
+      program,
+      config,
+      feeAmount,
+      otherUser.depositAta,
+      program.idl.errors[34].code,
+      otherUser
+    );
+
+    // cannot withdraw more than was collected
+    await withdrawTreasury(
+      program,
+      config,
+      feeAmount + 1,
+      config.keys.get(USER_DEPOSIT_ATA),
+      program.idl.errors[5].code
+    );
+
+    await withdrawTreasury(
+      program,
+      config,
+      feeAmount,
+      config.keys.get(USER_DEPOSIT_ATA),
+      null
+    );
+    await assertBalance(program, config.keys.get(TREASURY), 0);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), feeAmount);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.totalFeesCollected.toNumber(), feeAmount);
+    assert.equal(vaultMgrAccount.totalFeesWithdrawn.toNumber(), feeAmount);
+  });
+
+  it("Fee above 10000 bps", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+
+    await updateConfig(
+      program,
+      config,
+      { feeBps: 10001 },
+      program.idl.errors[39].code
+    );
+    await updateConfig(program, config, { feeBps: 500 }, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.feeBps, 500);
+  });
+});
+
+describe("Slippage", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Dispense swap checked against the caller's quote", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance
+    );
+    await tokenSwapInit(program, config);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.slippageBps, 100);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    const numbers = await fulfill(program, config);
+
+    // pool pays out less than quoted
+    await dispense(
+      program,
+      config,
+      numbers,
+      program.idl.errors[40].code,
+      null,
+      yieldVaultInitBalance * 2
+    );
+
+    // swap fees reduce the amount returned
+    await dispense(
+      program,
+      config,
+      numbers,
+      null,
+      null,
+      yieldVaultInitBalance - 3
+    );
+    await assertBalance(
+      program,
+      config.keys.get(DEPOSIT_VAULT),
+      userDepositAtaBalance + yieldVaultInitBalance - 3
+    );
+  });
+
+  it("Stake swap checked against the caller's quote", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    await buyNTickets(program, config, 20);
+
+    // 10 tokens are staked, the pool cannot pay out 100
+    await stake(program, config, program.idl.errors[40].code, 100);
+    await stake(program, config, null);
+  });
+
+  it("Redeem checked against the caller's quote of the position", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await buyNTickets(program, config, 20);
+    await stake(program, config, null);
+
+    // the position of 11 staked tokens is not worth 100
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[40].code,
+      null,
+      100
+    );
+    await redeem(program, config, ticket, ticketBump, null);
+  });
+
+  it("Slippage above 10000 bps", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+
+    await updateConfig(
+      program,
+      config,
+      { slippageBps: 10001 },
+      program.idl.errors[41].code
+    );
+    await updateConfig(program, config, { slippageBps: 50 }, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.slippageBps, 50);
+  });
+});
+
+describe("Pause", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Pause stops buy and stake, redeem keeps working", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    const numbers = [1, 2, 3, 4, 5, 6];
+    const [ticket, ticketBump] = await buy(program, numbers, config, null);
+    await buyNTickets(program, config, 20);
+
+    await pause(program, config, null);
+
+    await buy(program, numbers, config, program.idl.errors[37].code);
+    await stake(program, config, program.idl.errors[37].code);
+
+    // users can always exit
+    await redeem(program, config, ticket, ticketBump, null);
+
+    await unpause(program, config, null);
+
+    await buy(program, numbers, config, null);
+    await stake(program, config, null);
+  });
+
+  it("Pause stops dispense, prize claims keep working", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    await pause(program, config, null);
+    await dispense(program, config, numbers, program.idl.errors[37].code);
+    await unpause(program, config, null);
+    await dispense(program, config, numbers, null);
+
+    // winners can register and claim while paused
+    await pause(program, config, null);
+    await registerMatch(program, config, 1, ticket, null);
+    await sleep(registrationDurationSeconds + 1);
+    await claimPrize(program, config, 1, ticket, null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      yieldVaultInitBalance - 3
+    );
+  });
+
+  it("Redeem during a paused draw", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+    await tokenSwapInit(program, config);
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await buy(program, [7, 8, 9, 10, 11, 12], config, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    // locked until dispense
+    await redeem(
+      program,
+      config,
+      ticket,
+      ticketBump,
+      program.idl.errors[44].code
+    );
+
+    // dispense is stopped, users can still exit
+    await pause(program, config, null);
+    await redeem(program, config, ticket, ticketBump, null);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 1);
+
+    // winners are registered by number, the draw goes ahead once unpaused
+    let vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, true);
+
+    await unpause(program, config, null);
+    await dispense(program, config, winningNumbers, null);
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+  });
+
+  it("Redeem during a paused ticket draw cancels the draw", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      0,
+      { lotteryMode: { ticket: {} } }
+    );
+    await tokenSwapInit(program, config);
+
+    const [ticket, ticketBump] = await buy(
+      program,
+      [1, 2, 3, 4, 5, 6],
+      config,
+      null
+    );
+    await buy(program, [7, 8, 9, 10, 11, 12], config, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await fulfill(program, config);
+
+    await pause(program, config, null);
+
+    // the winner would be drawn from a registry that changed after draw
+    const events = await captureEvents(
+      program,
+      ["DrawCancelled"],
+      async () => {
+        await redeem(program, config, ticket, ticketBump, null);
+      }
+    );
+    assert.equal(events.length, 1);
+    assert.equal(events[0].data.drawId.toNumber(), 1);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+    assert.equal(vaultMgrAccount.ticketCount.toNumber(), 1);
+    assert.equal(vaultMgrAccount.drawId.toNumber(), 1);
+    await assertBalance(program, config.keys.get(USER_DEPOSIT_ATA), 1);
+
+    // drawn again with new randomness once unpaused, the oracle commits a
+    // new nonce since the cancelled request may have been signed
+    await unpause(program, config, null);
+    await commitOracleNonce(program, config, null);
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+    await dispense(program, config, winningNumbers, null);
+  });
+
+  it("Pause stops buy_many and buy_quick_pick", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 10;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance
+    );
+
+    await pause(program, config, null);
+
+    await buyMany(
+      program,
+      config,
+      [
+        [1, 2, 3, 4, 5, 6],
+        [7, 8, 9, 10, 11, 12],
+      ],
+      program.idl.errors[37].code
+    );
+    await buyQuickPick(
+      program,
+      config,
+      new anchor.BN(0),
+      program.idl.errors[37].code
+    );
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance
+    );
+
+    await unpause(program, config, null);
+
+    await buyMany(
+      program,
+      config,
+      [
+        [1, 2, 3, 4, 5, 6],
+        [7, 8, 9, 10, 11, 12],
+      ],
+      null
+    );
+    await buyQuickPick(program, config, new anchor.BN(0), null);
+    await assertBalance(
+      program,
+      config.keys.get(USER_DEPOSIT_ATA),
+      userDepositAtaBalance - 3
+    );
+  });
+
+  it("Draw, fulfill and update_config keep working while paused", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    await pause(program, config, null);
+    await updateConfig(program, config, { feeBps: 500 }, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    // the drawn numbers wait for dispense until unpaused
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.feeBps, 500);
+    assert.equal(vaultMgrAccount.locked, true);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+
+    await dispense(
+      program,
+      config,
+      winningNumbers,
+      program.idl.errors[37].code
+    );
+    await unpause(program, config, null);
+    await dispense(program, config, winningNumbers, null);
+  });
+
+  it("Pause in the middle of a draw", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+
+    const numbers = [1, 2, 3, 4, 5, 6];
+    await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    // randomness requested before the pause is still delivered
+    await pause(program, config, null);
+    const winningNumbers = await fulfill(program, config);
+
+    await dispense(
+      program,
+      config,
+      winningNumbers,
+      program.idl.errors[37].code
+    );
+    await buy(program, numbers, config, program.idl.errors[37].code);
+
+    let vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, true);
+    assert.equal(vaultMgrAccount.drawId.toNumber(), 1);
+
+    // the draw completes once unpaused
+    await unpause(program, config, null);
+    await dispense(program, config, winningNumbers, null);
+
+    vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.drawId.toNumber(), 2);
+  });
+
+  it("Commit and reveal keep working while paused", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      randomnessMode: { commitReveal: {} },
+      revealDurationSeconds: 10,
+    });
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    await pause(program, config, null);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    // wait for the reveal slot to be produced
+    await sleep(2);
+    await reveal(program, config, secret, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, true);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+  });
+
+  it("Expire draw keeps working while paused", async () => {
+    const drawDurationSeconds = 3;
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      randomnessMode: { commitReveal: {} },
+      revealDurationSeconds: 1,
+    });
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    const secret = Array.from(crypto.randomBytes(32));
+    await commit(program, config, sha256(secret), null);
+
+    // wait for cutoff_time to expire
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+
+    await pause(program, config, null);
+
+    // let the reveal deadline pass
+    await sleep(3);
+    await expireDraw(program, config, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.locked, false);
+    assert.equal(vaultMgrAccount.randomnessPending, false);
+  });
+
+  it("Expire draw record keeps working while paused", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+    const claimDurationSeconds = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+    await draw(program, config, null);
+    await fulfill(program, config);
+    await dispense(program, config, numbers, null);
+    await registerMatch(program, config, 1, ticket, null);
+
+    await pause(program, config, null);
+    await sleep(registrationDurationSeconds + claimDurationSeconds + 1);
+
+    // unclaimed prizes are released while paused
+    await expireDrawRecord(program, config, 1, null);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.equal(vaultMgrAccount.unclaimedPrizes.toNumber(), 0);
+  });
+
+  it("Guardian can pause, only the authority can unpause", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const guardian = await newUser(program, config);
+    const otherUser = await newUser(program, config);
+
+    // only the authority sets the guardian
+    await setGuardian(
+      program,
+      config,
+      guardian.keypair.publicKey,
+      program.idl.errors[34].code,
+      otherUser
+    );
+    await setGuardian(program, config, guardian.keypair.publicKey, null);
+
+    await pause(program, config, program.idl.errors[34].code, otherUser);
+    await pause(program, config, null, guardian);
+
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.ok(vaultMgrAccount.paused);
+    assert.equal(vaultMgrAccount.locked, false);
+
+    // already paused
+    await pause(program, config, program.idl.errors[37].code);
+
+    await unpause(program, config, program.idl.errors[34].code, guardian);
+    await unpause(program, config, null);
+
+    // not paused anymore
+    await unpause(program, config, program.idl.errors[38].code);
+  });
+});
+
+describe("Events", () => {
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.NoLossLottery as Program<NoLossLottery>;
+
+  it("Buy and redeem emit ticket events", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+    const numbers = [1, 2, 3, 4, 5, 6];
+
+    let ticket = null;
+    let ticketBump = null;
+    const purchased = await captureEvents(
+      program,
+      ["TicketPurchased"],
+      async () => {
+        [ticket, ticketBump] = await buy(program, numbers, config, null);
+      }
+    );
+    assert.equal(purchased.length, 1);
+    assert.ok(purchased[0].data.ticket.equals(ticket));
+    assert.ok(
+      purchased[0].data.vaultManager.equals(config.keys.get(VAULT_MANAGER))
+    );
+    assert.deepEqual(purchased[0].data.numbers, numbers);
+    assert.equal(purchased[0].data.drawId.toNumber(), 1);
+    assert.equal(purchased[0].data.price.toNumber(), 1);
+
+    const redeemed = await captureEvents(
+      program,
+      ["TicketRedeemed"],
+      async () => {
+        await redeem(program, config, ticket, ticketBump, null);
+      }
+    );
+    assert.equal(redeemed.length, 1);
+    assert.ok(redeemed[0].data.ticket.equals(ticket));
+    assert.deepEqual(redeemed[0].data.numbers, numbers);
+    assert.equal(redeemed[0].data.amount.toNumber(), 1);
+  });
+
+  it("Draw lifecycle emits events", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 2;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        numberDomain: SURE_WIN_DOMAIN,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    const numbers = SURE_WIN_NUMBERS;
+    const [ticket, _ticketBump] = await buy(program, numbers, config, null);
+
+    await sleep(drawDurationSeconds + 1);
+
+    const events = await captureEvents(
+      program,
+      [
+        "DrawStarted",
+        "WinningNumbersDrawn",
+        "SwapExecuted",
+        "DrawDispensed",
+        "MatchRegistered",
+        "WinnerPaid",
+      ],
+      async () => {
+        await draw(program, config, null);
+        await fulfill(program, config);
+        await dispense(program, config, numbers, null);
+        await registerMatch(program, config, 1, ticket, null);
+        await sleep(registrationDurationSeconds + 1);
+        await claimPrize(program, config, 1, ticket, null);
+      }
+    );
+    assert.deepEqual(
+      events.map((e) => e.name),
+      [
+        "DrawStarted",
+        "WinningNumbersDrawn",
+        "SwapExecuted",
+        "DrawDispensed",
+        "MatchRegistered",
+        "WinnerPaid",
+      ]
+    );
+
+    const [started, drawn, swapped, dispensed, registered, paid] = events;
+    assert.equal(started.data.drawId.toNumber(), 1);
+    assert.equal(started.data.ticketSupply.toNumber(), 1);
+    assert.deepEqual(drawn.data.winningNumbers, numbers);
+    assert.ok(
+      swapped.data.destinationVault.equals(config.keys.get(DEPOSIT_VAULT))
+    );
+    assert.equal(swapped.data.amountIn.toNumber(), yieldVaultInitBalance);
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(1)
+    );
+    assert.ok(dispensed.data.drawRecord.equals(drawRecord));
+    assert.equal(
+      dispensed.data.prizeAmount.toNumber(),
+      yieldVaultInitBalance - 3
+    );
+    assert.ok(registered.data.ticket.equals(ticket));
+    assert.equal(registered.data.tier, 3);
+    assert.ok(paid.data.ticket.equals(ticket));
+    assert.equal(paid.data.amount.toNumber(), yieldVaultInitBalance - 3);
+  });
+
+  it("Expiring a draw nobody won emits NoWinner", async () => {
+    const drawDurationSeconds = 1;
+    const userDepositAtaBalance = 1;
+    const yieldVaultInitBalance = 10;
+    const registrationDurationSeconds = 1;
+    const claimDurationSeconds = 1;
+
+    const config = await initialize(
+      program,
+      drawDurationSeconds,
+      userDepositAtaBalance,
+      yieldVaultInitBalance,
+      {
+        registrationDurationSeconds: registrationDurationSeconds,
+        claimDurationSeconds: claimDurationSeconds,
+      }
+    );
+    await tokenSwapInit(program, config);
+
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+    await sleep(drawDurationSeconds + 1);
+
+    await draw(program, config, null);
+    await dispense(program, config, await fulfill(program, config), null);
+    await sleep(registrationDurationSeconds + claimDurationSeconds + 1);
+
+    const events = await captureEvents(program, ["NoWinner"], async () => {
+      await expireDrawRecord(program, config, 1, null);
+    });
+    assert.equal(events.length, 1);
+    assert.equal(events[0].data.drawId.toNumber(), 1);
+    assert.equal(
+      events[0].data.releasedAmount.toNumber(),
+      yieldVaultInitBalance - 3
+    );
+  });
+
+  it("Commitments and postponed draws emit events", async () => {
+    const drawDurationSeconds = 2;
+
+    const config = await initialize(program, drawDurationSeconds, 1, 0, {
+      randomnessMode: { commitReveal: {} },
+      revealDurationSeconds: 10,
+    });
+    await buy(program, [1, 2, 3, 4, 5, 6], config, null);
+
+    // nobody commits before the cutoff, expire_draw pushes it back
+    await sleep(drawDurationSeconds + 1);
+    const postponed = await captureEvents(
+      program,
+      ["DrawPostponed"],
+      async () => {
+        await expireDraw(program, config, null);
+      }
+    );
+    assert.equal(postponed.length, 1);
+    assert.equal(postponed[0].data.drawId.toNumber(), 1);
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    assert.ok(postponed[0].data.cutoffTime.eq(vaultMgrAccount.cutoffTime));
+
+    const commitment = sha256(Array.from(crypto.randomBytes(32)));
+    const committed = await captureEvents(program, ["Committed"], async () => {
+      await commit(program, config, commitment, null);
+    });
+    assert.equal(committed.length, 1);
+    assert.ok(
+      committed[0].data.committer.equals(program.provider.wallet.publicKey)
+    );
+    assert.deepEqual(committed[0].data.commitment, commitment);
+  });
+
+  it("Authority and guardian changes emit events", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    const newAuthority = await newUser(program, config);
+    const guardian = anchor.web3.Keypair.generate().publicKey;
+
+    const events = await captureEvents(
+      program,
+      ["AuthorityTransferProposed", "GuardianUpdated"],
+      async () => {
+        await transferAuthority(
+          program,
+          config,
+          newAuthority.keypair.publicKey,
+          null
+        );
+        await setGuardian(program, config, guardian, null);
+      }
+    );
+    assert.deepEqual(
+      events.map((e) => e.name),
+      ["AuthorityTransferProposed", "GuardianUpdated"]
+    );
+
+    const [proposed, guardianUpdated] = events;
+    assert.ok(
+      proposed.data.authority.equals(program.provider.wallet.publicKey)
+    );
+    assert.ok(
+      proposed.data.pendingAuthority.equals(newAuthority.keypair.publicKey)
+    );
+    assert.ok(
+      guardianUpdated.data.previousGuardian.equals(
+        anchor.web3.PublicKey.default
+      )
+    );
+    assert.ok(guardianUpdated.data.guardian.equals(guardian));
+  });
+
+  it("Stake emits Staked and SwapExecuted", async () => {
+    const drawDurationSeconds = 1;
+
+    const config = await initialize(program, drawDurationSeconds);
+    await tokenSwapInit(program, config);
+    await buyNTickets(program, config, 20);
+
+    const events = await captureEvents(
+      program,
+      ["SwapExecuted", "Staked"],
+      async () => {
+        await stake(program, config, null);
+      }
+    );
+    assert.deepEqual(
+      events.map((e) => e.name),
+      ["SwapExecuted", "Staked"]
+    );
+    assert.ok(
+      events[0].data.destinationVault.equals(config.keys.get(YIELD_VAULT))
+    );
+    assert.equal(
+      events[1].data.amountIn.toNumber(),
+      events[0].data.amountIn.toNumber()
+    );
+    assert.ok(events[1].data.amountOut.toNumber() > 0);
+  });
+});
+
+// collect events emitted by the program while running action
+async function captureEvents(
+  program: Program<NoLossLottery>,
+  names: Array<string>,
+  action: () => Promise<void>
+): Promise<Array<{ name: string; data: any }>> {
+  const events = [];
+  const listeners = names.map((name) =>
+    program.addEventListener(name, (data, _slot) => {
+      events.push({ name: name, data: data });
+    })
+  );
+
+  try {
+    await action();
+    // give the websocket time to deliver the logs
+    await sleep(1);
+  } finally {
+    for (const listener of listeners) {
+      await program.removeEventListener(listener);
+    }
+  }
+
+  return events;
+}
+
+// create new Account and seed with lamports
+async function newAccountWithLamports(
+  connection: anchor.web3.Connection,
+  lamports: number = 100_000_000
+): Promise<anchor.web3.Account> {
+  // generate keypair
+  const account = new anchor.web3.Account();
+
+  // airdrop lamports
+  let txSig = await connection.requestAirdrop(account.publicKey, lamports);
+  await connection.confirmTransaction(txSig);
+  console.log("airdropTxSig:", txSig);
+
+  // check account balance
+  const lamportsBalance = await connection.getBalance(account.publicKey);
+  console.log("lamports balance:", lamportsBalance);
+
+  return account;
+}
+
+// sleep current thread in seconds
+async function sleep(seconds: number) {
+  const ms = seconds * 1000;
+  return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+// balance of a token account
+async function tokenAmount(
+  program: Program<NoLossLottery>,
+  tokenAccount: anchor.web3.PublicKey
+): Promise<anchor.BN> {
+  const balance = await program.provider.connection.getTokenAccountBalance(
+    tokenAccount
+  );
+  return new anchor.BN(balance.value.amount);
+}
+
+// output of swapping amountIn deposit tokens, or yield tokens unless
+// `deposit`, through the test pool at its current reserves
+async function poolQuote(
+  program: Program<NoLossLottery>,
+  config: Config,
+  amountIn: anchor.BN,
+  deposit: boolean
+): Promise<anchor.BN> {
+  if (amountIn.isZero()) {
+    return amountIn;
+  }
+  const fees = {
+    tradeFeeNumerator: new anchor.BN(TRADING_FEE_NUMERATOR),
+    tradeFeeDenominator: new anchor.BN(TRADING_FEE_DENOMINATOR),
+    ownerTradeFeeNumerator: new anchor.BN(OWNER_TRADING_FEE_NUMERATOR),
+    ownerTradeFeeDenominator: new anchor.BN(OWNER_TRADING_FEE_DENOMINATOR),
+  };
+  const depositReserve = await tokenAmount(
+    program,
+    config.keys.get(SWAP_DEPOSIT_VAULT)
+  );
+  const yieldReserve = await tokenAmount(
+    program,
+    config.keys.get(SWAP_YIELD_VAULT)
+  );
+  return deposit
+    ? swapQuote(amountIn, depositReserve, yieldReserve, fees)
+    : swapQuote(amountIn, yieldReserve, depositReserve, fees);
+}
+
+// caller's quote of the whole yield position, `expectedAmountOut` if given
+async function positionQuote(
+  program: Program<NoLossLottery>,
+  config: Config,
+  expectedAmountOut: number | null
+): Promise<anchor.BN> {
+  if (expectedAmountOut !== null) {
+    return new anchor.BN(expectedAmountOut);
+  }
+  const yieldAmount = await tokenAmount(program, config.keys.get(YIELD_VAULT));
+  return poolQuote(program, config, yieldAmount, false);
+}
+
+async function initialize(
+  program: Program<NoLossLottery>,
+  drawDurationSeconds: number,
+  userDepositAtaBalance = 100,
+  yieldVaultInitBalance = 0,
+  options: InitializeOptions = {}
+): Promise<Config> {
+  const mintAuthority = await newAccountWithLamports(
+    program.provider.connection
+  );
+
+  // create deposit mint for testing
+  const depositMint = await spl.createMint(
+    program.provider.connection,
+    mintAuthority,
+    mintAuthority.publicKey,
+    null,
+    9
+  );
+  console.log("deposit test mint created");
+
+  // create yield mint for testing
+  const yieldMint = await spl.createMint(
+    program.provider.connection,
+    mintAuthority,
+    mintAuthority.publicKey,
+    null,
+    9
+  );
+  console.log("yield test mint created");
+
+  // get PDAs
+
+  const [depositVault, depositVaultBump] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [depositMint.toBuffer()],
+      program.programId
+    );
+
+  const [yieldVault, yieldVaultBump] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [yieldMint.toBuffer()],
+      program.programId
+    );
+
+  const [vaultMgr, vaultMgrBump] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [
+        depositMint.toBuffer(),
+        yieldMint.toBuffer(),
+        depositVault.toBuffer(),
+        yieldVault.toBuffer(),
+      ],
+      program.programId
+    );
+
+  const [tickets, ticketsBump] = await anchor.web3.PublicKey.findProgramAddress(
+    [
+      depositMint.toBuffer(),
+      yieldMint.toBuffer(),
+      depositVault.toBuffer(),
+      yieldVault.toBuffer(),
+      vaultMgr.toBuffer(),
+    ],
+    program.programId
+  );
+
+  const [treasury, treasuryBump] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [Buffer.from("treasury"), vaultMgr.toBuffer()],
+      program.programId
+    );
+
+  // oracle key signing randomness requests
+  const oracle = anchor.web3.Keypair.generate();
+  const randomnessOracle = oracle.publicKey;
+
+  // ticket price in tokens
+  const ticketPrice = new anchor.BN(1);
+
+  // init vault
+  const initTxSig = await program.rpc.initialize(
+    new anchor.BN(drawDurationSeconds),
+    ticketPrice,
+    options.randomnessMode ?? { oracle: {} },
+    new anchor.BN(options.revealDurationSeconds ?? 0),
+    new anchor.BN(options.commitBond ?? COMMIT_BOND),
+    new anchor.BN(options.fulfillDurationSeconds ?? 60 * 60),
+    new anchor.BN(options.registrationDurationSeconds ?? 2),
+    new anchor.BN(options.claimDurationSeconds ?? 60 * 60),
+    options.prizeTiers ?? [0, 0, 0, 10000],
+    options.feeBps ?? 0,
+    options.lotteryMode ?? { numbers: {} },
+    options.numberDomain ?? { pickCount: 6, maxNumber: 49, unique: false },
+    {
+      accounts: {
+        depositMint: depositMint,
+        yieldMint: yieldMint,
+        depositVault: depositVault,
+        yieldVault: yieldVault,
+        vaultManager: vaultMgr,
+        tickets: tickets,
+        treasury: treasury,
+        randomnessOracle: randomnessOracle,
+        user: program.provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+    }
+  );
+  console.log("initTxSig:", initTxSig);
+
+  // get user ata
+  const userDepositAta = await spl.getOrCreateAssociatedTokenAccount(
+    program.provider.connection,
+    mintAuthority,
+    depositMint,
+    program.provider.wallet.publicKey
+  );
+
+  // mint tokens to user_ata
+  await spl.mintTo(
+    program.provider.connection,
+    mintAuthority,
+    depositMint,
+    userDepositAta.address,
+    mintAuthority.publicKey,
+    userDepositAtaBalance
+  );
+  console.log("minted %d tokens to user_ata", userDepositAtaBalance);
+
+  // the user is the commit authority and posts the bond from its ata
+  const commitReveal =
+    options.randomnessMode && "commitReveal" in options.randomnessMode;
+  if (commitReveal && (options.commitBond ?? COMMIT_BOND) > 0) {
+    await spl.mintTo(
+      program.provider.connection,
+      mintAuthority,
+      depositMint,
+      userDepositAta.address,
+      mintAuthority.publicKey,
+      options.commitBond ?? COMMIT_BOND
+    );
+  }
+
+  // get user tickets ata
+  const userTicketsAta = await spl.getOrCreateAssociatedTokenAccount(
+    program.provider.connection,
+    mintAuthority,
+    tickets,
+    program.provider.wallet.publicKey
+  );
+
+  // mint tokens to yield vault for testing
+  await spl.mintTo(
+    program.provider.connection,
+    mintAuthority,
+    yieldMint,
+    yieldVault,
+    mintAuthority.publicKey,
+    yieldVaultInitBalance
+  );
+  console.log("minted %d tokens to yield vault", yieldVaultInitBalance);
+
+  let keys = new Map<String, anchor.web3.PublicKey>();
+  keys.set(DEPOSIT_VAULT, depositVault);
+  keys.set(DEPOSIT_MINT, depositMint);
+  keys.set(YIELD_VAULT, yieldVault);
+  keys.set(YIELD_MINT, yieldMint);
+  keys.set(VAULT_MANAGER, vaultMgr);
+  keys.set(MINT_AUTHORITY, mintAuthority.publicKey);
+  keys.set(TICKETS, tickets);
+  keys.set(USER_DEPOSIT_ATA, userDepositAta.address);
+  keys.set(USER_TICKET_ATA, userTicketsAta.address);
+  keys.set(RANDOMNESS_ORACLE, randomnessOracle);
+  keys.set(TREASURY, treasury);
+
+  const config: Config = {
+    keys: keys,
+    mintAuthority: mintAuthority,
+    oracle: oracle,
+    oracleNonceSeed: null,
+  };
+
+  // the first oracle draw needs a committed nonce
+  if (!commitReveal) {
+    await commitOracleNonce(program, config, null);
+  }
+
+  return config;
+}
+
+// create a funded wallet with deposit tokens for a lottery
+async function newUser(
+  program: Program<NoLossLottery>,
+  config: Config,
+  userDepositAtaBalance = 100
+): Promise<User> {
+  const keypair = await newAccountWithLamports(program.provider.connection);
+
+  const depositAta = await spl.getOrCreateAssociatedTokenAccount(
+    program.provider.connection,
+    config.mintAuthority,
+    config.keys.get(DEPOSIT_MINT),
+    keypair.publicKey
+  );
+
+  await spl.mintTo(
+    program.provider.connection,
+    config.mintAuthority,
+    config.keys.get(DEPOSIT_MINT),
+    depositAta.address,
+    config.mintAuthority.publicKey,
+    userDepositAtaBalance
+  );
+
+  // created by buy
+  const ticketsAta = await spl.getAssociatedTokenAddress(
+    config.keys.get(TICKETS),
+    keypair.publicKey
+  );
+
+  return {
+    keypair: keypair,
+    depositAta: depositAta.address,
+    ticketsAta: ticketsAta,
+  };
+}
+
+async function buy(
+  program: Program<NoLossLottery>,
+  numbers: Array<number>,
+  config: Config,
+  error: number | null,
+  user: User | null = null
+): Promise<[anchor.web3.PublicKey, number]> {
+  const [accounts, ticket, ticketBump, registryNodes] = await buyAccounts(
+    program,
+    config,
+    numbers,
+    user
+  );
+
+  // buy a ticket
+  try {
+    const buyTxSig = await program.rpc.buy(numbers, {
+      accounts: accounts,
+      remainingAccounts: registryNodes,
+      signers: user ? [user.keypair] : [],
+    });
+    console.log("buySigTx:", buyTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+  return [ticket, ticketBump];
+}
+
+// buy a ticket with quick pick numbers derived from a slot hash, the latest
+// one by default, rolls whose combination already exists are skipped
+async function buyQuickPick(
+  program: Program<NoLossLottery>,
+  config: Config,
+  nonce: anchor.BN,
+  error: number | null,
+  user: User | null = null,
+  slotAndHash: [anchor.BN, Array<number>] | null = null
+): Promise<[anchor.web3.PublicKey, Array<number>]> {
+  const vaultMgrAccount = await program.account.vaultManager.fetch(
+    config.keys.get(VAULT_MANAGER)
+  );
+  const [slot, slotHash] = slotAndHash ?? (await latestSlotHash(program));
+  const owner = user
+    ? user.keypair.publicKey
+    : program.provider.wallet.publicKey;
+
+  let numbers: Array<number>;
+  let takenCombinations = [];
+  for (let roll = 0; ; roll++) {
+    numbers = quickPickNumbers(
+      slotHash,
+      owner,
+      nonce,
+      roll,
+      vaultMgrAccount.numberDomain
+    );
+    const combination = await combinationAddress(program, config, numbers);
+    if (!(await program.account.combination.fetchNullable(combination))) {
+      break;
+    }
+    takenCombinations.push({
+      pubkey: combination,
+      isWritable: false,
+      isSigner: false,
+    });
+  }
+
+  const [accounts, ticket, _ticketBump, registryNodes] = await buyAccounts(
+    program,
+    config,
+    numbers,
+    user
+  );
+
+  try {
+    const buyQuickPickTxSig = await program.rpc.buyQuickPick(
+      numbers,
+      slot,
+      nonce,
+      {
+        accounts: {
+          buy: accounts,
+          slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+        },
+        remainingAccounts: takenCombinations.concat(registryNodes),
+        signers: user ? [user.keypair] : [],
+      }
+    );
+    console.log("buyQuickPickTxSig:", buyQuickPickTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+  return [ticket, numbers];
+}
+
+// buy a ticket for each set of numbers with one buy_many instruction
+async function buyMany(
+  program: Program<NoLossLottery>,
+  config: Config,
+  numbersList: Array<Array<number>>,
+  error: number | null,
+  user: User | null = null
+): Promise<Array<[anchor.web3.PublicKey, number]>> {
+  const vaultMgrAccount = await program.account.vaultManager.fetch(
+    config.keys.get(VAULT_MANAGER)
+  );
+
+  // combination, ticket and registry entry of each ticket
+  // tickets of the batch sharing a combination take its next indexes in turn
+  const nextIndexes = new Map<string, anchor.BN>();
+  const tickets: Array<[anchor.web3.PublicKey, number]> = [];
+  const slots = [];
+  let remainingAccounts = [];
+  for (let i = 0; i < numbersList.length; i++) {
+    const combination = await combinationAddress(
+      program,
+      config,
+      numbersList[i]
+    );
+    if (!nextIndexes.has(combination.toBase58())) {
+      const combinationAccount =
+        await program.account.combination.fetchNullable(combination);
+      nextIndexes.set(
+        combination.toBase58(),
+        combinationAccount ? combinationAccount.nextIndex : new anchor.BN(0)
+      );
+    }
+    const index = nextIndexes.get(combination.toBase58());
+    nextIndexes.set(combination.toBase58(), index.addn(1));
+
+    const [ticket, ticketBump] = await ticketAddress(
+      program,
+      config,
+      numbersList[i],
+      index
+    );
+    const slot = vaultMgrAccount.ticketCount.addn(i);
+    const [ticketEntry, _ticketEntryBump] = await ticketEntryAddress(
+      program,
+      config,
+      slot
+    );
+    slots.push(slot);
+    tickets.push([ticket, ticketBump]);
+    for (const pubkey of [combination, ticket, ticketEntry]) {
+      remainingAccounts.push({
+        pubkey: pubkey,
+        isWritable: true,
+        isSigner: false,
+      });
+    }
+  }
+
+  // then the registry tree nodes of the new slots
+  remainingAccounts = remainingAccounts.concat(
+    await registryNodeAccounts(
+      program.programId,
+      config.keys.get(VAULT_MANAGER),
+      slots
+    )
+  );
+
+  try {
+    const buyManyTxSig = await program.rpc.buyMany(numbersList, {
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        tickets: config.keys.get(TICKETS),
+        userTicketsAta: user
+          ? user.ticketsAta
+          : config.keys.get(USER_TICKET_ATA),
+        user: user ? user.keypair.publicKey : program.provider.wallet.publicKey,
+        userDepositAta: user
+          ? user.depositAta
+          : config.keys.get(USER_DEPOSIT_ATA),
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: remainingAccounts,
+      signers: user ? [user.keypair] : [],
+    });
+    console.log("buyManyTxSig:", buyManyTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+  return tickets;
+}
+
+// accounts of a ticket purchase with canonical `numbers`
+async function buyAccounts(
+  program: Program<NoLossLottery>,
+  config: Config,
+  numbers: Array<number>,
+  user: User | null
+): Promise<[any, anchor.web3.PublicKey, number, Array<any>]> {
+  // ticket is added to the holders of its number combination
+  const combination = await combinationAddress(program, config, numbers);
+  const combinationAccount = await program.account.combination.fetchNullable(
+    combination
+  );
+  const index = combinationAccount
+    ? combinationAccount.nextIndex
+    : new anchor.BN(0);
+
+  // create ticket PDA
+  const [ticket, ticketBump] = await ticketAddress(
+    program,
+    config,
+    numbers,
+    index
+  );
+
+  // ticket takes the next registry slot
+  const vaultMgrAccount = await program.account.vaultManager.fetch(
+    config.keys.get(VAULT_MANAGER)
+  );
+  const [ticketEntry, _ticketEntryBump] = await ticketEntryAddress(
+    program,
+    config,
+    vaultMgrAccount.ticketCount
+  );
+
+  const accounts = {
+    depositMint: config.keys.get(DEPOSIT_MINT),
+    depositVault: config.keys.get(DEPOSIT_VAULT),
+    yieldMint: config.keys.get(YIELD_MINT),
+    yieldVault: config.keys.get(YIELD_VAULT),
+    vaultManager: config.keys.get(VAULT_MANAGER),
+    tickets: config.keys.get(TICKETS),
+    combination: combination,
+    ticket: ticket,
+    ticketEntry: ticketEntry,
+    userTicketsAta: user ? user.ticketsAta : config.keys.get(USER_TICKET_ATA),
+    user: user ? user.keypair.publicKey : program.provider.wallet.publicKey,
+    userDepositAta: user
+      ? user.depositAta
+      : config.keys.get(USER_DEPOSIT_ATA),
+    systemProgram: anchor.web3.SystemProgram.programId,
+    associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+    tokenProgram: spl.TOKEN_PROGRAM_ID,
+    rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+  };
+
+  // registry tree nodes of the new slot, passed as remaining accounts
+  const registryNodes = await registryNodeAccounts(
+    program.programId,
+    config.keys.get(VAULT_MANAGER),
+    [vaultMgrAccount.ticketCount]
+  );
+  return [accounts, ticket, ticketBump, registryNodes];
+}
+
+async function redeem(
+  program: Program<NoLossLottery>,
+  config: Config,
+  ticket: anchor.web3.PublicKey,
+  ticketBump: number,
+  error: number | null,
+  user: User | null = null,
+  expectedAmountOut: number | null = null
+) {
+  try {
+    const ticketAccount = await program.account.ticket.fetch(ticket);
+    const combination = await combinationAddress(
+      program,
+      config,
+      ticketAccount.numbers
+    );
+
+    // last registry entry is moved into the slot of the redeemed ticket
+    const [ticketEntry, _ticketEntryBump] = await ticketEntryAddress(
+      program,
+      config,
+      ticketAccount.registryIndex
+    );
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+    const [lastTicketEntry, _lastTicketEntryBump] = await ticketEntryAddress(
+      program,
+      config,
+      vaultMgrAccount.ticketCount.subn(1)
+    );
+    const lastTicketEntryAccount = await program.account.ticketEntry.fetch(
+      lastTicketEntry
+    );
+
+    // the registry tree is updated on the paths of both slots
+    const registryNodes = await registryNodeAccounts(
+      program.programId,
+      config.keys.get(VAULT_MANAGER),
+      [ticketAccount.registryIndex, vaultMgrAccount.ticketCount.subn(1)]
+    );
+
+    // user redeem token
+    const expectedPositionValue = await positionQuote(
+      program,
+      config,
+      expectedAmountOut
+    );
+    const redeemTxSig = await program.rpc.redeem(expectedPositionValue, {
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        swapPool: swapPoolAccounts(config),
+        tickets: config.keys.get(TICKETS),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        ticket: ticket,
+        combination: combination,
+        ticketEntry: ticketEntry,
+        lastTicketEntry: lastTicketEntry,
+        lastTicket: lastTicketEntryAccount.ticket,
+        userTicketsAta: user
+          ? user.ticketsAta
+          : config.keys.get(USER_TICKET_ATA),
+        user: user ? user.keypair.publicKey : program.provider.wallet.publicKey,
+        userDepositAta: user
+          ? user.depositAta
+          : config.keys.get(USER_DEPOSIT_ATA),
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: registryNodes,
+      signers: user ? [user.keypair] : [],
+    });
+    console.log("redeemTxSig:", redeemTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+async function draw(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error: number | null
+) {
+  try {
+    // draw winner
+    const drawTxSig = await program.rpc.draw({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        tickets: config.keys.get(TICKETS),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        user: program.provider.wallet.publicKey,
+        slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+    });
+    console.log("drawTxSig:", drawTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+// oracle commits the nonce of its signature over the next request
+// a fresh nonce seed is kept in the config for the fulfillment
+async function commitOracleNonce(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error: number | null,
+  oracle: anchor.web3.Keypair | null = null
+) {
+  const nonceSeed = crypto.randomBytes(32);
+
+  try {
+    const commitOracleNonceTxSig = await program.rpc.commitOracleNonce(
+      Array.from(oracleNonce(nonceSeed)),
+      {
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          oracle: (oracle ?? config.oracle).publicKey,
+        },
+        signers: [oracle ?? config.oracle],
+      }
+    );
+    console.log("commitOracleNonceTxSig:", commitOracleNonceTxSig);
+    if (error) {
+      assert.fail("commit oracle nonce should fail");
+    }
+    config.oracleNonceSeed = nonceSeed;
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+// sign the pending randomness request with the oracle key and the committed
+// nonce and relay it through the mock oracle, then commit the next nonce
+// returns the winning numbers drawn from it
+// `oracle`, `message` and `nonceSeed` replace the configured oracle key, the
+// request and the committed nonce
+async function fulfill(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error: number | null = null,
+  oracle: anchor.web3.Keypair | null = null,
+  message: Array<number> | null = null,
+  nonceSeed: Uint8Array | null = null
+): Promise<Array<number>> {
+  const oracleProgram = anchor.workspace.MockOracle as Program<MockOracle>;
+  const vaultMgr = config.keys.get(VAULT_MANAGER);
+
+  const request = (await program.account.vaultManager.fetch(vaultMgr))
+    .randomnessRequest;
+  const signatureIx = oracleSignatureInstruction(
+    oracle ?? config.oracle,
+    nonceSeed ?? config.oracleNonceSeed,
+    message ?? request
+  );
+
+  try {
+    const fulfillTxSig = await oracleProgram.rpc.fulfill({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: vaultMgr,
+        instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        lotteryProgram: program.programId,
+      },
+      instructions: [signatureIx],
+    });
+    console.log("fulfillTxSig:", fulfillTxSig);
+    if (error) {
+      assert.fail("fulfill should fail");
+    }
+    await commitOracleNonce(program, config, null);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+
+  return (await program.account.vaultManager.fetch(vaultMgr)).winningNumbers;
+}
+
+// Ed25519 program instruction verifying the oracle signature over a message
+function oracleSignatureInstruction(
+  oracle: anchor.web3.Keypair,
+  nonceSeed: Uint8Array,
+  message: Array<number>
+): anchor.web3.TransactionInstruction {
+  return anchor.web3.Ed25519Program.createInstructionWithPublicKey({
+    publicKey: oracle.publicKey.toBytes(),
+    message: Buffer.from(message),
+    signature: nonceSignature(oracle, nonceSeed, Buffer.from(message)),
+  });
+}
+
+// randomness fulfill derives from a request with the committed nonce, the
+// hash of the oracle signature
+function oracleRandomness(
+  config: Config,
+  request: Array<number>
+): Array<number> {
+  const signature = nonceSignature(
+    config.oracle,
+    config.oracleNonceSeed,
+    Buffer.from(request)
+  );
+  return sha256(Array.from(signature));
+}
+
+// the bond is paid from the committer's deposit ata
+async function commit(
+  program: Program<NoLossLottery>,
+  config: Config,
+  commitment: Array<number>,
+  error: number | null,
+  user: User | null = null
+) {
+  try {
+    const commitTxSig = await program.rpc.commit(commitment, {
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        treasury: config.keys.get(TREASURY),
+        user: user ? user.keypair.publicKey : program.provider.wallet.publicKey,
+        userDepositAta: user
+          ? user.depositAta
+          : config.keys.get(USER_DEPOSIT_ATA),
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      },
+      signers: user ? [user.keypair] : [],
+    });
+    console.log("commitTxSig:", commitTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+// the bond is returned to the committer's deposit ata
+async function reveal(
+  program: Program<NoLossLottery>,
+  config: Config,
+  secret: Array<number>,
+  error: number | null,
+  user: User | null = null
+) {
+  try {
+    const revealTxSig = await program.rpc.reveal(secret, {
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        treasury: config.keys.get(TREASURY),
+        user: user ? user.keypair.publicKey : program.provider.wallet.publicKey,
+        userDepositAta: user
+          ? user.depositAta
+          : config.keys.get(USER_DEPOSIT_ATA),
+        slotHashes: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      },
+      signers: user ? [user.keypair] : [],
+    });
+    console.log("revealTxSig:", revealTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+async function expireDraw(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error: number | null,
+  user: anchor.web3.Account | null = null
+) {
+  try {
+    const expireDrawTxSig = await program.rpc.expireDraw({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        user: user ? user.publicKey : program.provider.wallet.publicKey,
+      },
+      signers: user ? [user] : [],
+    });
+    console.log("expireDrawTxSig:", expireDrawTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+// sha256 hash used for commitments
+function sha256(data: Array<number>): Array<number> {
+  return Array.from(crypto.createHash("sha256").update(Buffer.from(data)).digest());
+}
+
+async function dispense(
+  program: Program<NoLossLottery>,
+  config: Config,
+  numbers: Array<number>,
+  error = null,
+  crank: User | null = null,
+  expectedAmountOut: number | null = null
+) {
+  try {
+    // fetch current draw id
+    const vaultMgrAccount = await program.account.vaultManager.fetch(
+      config.keys.get(VAULT_MANAGER)
+    );
+
+    // draw result PDA of the current draw
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      vaultMgrAccount.drawId
+    );
+
+    // ticket lottery mode and consolation draws need the registry entry of
+    // the drawn ticket, then the registry tree nodes read to find it
+    const consolation =
+      vaultMgrAccount.maxRollovers.gtn(0) &&
+      vaultMgrAccount.rolloverCount.gte(vaultMgrAccount.maxRollovers);
+    let remainingAccounts = [];
+    if (
+      (vaultMgrAccount.lotteryMode.ticket || consolation) &&
+      vaultMgrAccount.ticketCount.gtn(0)
+    ) {
+      const [slot, nodes] = await drawRegistrySlot(
+        program,
+        config.keys.get(VAULT_MANAGER)
+      );
+      const [winningEntry, _winningEntryBump] = await ticketEntryAddress(
+        program,
+        config,
+        slot
+      );
+      for (const pubkey of [winningEntry].concat(nodes)) {
+        remainingAccounts.push({
+          pubkey: pubkey,
+          isWritable: false,
+          isSigner: false,
+        });
+      }
+    }
+
+    // numbers draws roll over unless a ticket holds the winning combination
+    if (vaultMgrAccount.lotteryMode.numbers && !consolation) {
+      remainingAccounts.push({
+        pubkey: await combinationAddress(
+          program,
+          config,
+          vaultMgrAccount.winningNumbers
+        ),
+        isWritable: false,
+        isSigner: false,
+      });
+    }
+
+    // record prizes of the draw
+    const dispenseTxSig = await program.rpc.dispense(
+      numbers,
+      await positionQuote(program, config, expectedAmountOut),
+      {
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          tickets: config.keys.get(TICKETS),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          drawRecord: drawRecord,
+          treasury: config.keys.get(TREASURY),
+          swapPool: swapPoolAccounts(config),
+          user: crank
+            ? crank.keypair.publicKey
+            : program.provider.wallet.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+        remainingAccounts: remainingAccounts,
+        signers: crank ? [crank.keypair] : [],
+      }
+    );
+    console.log("dispenseTxSig:", dispenseTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+async function registerMatch(
+  program: Program<NoLossLottery>,
+  config: Config,
+  drawId: number,
+  ticket: anchor.web3.PublicKey,
+  error = null,
+  user: User | null = null
+) {
+  try {
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(drawId)
+    );
+    const [ticketClaim, _ticketClaimBump] = await ticketClaimAddress(
+      program,
+      drawRecord,
+      ticket
+    );
+
+    const registerMatchTxSig = await program.rpc.registerMatch({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        drawRecord: drawRecord,
+        ticket: ticket,
+        ticketClaim: ticketClaim,
+        user: user ? user.keypair.publicKey : program.provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: user ? [user.keypair] : [],
+    });
+    console.log("registerMatchTxSig:", registerMatchTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+// time the ticket was held during the draw period, its share of the tier prize
+async function ticketClaimWeight(
+  program: Program<NoLossLottery>,
+  config: Config,
+  drawId: number,
+  ticket: anchor.web3.PublicKey
+): Promise<number> {
+  const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+    program,
+    config,
+    new anchor.BN(drawId)
+  );
+  const [ticketClaim, _ticketClaimBump] = await ticketClaimAddress(
+    program,
+    drawRecord,
+    ticket
+  );
+  const ticketClaimAccount = await program.account.ticketClaim.fetch(
+    ticketClaim
+  );
+  return ticketClaimAccount.weight.toNumber();
+}
+
+async function claimPrize(
+  program: Program<NoLossLottery>,
+  config: Config,
+  drawId: number,
+  ticket: anchor.web3.PublicKey,
+  error = null,
+  winner: User | null = null
+) {
+  try {
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(drawId)
+    );
+    const [ticketClaim, _ticketClaimBump] = await ticketClaimAddress(
+      program,
+      drawRecord,
+      ticket
+    );
+
+    const claimPrizeTxSig = await program.rpc.claimPrize({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        drawRecord: drawRecord,
+        ticketClaim: ticketClaim,
+        winnerDepositAta: winner
+          ? winner.depositAta
+          : config.keys.get(USER_DEPOSIT_ATA),
+        winner: winner
+          ? winner.keypair.publicKey
+          : program.provider.wallet.publicKey,
+        tokenProgram: spl.TOKEN_PROGRAM_ID,
+      },
+      signers: winner ? [winner.keypair] : [],
+    });
+    console.log("claimPrizeTxSig:", claimPrizeTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+async function expireDrawRecord(
+  program: Program<NoLossLottery>,
+  config: Config,
+  drawId: number,
+  error = null
+) {
+  try {
+    const [drawRecord, _drawRecordBump] = await drawRecordAddress(
+      program,
+      config,
+      new anchor.BN(drawId)
+    );
+
+    const expireDrawRecordTxSig = await program.rpc.expireDrawRecord({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
+        yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        drawRecord: drawRecord,
+      },
+    });
+    console.log("expireDrawRecordTxSig:", expireDrawRecordTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
 
-  const config: Config = {
-    keys: keys,
-    mintAuthority: mintAuthority,
-  };
+// config fields to update, missing fields are left unchanged
+interface ConfigUpdate {
+  drawDuration?: anchor.BN;
+  ticketPrice?: anchor.BN;
+  depositTokenReserve?: anchor.BN;
+  revealDuration?: anchor.BN;
+  registrationDuration?: anchor.BN;
+  claimDuration?: anchor.BN;
+  prizeTiers?: Array<number>;
+  feeBps?: number;
+  slippageBps?: number;
+  minHoldingPeriod?: anchor.BN;
+  maxRollovers?: anchor.BN;
+  numberDomain?: NumberDomain;
+  fulfillDuration?: anchor.BN;
+  commitBond?: anchor.BN;
+}
 
-  return config;
+async function updateConfig(
+  program: Program<NoLossLottery>,
+  config: Config,
+  update: ConfigUpdate,
+  error = null,
+  authority: User | null = null
+) {
+  try {
+    const updateConfigTxSig = await program.rpc.updateConfig(
+      update.drawDuration ?? null,
+      update.ticketPrice ?? null,
+      update.depositTokenReserve ?? null,
+      update.revealDuration ?? null,
+      update.registrationDuration ?? null,
+      update.claimDuration ?? null,
+      update.prizeTiers ?? null,
+      update.feeBps ?? null,
+      update.slippageBps ?? null,
+      update.minHoldingPeriod ?? null,
+      update.maxRollovers ?? null,
+      update.numberDomain ?? null,
+      update.fulfillDuration ?? null,
+      update.commitBond ?? null,
+      {
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          tickets: config.keys.get(TICKETS),
+          authority: authority
+            ? authority.keypair.publicKey
+            : program.provider.wallet.publicKey,
+        },
+        signers: authority ? [authority.keypair] : [],
+      }
+    );
+    console.log("updateConfigTxSig:", updateConfigTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
 }
 
-async function buy(
+async function transferAuthority(
   program: Program<NoLossLottery>,
-  numbers: Array<number>,
   config: Config,
-  error: number | null
-): Promise<[anchor.web3.PublicKey, number]> {
-  // create ticket PDA
-  const [ticket, ticketBump] = await anchor.web3.PublicKey.findProgramAddress(
-    [Uint8Array.from(numbers), config.keys.get(VAULT_MANAGER).toBuffer()],
-    program.programId
-  );
+  newAuthority: anchor.web3.PublicKey,
+  error = null,
+  authority: User | null = null
+) {
+  try {
+    const transferAuthorityTxSig = await program.rpc.transferAuthority(
+      newAuthority,
+      {
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          authority: authority
+            ? authority.keypair.publicKey
+            : program.provider.wallet.publicKey,
+        },
+        signers: authority ? [authority.keypair] : [],
+      }
+    );
+    console.log("transferAuthorityTxSig:", transferAuthorityTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
 
-  // buy a ticket
+async function acceptAuthority(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error = null,
+  pendingAuthority: User | null = null
+) {
   try {
-    const buyTxSig = await program.rpc.buy(numbers, {
+    const acceptAuthorityTxSig = await program.rpc.acceptAuthority({
       accounts: {
         depositMint: config.keys.get(DEPOSIT_MINT),
-        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldVault: config.keys.get(YIELD_VAULT),
         vaultManager: config.keys.get(VAULT_MANAGER),
-        tickets: config.keys.get(TICKETS),
-        ticket: ticket,
-        userTicketsAta: config.keys.get(USER_TICKET_ATA),
-        user: program.provider.wallet.publicKey,
-        userDepositAta: config.keys.get(USER_DEPOSIT_ATA),
-        systemProgram: anchor.web3.SystemProgram.programId,
-        associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        pendingAuthority: pendingAuthority
+          ? pendingAuthority.keypair.publicKey
+          : program.provider.wallet.publicKey,
       },
+      signers: pendingAuthority ? [pendingAuthority.keypair] : [],
     });
-    console.log("buySigTx:", buyTxSig);
+    console.log("acceptAuthorityTxSig:", acceptAuthorityTxSig);
   } catch (e) {
     if (error) {
       assert.equal(e.code, error);
@@ -822,43 +6009,50 @@ async function buy(
       throw e;
     }
   }
-  return [ticket, ticketBump];
 }
 
-async function redeem(
+// accounts of the pool stored by set_yield_source, nested as `swap_pool`
+function swapPoolAccounts(config: Config) {
+  return {
+    swapYieldVault: config.keys.get(SWAP_YIELD_VAULT),
+    swapDepositVault: config.keys.get(SWAP_DEPOSIT_VAULT),
+    poolMint: config.keys.get(POOL_MINT),
+    amm: config.keys.get(TOKEN_SWAP_ACCOUNT),
+    ammAuthority: config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY),
+    poolFee: config.keys.get(POOL_FEE),
+    tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
+  };
+}
+
+async function setYieldSource(
   program: Program<NoLossLottery>,
   config: Config,
-  ticket: anchor.web3.PublicKey,
-  ticketBump: number,
-  error: number | null
+  error = null,
+  authority: User | null = null,
+  tokenSwapProgram: anchor.web3.PublicKey = tokenSwap.TOKEN_SWAP_PROGRAM_ID
 ) {
   try {
-    // user redeem token
-    const redeemTxSig = await program.rpc.redeem({
+    const setYieldSourceTxSig = await program.rpc.setYieldSource({
       accounts: {
         depositMint: config.keys.get(DEPOSIT_MINT),
-        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldVault: config.keys.get(YIELD_VAULT),
-        swapYieldVault: config.keys.get(SWAP_YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
         swapDepositVault: config.keys.get(SWAP_DEPOSIT_VAULT),
+        swapYieldVault: config.keys.get(SWAP_YIELD_VAULT),
         poolMint: config.keys.get(POOL_MINT),
+        poolFee: config.keys.get(POOL_FEE),
         amm: config.keys.get(TOKEN_SWAP_ACCOUNT),
         ammAuthority: config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY),
-        poolFee: config.keys.get(POOL_FEE),
-        tickets: config.keys.get(TICKETS),
-        vaultManager: config.keys.get(VAULT_MANAGER),
-        ticket: ticket,
-        userTicketsAta: config.keys.get(USER_TICKET_ATA),
-        user: program.provider.wallet.publicKey,
-        userDepositAta: config.keys.get(USER_DEPOSIT_ATA),
-        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
-        systemProgram: anchor.web3.SystemProgram.programId,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenSwapProgram: tokenSwapProgram,
+        authority: authority
+          ? authority.keypair.publicKey
+          : program.provider.wallet.publicKey,
       },
+      signers: authority ? [authority.keypair] : [],
     });
-    console.log("redeemTxSig:", redeemTxSig);
+    console.log("setYieldSourceTxSig:", setYieldSourceTxSig);
   } catch (e) {
     if (error) {
       assert.equal(e.code, error);
@@ -868,28 +6062,66 @@ async function redeem(
   }
 }
 
-async function draw(
+async function withdrawTreasury(
   program: Program<NoLossLottery>,
   config: Config,
-  error: number | null
+  amount: number,
+  destination: anchor.web3.PublicKey,
+  error = null,
+  authority: User | null = null
 ) {
   try {
-    // draw winner
-    const drawTxSig = await program.rpc.draw({
+    const withdrawTreasuryTxSig = await program.rpc.withdrawTreasury(
+      new anchor.BN(amount),
+      {
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          treasury: config.keys.get(TREASURY),
+          destination: destination,
+          authority: authority
+            ? authority.keypair.publicKey
+            : program.provider.wallet.publicKey,
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+        signers: authority ? [authority.keypair] : [],
+      }
+    );
+    console.log("withdrawTreasuryTxSig:", withdrawTreasuryTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+async function setGuardian(
+  program: Program<NoLossLottery>,
+  config: Config,
+  guardian: anchor.web3.PublicKey,
+  error = null,
+  authority: User | null = null
+) {
+  try {
+    const setGuardianTxSig = await program.rpc.setGuardian(guardian, {
       accounts: {
         depositMint: config.keys.get(DEPOSIT_MINT),
-        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldVault: config.keys.get(YIELD_VAULT),
-        tickets: config.keys.get(TICKETS),
         vaultManager: config.keys.get(VAULT_MANAGER),
-        user: program.provider.wallet.publicKey,
-        systemProgram: anchor.web3.SystemProgram.programId,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
-        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        authority: authority
+          ? authority.keypair.publicKey
+          : program.provider.wallet.publicKey,
       },
+      signers: authority ? [authority.keypair] : [],
     });
-    console.log("drawTxSig:", drawTxSig);
+    console.log("setGuardianTxSig:", setGuardianTxSig);
   } catch (e) {
     if (error) {
       assert.equal(e.code, error);
@@ -899,48 +6131,91 @@ async function draw(
   }
 }
 
-async function dispense(
+async function setCommitAuthority(
   program: Program<NoLossLottery>,
   config: Config,
-  numbers: Array<number>,
-  error = null
+  commitAuthority: anchor.web3.PublicKey,
+  error = null,
+  authority: User | null = null
 ) {
   try {
-    // fetch winning numbers
-    const vaultMgrAccount = await program.account.vaultManager.fetch(
-      config.keys.get(VAULT_MANAGER)
-    );
-
-    // create winning ticket PDA
-    const [ticket, ticketBump] = await anchor.web3.PublicKey.findProgramAddress(
-      [Uint8Array.from(numbers), config.keys.get(VAULT_MANAGER).toBuffer()],
-      program.programId
+    const setCommitAuthorityTxSig = await program.rpc.setCommitAuthority(
+      commitAuthority,
+      {
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          authority: authority
+            ? authority.keypair.publicKey
+            : program.provider.wallet.publicKey,
+        },
+        signers: authority ? [authority.keypair] : [],
+      }
     );
+    console.log("setCommitAuthorityTxSig:", setCommitAuthorityTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
 
-    // dispense prize to winner
-    const dispenseTxSig = await program.rpc.dispense(numbers, {
+async function pause(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error = null,
+  signer: User | null = null
+) {
+  try {
+    const pauseTxSig = await program.rpc.pause({
       accounts: {
         depositMint: config.keys.get(DEPOSIT_MINT),
+        yieldMint: config.keys.get(YIELD_MINT),
         depositVault: config.keys.get(DEPOSIT_VAULT),
+        yieldVault: config.keys.get(YIELD_VAULT),
+        vaultManager: config.keys.get(VAULT_MANAGER),
+        signer: signer
+          ? signer.keypair.publicKey
+          : program.provider.wallet.publicKey,
+      },
+      signers: signer ? [signer.keypair] : [],
+    });
+    console.log("pauseTxSig:", pauseTxSig);
+  } catch (e) {
+    if (error) {
+      assert.equal(e.code, error);
+    } else {
+      throw e;
+    }
+  }
+}
+
+async function unpause(
+  program: Program<NoLossLottery>,
+  config: Config,
+  error = null,
+  authority: User | null = null
+) {
+  try {
+    const unpauseTxSig = await program.rpc.unpause({
+      accounts: {
+        depositMint: config.keys.get(DEPOSIT_MINT),
         yieldMint: config.keys.get(YIELD_MINT),
+        depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldVault: config.keys.get(YIELD_VAULT),
-        tickets: config.keys.get(TICKETS),
         vaultManager: config.keys.get(VAULT_MANAGER),
-        ticket: ticket,
-        swapYieldVault: config.keys.get(SWAP_YIELD_VAULT),
-        swapDepositVault: config.keys.get(SWAP_DEPOSIT_VAULT),
-        poolMint: config.keys.get(POOL_MINT),
-        amm: config.keys.get(TOKEN_SWAP_ACCOUNT),
-        ammAuthority: config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY),
-        poolFee: config.keys.get(POOL_FEE),
-        user: program.provider.wallet.publicKey,
-        userDepositAta: config.keys.get(USER_DEPOSIT_ATA),
-        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
-        systemProgram: anchor.web3.SystemProgram.programId,
-        tokenProgram: spl.TOKEN_PROGRAM_ID,
+        authority: authority
+          ? authority.keypair.publicKey
+          : program.provider.wallet.publicKey,
       },
+      signers: authority ? [authority.keypair] : [],
     });
-    console.log("dispenseTxSig:", dispenseTxSig);
+    console.log("unpauseTxSig:", unpauseTxSig);
   } catch (e) {
     if (error) {
       assert.equal(e.code, error);
@@ -953,24 +6228,41 @@ async function dispense(
 async function stake(
   program: Program<NoLossLottery>,
   config: Config,
-  error = null
+  error = null,
+  expectedAmountOut: number | null = null
 ) {
   try {
-    const stakeTxSig = await program.rpc.stake({
+    // caller's quote of staking everything above the reserve and unclaimed
+    // prizes, `expectedAmountOut` if given
+    let quote: anchor.BN;
+    if (expectedAmountOut === null) {
+      const vaultMgrAccount = await program.account.vaultManager.fetch(
+        config.keys.get(VAULT_MANAGER)
+      );
+      const reserved = vaultMgrAccount.depositTokenReserve.add(
+        vaultMgrAccount.unclaimedPrizes
+      );
+      const depositAmount = await tokenAmount(
+        program,
+        config.keys.get(DEPOSIT_VAULT)
+      );
+      const amountIn = depositAmount.gt(reserved)
+        ? depositAmount.sub(reserved)
+        : new anchor.BN(0);
+      quote = await poolQuote(program, config, amountIn, true);
+    } else {
+      quote = new anchor.BN(expectedAmountOut);
+    }
+
+    const stakeTxSig = await program.rpc.stake(quote, {
       accounts: {
         vaultManager: config.keys.get(VAULT_MANAGER),
         depositMint: config.keys.get(DEPOSIT_MINT),
         depositVault: config.keys.get(DEPOSIT_VAULT),
         yieldMint: config.keys.get(YIELD_MINT),
         yieldVault: config.keys.get(YIELD_VAULT),
-        swapYieldVault: config.keys.get(SWAP_YIELD_VAULT),
-        swapDepositVault: config.keys.get(SWAP_DEPOSIT_VAULT),
-        poolMint: config.keys.get(POOL_MINT),
-        amm: config.keys.get(TOKEN_SWAP_ACCOUNT),
-        ammAuthority: config.keys.get(TOKEN_SWAP_ACCOUNT_AUTHORITY),
-        poolFee: config.keys.get(POOL_FEE),
+        swapPool: swapPoolAccounts(config),
         user: program.provider.wallet.publicKey,
-        tokenSwapProgram: tokenSwap.TOKEN_SWAP_PROGRAM_ID,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
@@ -987,11 +6279,7 @@ async function stake(
 }
 
 async function tokenSwapInit(program: Program<NoLossLottery>, config: Config) {
-  // Pool fees
-  const TRADING_FEE_NUMERATOR = 25;
-  const TRADING_FEE_DENOMINATOR = 10000;
-  const OWNER_TRADING_FEE_NUMERATOR = 5;
-  const OWNER_TRADING_FEE_DENOMINATOR = 10000;
+  // Pool fees, trading fees are shared with poolQuote
   const OWNER_WITHDRAW_FEE_NUMERATOR = 0;
   const OWNER_WITHDRAW_FEE_DENOMINATOR = 0;
   const HOST_FEE_NUMERATOR = 20;
@@ -1102,6 +6390,9 @@ async function tokenSwapInit(program: Program<NoLossLottery>, config: Config) {
   config.keys.set(TOKEN_SWAP_ACCOUNT, tokenSwapAccount.publicKey);
   config.keys.set(TOKEN_SWAP_ACCOUNT_AUTHORITY, tokenSwapAccountAuthority);
   config.keys.set(POOL_FEE, feeAccount.address);
+
+  // only this pool is accepted by stake, redeem and dispense
+  await setYieldSource(program, config, null);
 }
 
 async function assertBalance(
@@ -1115,6 +6406,46 @@ async function assertBalance(
   assert.equal(balance, expectedBalance);
 }
 
+// vault tokens are not delegated to anyone
+async function assertNoDelegate(
+  program: Program<NoLossLottery>,
+  account: anchor.web3.PublicKey
+) {
+  const tokenAccount = await spl.getAccount(
+    program.provider.connection,
+    account
+  );
+  assert.equal(tokenAccount.delegate, null);
+  assert.equal(tokenAccount.delegatedAmount, BigInt(0));
+}
+
+// value of the pool against the principal owed to ticket holders
+async function poolHealth(
+  program: Program<NoLossLottery>,
+  config: Config
+): Promise<any> {
+  const events = await captureEvents(
+    program,
+    ["PoolHealthReported"],
+    async () => {
+      const poolHealthTxSig = await program.rpc.poolHealth({
+        accounts: {
+          depositMint: config.keys.get(DEPOSIT_MINT),
+          yieldMint: config.keys.get(YIELD_MINT),
+          depositVault: config.keys.get(DEPOSIT_VAULT),
+          yieldVault: config.keys.get(YIELD_VAULT),
+          vaultManager: config.keys.get(VAULT_MANAGER),
+          swapPool: swapPoolAccounts(config),
+          tokenProgram: spl.TOKEN_PROGRAM_ID,
+        },
+      });
+      console.log("poolHealthTxSig:", poolHealthTxSig);
+    }
+  );
+  assert.equal(events.length, 1);
+  return events[0].data;
+}
+
 function assertPublicKey(
   f: Function,
   key1: anchor.web3.PublicKey,
@@ -1123,17 +6454,195 @@ function assertPublicKey(
   return f(key1.toString(), key2.toString());
 }
 
+// combination PDA shared by every ticket with the same numbers
+async function combinationAddress(
+  program: Program<NoLossLottery>,
+  config: Config,
+  numbers: Array<number>
+): Promise<anchor.web3.PublicKey> {
+  const [combination, _combinationBump] =
+    await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Uint8Array.from(canonicalNumbers(numbers)),
+        config.keys.get(VAULT_MANAGER).toBuffer(),
+      ],
+      program.programId
+    );
+  return combination;
+}
+
+// count ticket numbers found in the winning numbers, as count_matches
+function countMatches(
+  numbers: Array<number>,
+  winningNumbers: Array<number>
+): number {
+  const remaining = [...winningNumbers];
+  let matches = 0;
+  for (const number of numbers) {
+    const i = number == 0 ? -1 : remaining.indexOf(number);
+    if (i >= 0) {
+      remaining.splice(i, 1);
+      matches++;
+    }
+  }
+  return matches;
+}
+
+// prize tier of a match count, as match_tier
+function matchTier(matches: number, pickCount: number): number | null {
+  const missed = pickCount - matches;
+  if (matches == 0 || missed < 0 || missed >= 4) {
+    return null;
+  }
+  return 3 - missed;
+}
+
+// picks sorted ascending with the unused 0 slots last
+function canonicalNumbers(numbers: Array<number>): Array<number> {
+  const picks = numbers.filter((n) => n != 0).sort((a, b) => a - b);
+  return picks.concat(numbers.filter((n) => n == 0));
+}
+
+// numbers of the domain derived from randomness, as derive_winning_numbers
+function deriveNumbers(
+  randomness: Array<number>,
+  domain: NumberDomain
+): Array<number> {
+  const numbers = new Array(6).fill(0);
+  for (let i = 0; i < domain.pickCount; i++) {
+    const bytes = Buffer.from(randomness.slice(i * 4, i * 4 + 4));
+    const value = bytes.readUInt32LE(0);
+    if (domain.unique) {
+      // pick among the numbers not drawn yet, then skip the drawn ones below it
+      let number = (value % (domain.maxNumber - i)) + 1;
+      const drawn = numbers.slice(0, i).sort((a, b) => a - b);
+      for (const drawnNumber of drawn) {
+        if (drawnNumber <= number) {
+          number++;
+        }
+      }
+      numbers[i] = number;
+    } else {
+      numbers[i] = (value % domain.maxNumber) + 1;
+    }
+  }
+  return numbers;
+}
+
+// quick pick numbers of a roll, as quick_pick_numbers
+function quickPickNumbers(
+  slotHash: Array<number>,
+  user: anchor.web3.PublicKey,
+  nonce: anchor.BN,
+  roll: number,
+  domain: NumberDomain
+): Array<number> {
+  const randomness = sha256([
+    ...slotHash,
+    ...user.toBytes(),
+    ...nonce.toArrayLike(Buffer, "le", 8),
+    roll,
+  ]);
+  return canonicalNumbers(deriveNumbers(randomness, domain));
+}
+
+// newest entry of the SlotHashes sysvar
+async function latestSlotHash(
+  program: Program<NoLossLottery>
+): Promise<[anchor.BN, Array<number>]> {
+  const slotHashes = await program.provider.connection.getAccountInfo(
+    anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY
+  );
+  const slot = new anchor.BN(slotHashes.data.slice(8, 16), "le");
+  return [slot, Array.from(slotHashes.data.slice(16, 48))];
+}
+
+// PDA of the ticket at `index` within its number combination
+async function ticketAddress(
+  program: Program<NoLossLottery>,
+  config: Config,
+  numbers: Array<number>,
+  index: anchor.BN
+): Promise<[anchor.web3.PublicKey, number]> {
+  return anchor.web3.PublicKey.findProgramAddress(
+    [
+      Uint8Array.from(canonicalNumbers(numbers)),
+      config.keys.get(VAULT_MANAGER).toBuffer(),
+      index.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+}
+
+// PDA of the history of draw `drawId`
+async function drawRecordAddress(
+  program: Program<NoLossLottery>,
+  config: Config,
+  drawId: anchor.BN
+): Promise<[anchor.web3.PublicKey, number]> {
+  return anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("draw_record"),
+      config.keys.get(VAULT_MANAGER).toBuffer(),
+      drawId.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+}
+
+// PDA registering `ticket` for a prize tier of a draw
+async function ticketEntryAddress(
+  program: Program<NoLossLottery>,
+  config: Config,
+  index: anchor.BN
+): Promise<[anchor.web3.PublicKey, number]> {
+  return anchor.web3.PublicKey.findProgramAddress(
+    [
+      Buffer.from("ticket_entry"),
+      config.keys.get(VAULT_MANAGER).toBuffer(),
+      index.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+}
+
+// registry tree node at level holding the first slots of the registry
+async function registryNode(
+  program: Program<NoLossLottery>,
+  config: Config,
+  level: number
+) {
+  return program.account.registryNode.fetch(
+    await registryNodeAddress(
+      program.programId,
+      config.keys.get(VAULT_MANAGER),
+      level,
+      new anchor.BN(0)
+    )
+  );
+}
+
+async function ticketClaimAddress(
+  program: Program<NoLossLottery>,
+  drawRecord: anchor.web3.PublicKey,
+  ticket: anchor.web3.PublicKey
+): Promise<[anchor.web3.PublicKey, number]> {
+  return anchor.web3.PublicKey.findProgramAddress(
+    [Buffer.from("ticket_claim"), drawRecord.toBuffer(), ticket.toBuffer()],
+    program.programId
+  );
+}
+
 async function buyNTickets(
   program: Program<NoLossLottery>,
   config: Config,
   count: Number
 ) {
   // buy a bunch of tickets
-  let buyPromises = [];
+  // one at a time, each ticket takes the next registry slot
   for (let i = 0; i < count; i++) {
-    buyPromises.push(buy(program, [1 + i, 2, 3, 4, 5, 6], config, null));
+    await buy(program, [1 + i, 2, 3, 4, 5, 6], config, null);
   }
-  await Promise.all(buyPromises);
 
   console.log("%d tickets purchased", count);
 }